@@ -1,25 +1,52 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use graphics_vid::*;
+use graphics_vid::composite::BlendMode;
+use graphics_vid::glyph_cache::{GlyphCache, TextStyle};
+
+// Every printable ASCII character at the seven sizes the demo shows them at
+const ASCII: &str = " !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+const TEXT_SIZES: [f32; 7] = [6.0, 8.0, 10.0, 12.0, 14.0, 16.0, 18.0];
 
 pub fn criterion_benchmark(c: &mut Criterion) {
     let width = 1920;
     let height = 1080;
-    let mut buffer = vec![(0.0,0.0,0.0,0.0); width*height];
+    let mut canvas = Canvas::new(width, height);
     let mut ibuffer = vec![0; width*height];
     c.bench_function("simple line", |b| b.iter(|| {
         let p0 = (black_box(0), black_box(0));
         let p1 = (black_box(1919), black_box(1079));
-        wu_line(
+        canvas.wu_line(
             black_box((1.0, 1.0, 1.0, 1.0)),
+            BlendMode::SourceOver,
             p0,
             p1,
-            width,
-            &mut buffer,
         );
     }));
 
     c.bench_function("gamma_correction", |b| b.iter(|| {
-        gamma_correct_buffer(&buffer, &mut ibuffer);
+        canvas.gamma_correct(&mut ibuffer);
+    }));
+
+    c.bench_function("ascii text", |b| b.iter(|| {
+        for (i, &size) in TEXT_SIZES.iter().enumerate() {
+            canvas.draw_text((1.0, 1.0, 1.0), BlendMode::SourceOver, (60, 90 + i as i32 * 20), size, TextSpacing::Monospace, black_box(ASCII));
+        }
+    }));
+
+    // The cache is filled before timing starts, like it would be after the
+    // first frame of an animation
+    let mut glyphs = GlyphCache::new();
+    c.bench_function("ascii text cached", |b| b.iter(|| {
+        for (i, &size) in TEXT_SIZES.iter().enumerate() {
+            canvas.draw_text_cached(
+                &mut glyphs,
+                (1.0, 1.0, 1.0),
+                BlendMode::SourceOver,
+                (60, 90 + i as i32 * 20),
+                TextStyle::new(size),
+                black_box(ASCII),
+            );
+        }
     }));
 }
 
@@ -0,0 +1,281 @@
+use std::time::Instant;
+
+use crate::{clamp, interpf};
+
+// Scenes are drawn as a function of time in seconds, so that how fast
+// things move doesn't depend on how often frames are produced. A Clock
+// supplies that time: the live preview follows the wall clock, while
+// offline rendering steps by exactly one frame per tick so every export of
+// a scene is identical.
+pub enum Clock {
+    Fixed {
+        frame: u64,
+        frame_rate: f64,
+    },
+    RealTime {
+        start: Instant,
+    },
+}
+
+impl Clock {
+    pub fn fixed(frame_rate: f64) -> Clock {
+        Clock::Fixed {
+            frame: 0,
+            frame_rate,
+        }
+    }
+
+    pub fn real_time() -> Clock {
+        Clock::RealTime {
+            start: Instant::now(),
+        }
+    }
+
+    // Seconds since the clock started
+    pub fn time(&self) -> f32 {
+        match self {
+            Clock::Fixed { frame, frame_rate } => (*frame as f64 / frame_rate) as f32,
+            Clock::RealTime { start } => start.elapsed().as_secs_f32(),
+        }
+    }
+
+    // Advance to the next frame. Real time clocks advance on their own, so
+    // this does nothing for them.
+    pub fn tick(&mut self) {
+        if let Clock::Fixed { frame, .. } = self {
+            *frame += 1;
+        }
+    }
+}
+
+// Anything that can be blended between two keyframes
+pub trait Animatable: Copy {
+    fn lerp(a: Self, b: Self, t: f32) -> Self;
+}
+
+impl Animatable for f32 {
+    fn lerp(a: f32, b: f32, t: f32) -> f32 {
+        interpf(t, a, b)
+    }
+}
+
+impl Animatable for (f32, f32) {
+    fn lerp(a: (f32, f32), b: (f32, f32), t: f32) -> (f32, f32) {
+        (interpf(t, a.0, b.0), interpf(t, a.1, b.1))
+    }
+}
+
+impl Animatable for (f32, f32, f32) {
+    fn lerp(a: (f32, f32, f32), b: (f32, f32, f32), t: f32) -> (f32, f32, f32) {
+        (interpf(t, a.0, b.0), interpf(t, a.1, b.1), interpf(t, a.2, b.2))
+    }
+}
+
+impl Animatable for (f32, f32, f32, f32) {
+    fn lerp(a: (f32, f32, f32, f32), b: (f32, f32, f32, f32), t: f32) -> (f32, f32, f32, f32) {
+        (
+            interpf(t, a.0, b.0),
+            interpf(t, a.1, b.1),
+            interpf(t, a.2, b.2),
+            interpf(t, a.3, b.3),
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Easing {
+    // Hold the value until the next keyframe
+    Step,
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    // CSS-style cubic Bézier timing function with control points
+    // (x1, y1) and (x2, y2). The end points are fixed at (0,0) and (1,1).
+    CubicBezier(f32, f32, f32, f32),
+}
+
+impl Easing {
+    // Map linear progress t in [0, 1] to eased progress
+    pub fn apply(self, t: f32) -> f32 {
+        let t = clamp(t, 0.0, 1.0);
+        match self {
+            Easing::Step => if t < 1.0 { 0.0 } else { 1.0 },
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            },
+            Easing::CubicBezier(x1, y1, x2, y2) => cubic_bezier_timing(t, x1, y1, x2, y2),
+        }
+    }
+}
+
+fn cubic_bezier_timing(x: f32, x1: f32, y1: f32, x2: f32, y2: f32) -> f32 {
+    fn bezier(t: f32, p1: f32, p2: f32) -> f32 {
+        let u = 1.0 - t;
+        3.0 * u * u * t * p1 + 3.0 * u * t * t * p2 + t * t * t
+    }
+
+    fn bezier_slope(t: f32, p1: f32, p2: f32) -> f32 {
+        let u = 1.0 - t;
+        3.0 * u * u * p1 + 6.0 * u * t * (p2 - p1) + 3.0 * t * t * (1.0 - p2)
+    }
+
+    // Find the curve parameter whose x matches the input with a few rounds
+    // of Newton's method, falling back to bisection where the curve is
+    // too flat for Newton to make progress.
+    let mut t = x;
+    for _ in 0..8 {
+        let error = bezier(t, x1, x2) - x;
+        if error.abs() < 1e-6 {
+            return bezier(t, y1, y2);
+        }
+        let slope = bezier_slope(t, x1, x2);
+        if slope.abs() < 1e-6 {
+            break;
+        }
+        t -= error / slope;
+    }
+
+    let mut low = 0.0;
+    let mut high = 1.0;
+    t = x;
+    for _ in 0..32 {
+        if bezier(t, x1, x2) < x {
+            low = t;
+        } else {
+            high = t;
+        }
+        t = (low + high) / 2.0;
+    }
+
+    bezier(t, y1, y2)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe<T> {
+    pub time: f32,
+    pub value: T,
+    // How to get from this keyframe to the next one
+    pub easing: Easing,
+}
+
+impl<T> Keyframe<T> {
+    pub fn new(time: f32, value: T, easing: Easing) -> Keyframe<T> {
+        Keyframe {
+            time,
+            value,
+            easing,
+        }
+    }
+}
+
+// A value that changes over time, defined by keyframes. Before the first
+// keyframe the track holds its first value, and after the last keyframe it
+// holds its last value.
+#[derive(Clone, Debug)]
+pub struct Track<T> {
+    keyframes: Vec<Keyframe<T>>,
+}
+
+impl<T: Animatable> Track<T> {
+    // The keyframes can be in any order. Panics if there are none or if any
+    // of their times aren't finite.
+    pub fn new(mut keyframes: Vec<Keyframe<T>>) -> Track<T> {
+        assert!(!keyframes.is_empty(), "a track needs at least one keyframe");
+        assert!(keyframes.iter().all(|k| k.time.is_finite()), "keyframe times must be finite");
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Track { keyframes }
+    }
+
+    // A track which always has the same value
+    pub fn constant(value: T) -> Track<T> {
+        Track::new(vec![Keyframe::new(0.0, value, Easing::Step)])
+    }
+
+    // Panics if the keyframe's time isn't finite
+    pub fn add(&mut self, keyframe: Keyframe<T>) {
+        assert!(keyframe.time.is_finite(), "keyframe times must be finite");
+        let index = self.keyframes.iter()
+            .position(|k| k.time > keyframe.time)
+            .unwrap_or(self.keyframes.len());
+        self.keyframes.insert(index, keyframe);
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe<T>] {
+        &self.keyframes
+    }
+
+    // Time of the last keyframe
+    pub fn duration(&self) -> f32 {
+        self.keyframes[self.keyframes.len() - 1].time
+    }
+
+    pub fn evaluate(&self, time: f32) -> T {
+        let first = &self.keyframes[0];
+        if time <= first.time {
+            return first.value;
+        }
+
+        for pair in self.keyframes.windows(2) {
+            let (from, to) = (&pair[0], &pair[1]);
+            if time < to.time {
+                let t = (time - from.time) / (to.time - from.time);
+                return T::lerp(from.value, to.value, from.easing.apply(t));
+            }
+        }
+
+        self.keyframes[self.keyframes.len() - 1].value
+    }
+
+    // Evaluate the track as if it repeated forever, with a period equal to
+    // its duration.
+    pub fn evaluate_looped(&self, time: f32) -> T {
+        let duration = self.duration();
+        if duration <= 0.0 {
+            return self.evaluate(time);
+        }
+        self.evaluate(time.rem_euclid(duration))
+    }
+}
+
+#[test]
+fn test_track_evaluation() {
+    let track = Track::new(vec![
+        Keyframe::new(1.0, 10.0, Easing::Linear),
+        Keyframe::new(0.0, 0.0, Easing::Step),
+        Keyframe::new(2.0, 20.0, Easing::Linear),
+    ]);
+
+    assert_eq!(track.evaluate(-1.0), 0.0);
+    assert_eq!(track.evaluate(0.5), 0.0);
+    assert_eq!(track.evaluate(1.0), 10.0);
+    assert_eq!(track.evaluate(1.5), 15.0);
+    assert_eq!(track.evaluate(3.0), 20.0);
+    assert_eq!(track.evaluate_looped(2.5), 0.0);
+
+    // Keyframes at the same time jump straight from one value to the next
+    // and keyframes at times that aren't numbers are rejected
+    let jump = Track::new(vec![Keyframe::new(1.0, 5.0, Easing::Linear), Keyframe::new(1.0, 10.0, Easing::Linear)]);
+    assert_eq!(jump.evaluate(1.0), 5.0);
+    assert_eq!(jump.evaluate(1.01), 10.0);
+    assert!(std::panic::catch_unwind(|| Track::new(vec![Keyframe::new(f32::NAN, 0.0, Easing::Linear)])).is_err());
+
+    for &easing in &[Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut, Easing::CubicBezier(0.25, 0.1, 0.25, 1.0)] {
+        assert!(easing.apply(0.0).abs() < 1e-4);
+        assert!((easing.apply(1.0) - 1.0).abs() < 1e-4);
+    }
+    assert!((Easing::CubicBezier(0.0, 0.0, 1.0, 1.0).apply(0.3) - 0.3).abs() < 1e-4);
+
+    let mut clock = Clock::fixed(60.0);
+    for _ in 0..90 {
+        clock.tick();
+    }
+    assert_eq!(clock.time(), 1.5);
+}
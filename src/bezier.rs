@@ -0,0 +1,159 @@
+use crate::Canvas;
+use crate::composite::BlendMode;
+
+// Curves are drawn as a series of straight lines. This is how far (in
+// pixels) those lines are allowed to stray from the real curve.
+pub const CURVE_TOLERANCE: f32 = 0.1;
+
+// Recursion limit for subdivision. 2^16 lines is far more than any curve
+// that fits on screen needs, this just guards against NaNs and infinities.
+const MAX_DEPTH: u32 = 16;
+
+// Approximate the cubic Bézier curve from p0 to p3 with control points p1
+// and p2 by straight lines, pushing the end of each line onto points. p0
+// itself isn't pushed, so consecutive curves can share a point list.
+pub fn flatten_cubic(
+    p0: (f32, f32),
+    p1: (f32, f32),
+    p2: (f32, f32),
+    p3: (f32, f32),
+    tolerance: f32,
+    points: &mut Vec<(f32, f32)>,
+) {
+    flatten_cubic_recursive(p0, p1, p2, p3, tolerance, 0, points);
+}
+
+// Same as flatten_cubic but for a quadratic curve with a single control
+// point p1.
+pub fn flatten_quad(
+    p0: (f32, f32),
+    p1: (f32, f32),
+    p2: (f32, f32),
+    tolerance: f32,
+    points: &mut Vec<(f32, f32)>,
+) {
+    // Every quadratic curve is also a cubic curve with these control points
+    let c1 = (p0.0 + 2.0 / 3.0 * (p1.0 - p0.0), p0.1 + 2.0 / 3.0 * (p1.1 - p0.1));
+    let c2 = (p2.0 + 2.0 / 3.0 * (p1.0 - p2.0), p2.1 + 2.0 / 3.0 * (p1.1 - p2.1));
+    flatten_cubic(p0, c1, c2, p2, tolerance, points);
+}
+
+fn flatten_cubic_recursive(
+    p0: (f32, f32),
+    p1: (f32, f32),
+    p2: (f32, f32),
+    p3: (f32, f32),
+    tolerance: f32,
+    depth: u32,
+    points: &mut Vec<(f32, f32)>,
+) {
+    // The curve stays within the hull of its control points, so once the
+    // control points are close enough to the line from p0 to p3 so is the
+    // curve.
+    let flat = distance_to_line(p1, p0, p3) <= tolerance
+        && distance_to_line(p2, p0, p3) <= tolerance;
+
+    if flat || depth >= MAX_DEPTH {
+        points.push(p3);
+        return;
+    }
+
+    // Split the curve in half with de Casteljau's algorithm
+    let mid = |a: (f32, f32), b: (f32, f32)| ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+    let p01 = mid(p0, p1);
+    let p12 = mid(p1, p2);
+    let p23 = mid(p2, p3);
+    let p012 = mid(p01, p12);
+    let p123 = mid(p12, p23);
+    let half = mid(p012, p123);
+
+    flatten_cubic_recursive(p0, p01, p012, half, tolerance, depth + 1, points);
+    flatten_cubic_recursive(half, p123, p23, p3, tolerance, depth + 1, points);
+}
+
+// Distance from p to the line through a and b, or to a if a and b are the
+// same point
+fn distance_to_line(p: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length = (dx * dx + dy * dy).sqrt();
+    if length < 1e-6 {
+        ((p.0 - a.0).powi(2) + (p.1 - a.1).powi(2)).sqrt()
+    } else {
+        ((p.0 - a.0) * dy - (p.1 - a.1) * dx).abs() / length
+    }
+}
+
+impl Canvas {
+    // Draw a quadratic Bézier curve as anti-aliased hairlines
+    pub fn quad_bezier(
+        &mut self,
+        color: (f32, f32, f32, f32),
+        blend: BlendMode,
+        p0: (f32, f32),
+        p1: (f32, f32),
+        p2: (f32, f32),
+    ) {
+        let mut points = vec![p0];
+        flatten_quad(p0, p1, p2, CURVE_TOLERANCE, &mut points);
+        self.polyline(color, blend, &points);
+    }
+
+    // Draw a cubic Bézier curve as anti-aliased hairlines
+    pub fn cubic_bezier(
+        &mut self,
+        color: (f32, f32, f32, f32),
+        blend: BlendMode,
+        p0: (f32, f32),
+        p1: (f32, f32),
+        p2: (f32, f32),
+        p3: (f32, f32),
+    ) {
+        let mut points = vec![p0];
+        flatten_cubic(p0, p1, p2, p3, CURVE_TOLERANCE, &mut points);
+        self.polyline(color, blend, &points);
+    }
+
+    fn polyline(&mut self, color: (f32, f32, f32, f32), blend: BlendMode, points: &[(f32, f32)]) {
+        for pair in points.windows(2) {
+            self.wu_line_f(color, blend, pair[0], pair[1]);
+        }
+    }
+}
+
+#[test]
+fn test_flatten_cubic() {
+    let (p0, p1, p2, p3) = ((0.0, 0.0), (0.0, 100.0), (100.0, 100.0), (100.0, 0.0));
+    let mut points = vec![p0];
+    flatten_cubic(p0, p1, p2, p3, CURVE_TOLERANCE, &mut points);
+    assert_eq!(points.last(), Some(&p3));
+
+    // Every point is on the curve, and the middle of every line is within
+    // the tolerance of it
+    let curve = |t: f32| {
+        let u = 1.0 - t;
+        (
+            3.0 * u * t * t * p2.0 + t * t * t * p3.0,
+            3.0 * u * u * t * p1.1 + 3.0 * u * t * t * p2.1,
+        )
+    };
+    let samples: Vec<(f32, f32)> = (0..=10000).map(|i| curve(i as f32 / 10000.0)).collect();
+    let distance = |p: (f32, f32)| {
+        samples.iter()
+            .map(|s| ((s.0 - p.0).powi(2) + (s.1 - p.1).powi(2)).sqrt())
+            .fold(f32::INFINITY, f32::min)
+    };
+    for pair in points.windows(2) {
+        assert!(distance(pair[0]) < 0.05);
+        let middle = ((pair[0].0 + pair[1].0) / 2.0, (pair[0].1 + pair[1].1) / 2.0);
+        assert!(distance(middle) < CURVE_TOLERANCE + 0.05);
+    }
+
+    // Straight curves don't need splitting at all
+    let mut points = Vec::new();
+    flatten_quad((0.0, 0.0), (5.0, 5.0), (10.0, 10.0), CURVE_TOLERANCE, &mut points);
+    assert_eq!(points, vec![(10.0, 10.0)]);
+
+    // Curves off the canvas are clipped
+    let mut canvas = Canvas::new(16, 16);
+    canvas.cubic_bezier((1.0, 1.0, 1.0, 1.0), BlendMode::SourceOver, (-50.0, 8.0), (0.0, -100.0), (20.0, 100.0), (80.0, 8.0));
+}
@@ -0,0 +1,213 @@
+use rayon::prelude::*;
+
+use crate::Canvas;
+use crate::convolve::{convolve_separable_buffer, gaussian_weights, EdgeMode};
+
+// Glow around bright parts of the image, like light scattering in a camera
+// lens. Works on the linear canvas before tone mapping, so overbright
+// pixels glow more than ones that are merely white.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bloom {
+    // Only the part of each pixel brighter than this glows
+    pub threshold: f32,
+    // How much of the glow is added back onto the image
+    pub strength: f32,
+    // Roughly how far the glow spreads, in pixels
+    pub radius: f32,
+}
+
+impl Bloom {
+    pub fn new(threshold: f32, strength: f32, radius: f32) -> Bloom {
+        Bloom {
+            threshold,
+            strength,
+            radius,
+        }
+    }
+}
+
+// Blur applied at every level of the mip chain, in that level's pixels.
+// Each level is half the size of the one before, so together they cover
+// everything from fine to wide glow.
+const LEVEL_SIGMA: f32 = 1.5;
+
+// An image at some fraction of the canvas size. Alpha isn't used, but
+// keeping it means the convolve module can blur levels directly.
+struct Level {
+    width: usize,
+    height: usize,
+    pixels: Vec<(f32, f32, f32, f32)>,
+}
+
+impl Level {
+    fn get(&self, x: usize, y: usize) -> (f32, f32, f32, f32) {
+        self.pixels[x + y * self.width]
+    }
+
+    // Half the size, averaging blocks of 2x2 pixels
+    fn downsample(&self) -> Level {
+        let width = self.width.div_ceil(2);
+        let height = self.height.div_ceil(2);
+        let pixels = (0..width * height)
+            .into_par_iter()
+            .map(|i| {
+                let (x, y) = ((i % width) * 2, (i / width) * 2);
+                let x1 = (x + 1).min(self.width - 1);
+                let y1 = (y + 1).min(self.height - 1);
+                let samples = [self.get(x, y), self.get(x1, y), self.get(x, y1), self.get(x1, y1)];
+                samples.iter().fold((0.0, 0.0, 0.0, 0.0), |sum, p| {
+                    (sum.0 + p.0 / 4.0, sum.1 + p.1 / 4.0, sum.2 + p.2 / 4.0, 0.0)
+                })
+            })
+            .collect();
+
+        Level { width, height, pixels }
+    }
+
+    // Bilinearly interpolated color at a point given in this level's
+    // pixels, with pixel centres on whole numbers
+    fn sample(&self, x: f32, y: f32) -> (f32, f32, f32, f32) {
+        let x = x.max(0.0).min((self.width - 1) as f32);
+        let y = y.max(0.0).min((self.height - 1) as f32);
+        let (x0, y0) = (x as usize, y as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (tx, ty) = (x - x0 as f32, y - y0 as f32);
+
+        let lerp = |a: (f32, f32, f32, f32), b: (f32, f32, f32, f32), t: f32| {
+            (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t, a.2 + (b.2 - a.2) * t, 0.0)
+        };
+        lerp(
+            lerp(self.get(x0, y0), self.get(x1, y0), tx),
+            lerp(self.get(x0, y1), self.get(x1, y1), tx),
+            ty,
+        )
+    }
+
+    // Add a smaller level on top of this one, scaled up to fit
+    fn add_upsampled(&mut self, smaller: &Level) {
+        let width = self.width;
+        let scale_x = smaller.width as f32 / self.width as f32;
+        let scale_y = smaller.height as f32 / self.height as f32;
+        self.pixels.par_iter_mut().enumerate().for_each(|(i, pixel)| {
+            let x = ((i % width) as f32 + 0.5) * scale_x - 0.5;
+            let y = ((i / width) as f32 + 0.5) * scale_y - 0.5;
+            let p = smaller.sample(x, y);
+            *pixel = (pixel.0 + p.0, pixel.1 + p.1, pixel.2 + p.2, 0.0);
+        });
+    }
+
+    // Gaussian blur, repeating the edge pixels past the edges
+    fn blur(&mut self, sigma: f32) {
+        let weights = gaussian_weights(sigma);
+        let mut out = Vec::new();
+        convolve_separable_buffer(&self.pixels, self.width, &mut out, &weights, &weights, EdgeMode::Clamp);
+        self.pixels = out;
+    }
+}
+
+impl Canvas {
+    // Add a glow around everything brighter than bloom.threshold
+    pub fn bloom(&mut self, bloom: &Bloom) {
+        if self.width() == 0 || self.height() == 0 || bloom.radius.is_nan() || bloom.radius <= 0.0 {
+            return;
+        }
+
+        // Bright pass: keep only what's above the threshold, judged by the
+        // brightest channel so saturated colors glow too. Below zero every
+        // pixel would pass, and black ones would divide by zero.
+        let threshold = bloom.threshold.max(0.0);
+        let bright = Level {
+            width: self.width(),
+            height: self.height(),
+            pixels: self.pixels()
+                .par_iter()
+                .map(|&(r, g, b, _)| {
+                    let brightness = r.max(g).max(b);
+                    if brightness > threshold {
+                        let scale = (brightness - threshold) / brightness;
+                        (r * scale, g * scale, b * scale, 0.0)
+                    } else {
+                        (0.0, 0.0, 0.0, 0.0)
+                    }
+                })
+                .collect(),
+        };
+
+        // Enough levels that the blur on the smallest one reaches about
+        // radius pixels on the canvas
+        let count = ((bloom.radius / LEVEL_SIGMA).log2().ceil().max(1.0) as usize).min(8);
+        let mut levels = vec![bright.downsample()];
+        while levels.len() < count {
+            let next = levels.last().unwrap().downsample();
+            levels.push(next);
+        }
+        for level in levels.iter_mut() {
+            level.blur(LEVEL_SIGMA);
+        }
+
+        // Add the levels together from the smallest up
+        let mut glow = levels.pop().unwrap();
+        while let Some(mut level) = levels.pop() {
+            level.add_upsampled(&glow);
+            glow = level;
+        }
+
+        let strength = bloom.strength / count as f32;
+        let (width, height) = (self.width(), self.height());
+        let scale_x = glow.width as f32 / width as f32;
+        let scale_y = glow.height as f32 / height as f32;
+        self.pixels_mut().par_iter_mut().enumerate().for_each(|(i, pixel)| {
+            let x = ((i % width) as f32 + 0.5) * scale_x - 0.5;
+            let y = ((i / width) as f32 + 0.5) * scale_y - 0.5;
+            let p = glow.sample(x, y);
+            pixel.0 += p.0 * strength;
+            pixel.1 += p.1 * strength;
+            pixel.2 += p.2 * strength;
+        });
+    }
+}
+
+#[test]
+fn test_bloom() {
+    let mut canvas = Canvas::new(64, 64);
+    canvas.clear((0.0, 0.0, 0.0, 1.0));
+    canvas.pixels_mut()[32 + 32 * 64] = (10.0, 5.0, 0.5, 1.0);
+
+    let bloom = Bloom::new(1.0, 1.0, 8.0);
+    canvas.bloom(&bloom);
+    let pixel = |x, y| canvas.get_pixel(x, y).unwrap();
+
+    // The bright pixel glows in its own color, fading with distance
+    assert!(pixel(34, 32).0 > pixel(38, 32).0);
+    assert!(pixel(38, 32).0 > pixel(44, 32).0);
+    assert!(pixel(44, 32).0 > 0.0);
+    assert!((pixel(34, 32).1 / pixel(34, 32).0 - 0.5).abs() < 1e-3);
+
+    // The glow adds up to about as much light as was over the threshold
+    let total: f32 = canvas.pixels().iter().map(|p| p.0).sum();
+    assert!((total - 10.0 - 9.0).abs() < 0.5, "{}", total);
+
+    // Dim pixels don't glow at all
+    let mut dim = Canvas::new(64, 64);
+    dim.clear((0.0, 0.0, 0.0, 1.0));
+    dim.pixels_mut()[10 + 10 * 64] = (0.5, 0.5, 0.5, 1.0);
+    let before = dim.pixels().to_vec();
+    dim.bloom(&bloom);
+    assert_eq!(dim.pixels(), &before[..]);
+
+    // Degenerate settings and canvases don't panic
+    canvas.bloom(&Bloom::new(0.0, 1.0, 0.0));
+    Canvas::new(1, 1).bloom(&bloom);
+    Canvas::new(0, 0).bloom(&bloom);
+
+    // Neither does a threshold below zero, which works like zero
+    let bloomed = |threshold| {
+        let mut canvas = Canvas::new(16, 16);
+        canvas.clear((0.0, 0.0, 0.0, 1.0));
+        canvas.pixels_mut()[8 + 8 * 16] = (0.5, 0.5, 0.5, 1.0);
+        canvas.bloom(&Bloom::new(threshold, 1.0, 4.0));
+        canvas.pixels().to_vec()
+    };
+    assert!(bloomed(-1.0).iter().all(|p| p.0.is_finite()));
+    assert_eq!(bloomed(-1.0), bloomed(0.0));
+}
@@ -0,0 +1,166 @@
+use rayon::prelude::*;
+
+use crate::Canvas;
+
+// How linear light values are encoded for output. Pixels on a canvas are
+// linear, but images and video store values that have been through one of
+// these curves so that the 8 bits per channel are spent where the eye can
+// tell the difference.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransferFunction {
+    // IEC 61966-2-1, used by PNG, the web and most monitors
+    Srgb,
+    // The ITU-R BT.709 camera curve, for HD video
+    Rec709,
+    // A pure power curve with the given gamma, e.g. 2.2 or the BT.1886
+    // display gamma of 2.4
+    Gamma(f32),
+}
+
+impl TransferFunction {
+    // Encode a linear value. Values outside 0.0..=1.0 can't be represented
+    // and are clamped.
+    pub fn encode(self, linear: f32) -> f32 {
+        // Written this way round so NaN becomes 0.0 as well
+        let x = if linear > 0.0 { linear.min(1.0) } else { 0.0 };
+        match self {
+            TransferFunction::Srgb => {
+                if x <= 0.003_130_8 {
+                    12.92 * x
+                } else {
+                    1.055 * x.powf(1.0 / 2.4) - 0.055
+                }
+            },
+            TransferFunction::Rec709 => {
+                if x < 0.018 {
+                    4.5 * x
+                } else {
+                    1.099 * x.powf(0.45) - 0.099
+                }
+            },
+            TransferFunction::Gamma(gamma) => x.powf(1.0 / gamma),
+        }
+    }
+
+    // Inverse of encode, turning an encoded value back into linear light
+    pub fn decode(self, encoded: f32) -> f32 {
+        let x = if encoded > 0.0 { encoded.min(1.0) } else { 0.0 };
+        match self {
+            TransferFunction::Srgb => {
+                if x <= 0.040_45 {
+                    x / 12.92
+                } else {
+                    ((x + 0.055) / 1.055).powf(2.4)
+                }
+            },
+            TransferFunction::Rec709 => {
+                if x < 0.081 {
+                    x / 4.5
+                } else {
+                    ((x + 0.099) / 1.099).powf(1.0 / 0.45)
+                }
+            },
+            TransferFunction::Gamma(gamma) => x.powf(gamma),
+        }
+    }
+}
+
+// Size of the lookup table. Values between entries are interpolated, which
+// only goes noticeably wrong right at the start of the curves where they
+// are steepest.
+const TABLE_SIZE: usize = 1 << 14;
+
+// Lookup table from linear values to encoded values, since calling powf
+// three times for each of two million pixels every frame is slow.
+pub struct EncodingTable {
+    transfer: TransferFunction,
+    // Encoded values on the 0.0..=255.0 scale
+    table: Vec<f32>,
+}
+
+impl EncodingTable {
+    pub fn new(transfer: TransferFunction) -> EncodingTable {
+        let table = (0..TABLE_SIZE)
+            .map(|i| {
+                let linear = i as f32 / (TABLE_SIZE - 1) as f32;
+                transfer.encode(linear) * 255.0
+            })
+            .collect();
+
+        EncodingTable { transfer, table }
+    }
+
+    pub fn transfer(&self) -> TransferFunction {
+        self.transfer
+    }
+
+    // The encoded value on the 0.0..=255.0 scale before it's rounded to a
+    // whole 8 bit code, for dithering. Anything out of range is clamped.
+    pub fn level(&self, linear: f32) -> f32 {
+        let x = if linear > 0.0 { linear.min(1.0) } else { 0.0 };
+        let position = x * (TABLE_SIZE - 1) as f32;
+        let index = (position as usize).min(TABLE_SIZE - 2);
+        let t = position - index as f32;
+        self.table[index] + (self.table[index + 1] - self.table[index]) * t
+    }
+
+    // Encode a linear value as 0..=255, clamping anything out of range
+    pub fn encode(&self, linear: f32) -> u8 {
+        self.level(linear).round() as u8
+    }
+
+    // Encode a linear RGB pixel as 0x00RRGGBB. Alpha is ignored: canvas
+    // pixels are premultiplied, so that's the same as compositing over
+    // black.
+    pub fn encode_pixel(&self, (r, g, b, _): (f32, f32, f32, f32)) -> u32 {
+        (self.encode(r) as u32) << 16 | (self.encode(g) as u32) << 8 | self.encode(b) as u32
+    }
+}
+
+// Encode a buffer of linear pixels as 0x00RRGGBB in parallel
+pub fn encode_buffer(in_buffer: &[(f32, f32, f32, f32)], out_buffer: &mut Vec<u32>, table: &EncodingTable) {
+    in_buffer.par_iter()
+        .map(|&pixel| table.encode_pixel(pixel))
+        .collect_into_vec(out_buffer);
+}
+
+impl Canvas {
+    // Like gamma_correct, but with any transfer function
+    pub fn encode(&self, table: &EncodingTable, out_buffer: &mut Vec<u32>) {
+        encode_buffer(self.pixels(), out_buffer, table);
+    }
+}
+
+#[test]
+fn test_transfer_functions() {
+    let transfers = [
+        TransferFunction::Srgb,
+        TransferFunction::Rec709,
+        TransferFunction::Gamma(2.2),
+    ];
+
+    for &transfer in &transfers {
+        let table = EncodingTable::new(transfer);
+        for i in 0..=1000 {
+            let linear = i as f32 / 1000.0;
+            let encoded = transfer.encode(linear);
+            assert!((transfer.decode(encoded) - linear).abs() < 1e-4);
+
+            // The lookup table agrees with the curve to within rounding
+            let exact = encoded * 255.0;
+            assert!((table.level(linear) - exact).abs() < 0.05, "{:?} {}", transfer, linear);
+            assert!((table.encode(linear) as f32 - exact).abs() <= 0.55, "{:?} {}", transfer, linear);
+        }
+    }
+
+    // Reference values for the sRGB curve
+    assert!((TransferFunction::Srgb.encode(0.5) - 0.735_357).abs() < 1e-5);
+    assert!((TransferFunction::Srgb.encode(0.001) - 0.012_92).abs() < 1e-6);
+    assert!((TransferFunction::Srgb.decode(0.5) - 0.214_041).abs() < 1e-5);
+
+    // Overbright and invalid values are clamped instead of spilling into
+    // the neighbouring channels
+    let table = EncodingTable::new(TransferFunction::Srgb);
+    assert_eq!(table.encode_pixel((2.0, -1.0, f32::NAN, 1.0)), 0x00FF0000);
+    assert_eq!(table.encode_pixel((1.0, 0.5, 0.0, 1.0)), 0x00FFBC00);
+}
@@ -0,0 +1,178 @@
+use crate::{coord_to_index, in_range, Canvas};
+
+// How a color being drawn combines with what's already on the canvas.
+//
+// The first twelve are the Porter-Duff operators, which decide how much of
+// the source and destination survive based on where each of them is
+// opaque. The rest are blend modes that mix the colors where both are
+// present and otherwise behave like SourceOver.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendMode {
+    Clear,
+    Source,
+    Destination,
+    // Normal painting: the source on top of the destination
+    SourceOver,
+    DestinationOver,
+    SourceIn,
+    DestinationIn,
+    SourceOut,
+    DestinationOut,
+    SourceAtop,
+    DestinationAtop,
+    Xor,
+    // Adds light, so overlapping lines glow brighter. Colors aren't
+    // clamped and can go above 1.0.
+    Add,
+    // Inverse of multiplying the inverses, so it only ever brightens
+    Screen,
+    // Only ever darkens
+    Multiply,
+    // Multiplies the dark parts of the destination and screens the light
+    // parts, increasing contrast
+    Overlay,
+}
+
+// Colors on a canvas are stored with premultiplied alpha: the color
+// channels are already scaled by alpha. These convert from and to straight
+// alpha.
+pub fn premultiply((r, g, b, a): (f32, f32, f32, f32)) -> (f32, f32, f32, f32) {
+    (r * a, g * a, b * a, a)
+}
+
+pub fn unpremultiply((r, g, b, a): (f32, f32, f32, f32)) -> (f32, f32, f32, f32) {
+    if a == 0.0 {
+        (0.0, 0.0, 0.0, 0.0)
+    } else {
+        (r / a, g / a, b / a, a)
+    }
+}
+
+impl BlendMode {
+    // Combine a premultiplied source color with a premultiplied destination
+    // color
+    pub fn composite(self, src: (f32, f32, f32, f32), dst: (f32, f32, f32, f32)) -> (f32, f32, f32, f32) {
+        let (sa, da) = (src.3, dst.3);
+
+        // Porter-Duff operators are all of the form src * fa + dst * fb
+        let porter_duff = |fa: f32, fb: f32| (
+            src.0 * fa + dst.0 * fb,
+            src.1 * fa + dst.1 * fb,
+            src.2 * fa + dst.2 * fb,
+            sa * fa + da * fb,
+        );
+
+        // Separable blend modes mix the straight colors where both source
+        // and destination are present
+        let blend = |mix: fn(f32, f32) -> f32| {
+            let (s, d) = (unpremultiply(src), unpremultiply(dst));
+            let channel = |cs: f32, cd: f32, s: f32, d: f32| {
+                cs * (1.0 - da) + cd * (1.0 - sa) + sa * da * mix(s, d)
+            };
+            (
+                channel(src.0, dst.0, s.0, d.0),
+                channel(src.1, dst.1, s.1, d.1),
+                channel(src.2, dst.2, s.2, d.2),
+                sa + da - sa * da,
+            )
+        };
+
+        match self {
+            BlendMode::Clear => (0.0, 0.0, 0.0, 0.0),
+            BlendMode::Source => src,
+            BlendMode::Destination => dst,
+            BlendMode::SourceOver => porter_duff(1.0, 1.0 - sa),
+            BlendMode::DestinationOver => porter_duff(1.0 - da, 1.0),
+            BlendMode::SourceIn => porter_duff(da, 0.0),
+            BlendMode::DestinationIn => porter_duff(0.0, sa),
+            BlendMode::SourceOut => porter_duff(1.0 - da, 0.0),
+            BlendMode::DestinationOut => porter_duff(0.0, 1.0 - sa),
+            BlendMode::SourceAtop => porter_duff(da, 1.0 - sa),
+            BlendMode::DestinationAtop => porter_duff(1.0 - da, sa),
+            BlendMode::Xor => porter_duff(1.0 - da, 1.0 - sa),
+            BlendMode::Add => {
+                let (r, g, b, a) = porter_duff(1.0, 1.0);
+                (r, g, b, a.min(1.0))
+            },
+            BlendMode::Screen => blend(|s, d| s + d - s * d),
+            BlendMode::Multiply => blend(|s, d| s * d),
+            BlendMode::Overlay => blend(|s, d| {
+                if d <= 0.5 {
+                    s * 2.0 * d
+                } else {
+                    let d = 2.0 * d - 1.0;
+                    s + d - s * d
+                }
+            }),
+        }
+    }
+}
+
+impl Canvas {
+    // Composite a straight alpha color onto a pixel, for a pixel only
+    // partly covered by the shape being drawn. The result is mixed with the
+    // old pixel by the coverage, so operators like SourceIn only affect
+    // what's actually drawn over.
+    pub fn blend_pixel(
+        &mut self,
+        color: (f32, f32, f32, f32),
+        blend: BlendMode,
+        coverage: f32,
+        x: i32,
+        y: i32,
+    ) {
+        if !in_range(x, 0, self.width() as i32 - 1) || !in_range(y, 0, self.height() as i32 - 1) {
+            panic!("Point out of range x: {}   y: {}", x, y)
+        }
+
+        let index = coord_to_index(x as usize, y as usize, self.width());
+        let old = self.pixels()[index];
+        let new = blend.composite(premultiply(color), old);
+        self.pixels_mut()[index] = (
+            old.0 + (new.0 - old.0) * coverage,
+            old.1 + (new.1 - old.1) * coverage,
+            old.2 + (new.2 - old.2) * coverage,
+            old.3 + (new.3 - old.3) * coverage,
+        );
+    }
+}
+
+#[test]
+fn test_blend_modes() {
+    let composite = |blend: BlendMode, src, dst| {
+        let mut canvas = Canvas::new(1, 1);
+        canvas.clear(dst);
+        canvas.set_pixel(src, blend, 0, 0);
+        unpremultiply(canvas.get_pixel(0, 0).unwrap())
+    };
+    let close = |a: (f32, f32, f32, f32), b: (f32, f32, f32, f32)| {
+        (a.0 - b.0).abs() < 1e-6 && (a.1 - b.1).abs() < 1e-6
+            && (a.2 - b.2).abs() < 1e-6 && (a.3 - b.3).abs() < 1e-6
+    };
+
+    let red = (1.0, 0.0, 0.0, 1.0);
+    let half_blue = (0.0, 0.0, 1.0, 0.5);
+    let grey = (0.25, 0.5, 0.75, 1.0);
+
+    // Drawing onto transparent pixels leaves them partly transparent
+    assert!(close(composite(BlendMode::SourceOver, half_blue, (0.0, 0.0, 0.0, 0.0)), half_blue));
+    assert!(close(composite(BlendMode::SourceOver, half_blue, red), (0.5, 0.0, 0.5, 1.0)));
+    assert!(close(composite(BlendMode::DestinationOver, half_blue, red), red));
+    assert!(close(composite(BlendMode::SourceIn, half_blue, (1.0, 0.0, 0.0, 0.5)), (0.0, 0.0, 1.0, 0.25)));
+    assert!(close(composite(BlendMode::DestinationOut, half_blue, red), (1.0, 0.0, 0.0, 0.5)));
+    assert!(close(composite(BlendMode::Xor, red, red), (0.0, 0.0, 0.0, 0.0)));
+    assert!(close(composite(BlendMode::Clear, red, grey), (0.0, 0.0, 0.0, 0.0)));
+
+    // Blend modes
+    assert!(close(composite(BlendMode::Add, grey, grey), (0.5, 1.0, 1.5, 1.0)));
+    assert!(close(composite(BlendMode::Multiply, grey, grey), (0.0625, 0.25, 0.5625, 1.0)));
+    assert!(close(composite(BlendMode::Screen, grey, grey), (0.4375, 0.75, 0.9375, 1.0)));
+    assert!(close(composite(BlendMode::Overlay, grey, grey), (0.125, 0.5, 0.875, 1.0)));
+    assert!(close(composite(BlendMode::Multiply, red, (0.0, 0.0, 0.0, 0.0)), red));
+
+    // Partly covered pixels are mixed with what was there before
+    let mut canvas = Canvas::new(1, 1);
+    canvas.clear(grey);
+    canvas.blend_pixel(red, BlendMode::Clear, 0.5, 0, 0);
+    assert!(close(canvas.get_pixel(0, 0).unwrap(), (0.125, 0.25, 0.375, 0.5)));
+}
@@ -0,0 +1,263 @@
+use rayon::prelude::*;
+
+use crate::Canvas;
+
+type Pixel = (f32, f32, f32, f32);
+
+// What a kernel sees when it reaches past the edge of the image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EdgeMode {
+    // Repeat the nearest edge pixel. Flat areas stay flat right up to the
+    // edges.
+    Clamp,
+    // Carry on from the opposite edge, for images that tile
+    Wrap,
+    // Everything outside is transparent black, so shapes near the edges
+    // blur out of the image. Use this for shadows and glows drawn on their
+    // own canvas.
+    Transparent,
+}
+
+impl EdgeMode {
+    // Index of the source pixel for a position along an axis of the given
+    // size, or None if the position is outside and transparent
+    fn index(self, position: isize, size: usize) -> Option<usize> {
+        let size = size as isize;
+        if position >= 0 && position < size {
+            return Some(position as usize);
+        }
+        match self {
+            EdgeMode::Clamp => Some(position.max(0).min(size - 1) as usize),
+            EdgeMode::Wrap => Some(position.rem_euclid(size) as usize),
+            EdgeMode::Transparent => None,
+        }
+    }
+}
+
+// A width x height grid of weights, centred on the pixel being computed.
+// Both sizes must be odd so there is a centre.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Kernel {
+    width: usize,
+    height: usize,
+    weights: Vec<f32>,
+}
+
+impl Kernel {
+    // Weights are given row by row
+    pub fn new(width: usize, height: usize, weights: Vec<f32>) -> Kernel {
+        assert!(width % 2 == 1 && height % 2 == 1, "kernel sizes must be odd");
+        assert_eq!(weights.len(), width * height, "wrong number of kernel weights");
+        Kernel { width, height, weights }
+    }
+
+    // The kernel that leaves every pixel alone
+    pub fn identity() -> Kernel {
+        Kernel::new(1, 1, vec![1.0])
+    }
+
+    // The full kernel for a separable pair of weights, where each weight is
+    // row[x] * column[y]. Convolving with the pair directly with
+    // convolve_separable gives the same result much faster.
+    pub fn from_separable(row: &[f32], column: &[f32]) -> Kernel {
+        let weights = column.iter()
+            .flat_map(|&c| row.iter().map(move |&r| r * c))
+            .collect();
+        Kernel::new(row.len(), column.len(), weights)
+    }
+
+    // Boosts each pixel against its four neighbours. An amount of 0.0
+    // changes nothing, and 1.0 is the classic 3x3 sharpening kernel.
+    pub fn sharpen(amount: f32) -> Kernel {
+        let a = -amount;
+        Kernel::new(3, 3, vec![
+            0.0, a, 0.0,
+            a, 1.0 + 4.0 * amount, a,
+            0.0, a, 0.0,
+        ])
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn weights(&self) -> &[f32] {
+        &self.weights
+    }
+}
+
+// Normalized weights of a Gaussian with standard deviation sigma, reaching
+// out three standard deviations either side
+pub fn gaussian_weights(sigma: f32) -> Vec<f32> {
+    if sigma.is_nan() || sigma <= 0.0 {
+        return vec![1.0];
+    }
+
+    let reach = (sigma * 3.0).ceil() as isize;
+    let weights: Vec<f32> = (-reach..=reach)
+        .map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f32 = weights.iter().sum();
+    weights.iter().map(|w| w / total).collect()
+}
+
+// Normalized weights averaging radius pixels either side
+pub fn box_weights(radius: usize) -> Vec<f32> {
+    let size = radius * 2 + 1;
+    vec![1.0 / size as f32; size]
+}
+
+// Convolve a buffer of pixels width pixels wide with a kernel, in parallel.
+// Pixels are premultiplied, so every channel including alpha is convolved
+// the same way.
+pub fn convolve_buffer(
+    in_buffer: &[Pixel],
+    width: usize,
+    out_buffer: &mut Vec<Pixel>,
+    kernel: &Kernel,
+    edges: EdgeMode,
+) {
+    if width == 0 {
+        out_buffer.clear();
+        return;
+    }
+
+    let height = in_buffer.len() / width;
+    let (reach_x, reach_y) = ((kernel.width / 2) as isize, (kernel.height / 2) as isize);
+
+    (0..in_buffer.len())
+        .into_par_iter()
+        .map(|i| {
+            let (x, y) = ((i % width) as isize, (i / width) as isize);
+            let mut sum = (0.0, 0.0, 0.0, 0.0);
+            for (row, weights) in kernel.weights.chunks(kernel.width).enumerate() {
+                let sy = match edges.index(y + row as isize - reach_y, height) {
+                    Some(sy) => sy,
+                    None => continue,
+                };
+                for (column, &weight) in weights.iter().enumerate() {
+                    if let Some(sx) = edges.index(x + column as isize - reach_x, width) {
+                        let p = in_buffer[sx + sy * width];
+                        sum = (sum.0 + p.0 * weight, sum.1 + p.1 * weight, sum.2 + p.2 * weight, sum.3 + p.3 * weight);
+                    }
+                }
+            }
+            sum
+        })
+        .collect_into_vec(out_buffer);
+}
+
+// Convolve a buffer with row weights along each row and then column weights
+// along each column. Blurs like Gaussian and box are separable, and doing
+// them this way costs the sum of the two sizes per pixel instead of the
+// product.
+pub fn convolve_separable_buffer(
+    in_buffer: &[Pixel],
+    width: usize,
+    out_buffer: &mut Vec<Pixel>,
+    row: &[f32],
+    column: &[f32],
+    edges: EdgeMode,
+) {
+    let mut horizontal = Vec::new();
+    convolve_buffer(in_buffer, width, &mut horizontal, &Kernel::new(row.len(), 1, row.to_vec()), edges);
+    convolve_buffer(&horizontal, width, out_buffer, &Kernel::new(1, column.len(), column.to_vec()), edges);
+}
+
+impl Canvas {
+    pub fn convolve(&mut self, kernel: &Kernel, edges: EdgeMode) {
+        let mut out = Vec::new();
+        convolve_buffer(self.pixels(), self.width(), &mut out, kernel, edges);
+        self.pixels_mut().copy_from_slice(&out);
+    }
+
+    pub fn convolve_separable(&mut self, row: &[f32], column: &[f32], edges: EdgeMode) {
+        let mut out = Vec::new();
+        convolve_separable_buffer(self.pixels(), self.width(), &mut out, row, column, edges);
+        self.pixels_mut().copy_from_slice(&out);
+    }
+
+    // Gaussian blur with standard deviation sigma, in pixels
+    pub fn gaussian_blur(&mut self, sigma: f32, edges: EdgeMode) {
+        let weights = gaussian_weights(sigma);
+        self.convolve_separable(&weights, &weights, edges);
+    }
+
+    // Average of the square radius pixels either side of each pixel
+    pub fn box_blur(&mut self, radius: usize, edges: EdgeMode) {
+        let weights = box_weights(radius);
+        self.convolve_separable(&weights, &weights, edges);
+    }
+
+    pub fn sharpen(&mut self, amount: f32, edges: EdgeMode) {
+        self.convolve(&Kernel::sharpen(amount), edges);
+    }
+}
+
+#[test]
+fn test_convolution() {
+    let (width, height) = (16, 12);
+    let total = |canvas: &Canvas| canvas.pixels().iter().map(|p| p.0).sum::<f32>();
+
+    // A single bright pixel near the corner, on a transparent canvas
+    let impulse = || {
+        let mut canvas = Canvas::new(width, height);
+        canvas.clear((0.0, 0.0, 0.0, 0.0));
+        canvas.pixels_mut()[1 + 2 * width] = (1.0, 0.5, 0.25, 1.0);
+        canvas
+    };
+
+    let mut canvas = impulse();
+    canvas.convolve(&Kernel::identity(), EdgeMode::Clamp);
+    assert_eq!(canvas.pixels(), impulse().pixels());
+
+    // Box blur spreads the pixel evenly over a square, and wrapping brings
+    // back what goes off the edge
+    let mut canvas = impulse();
+    canvas.box_blur(2, EdgeMode::Wrap);
+    let share = 1.0 / 25.0;
+    assert!((canvas.get_pixel(3, 4).unwrap().0 - share).abs() < 1e-6);
+    assert!((canvas.get_pixel(15, 0).unwrap().3 - share).abs() < 1e-6);
+    assert_eq!(canvas.get_pixel(4, 2).unwrap().0, 0.0);
+    assert!((total(&canvas) - 1.0).abs() < 1e-5);
+
+    // Transparent edges lose whatever is blurred off the image, while a
+    // flat image clamped at the edges stays flat
+    let mut canvas = impulse();
+    canvas.gaussian_blur(1.5, EdgeMode::Transparent);
+    assert!(total(&canvas) < 0.9);
+    let mut flat = Canvas::new(width, height);
+    flat.clear((0.5, 0.5, 0.5, 1.0));
+    flat.gaussian_blur(1.5, EdgeMode::Clamp);
+    assert!(flat.pixels().iter().all(|p| (p.0 - 0.5).abs() < 1e-5 && (p.3 - 1.0).abs() < 1e-5));
+    let mut transparent = Canvas::new(width, height);
+    transparent.clear((0.5, 0.5, 0.5, 1.0));
+    transparent.gaussian_blur(1.5, EdgeMode::Transparent);
+    assert!(transparent.get_pixel(0, 0).unwrap().3 < 0.5);
+
+    // The separable pass matches the full kernel
+    let weights = gaussian_weights(1.2);
+    let (mut a, mut b) = (impulse(), impulse());
+    a.convolve_separable(&weights, &weights, EdgeMode::Clamp);
+    b.convolve(&Kernel::from_separable(&weights, &weights), EdgeMode::Clamp);
+    for (p, q) in a.pixels().iter().zip(b.pixels()) {
+        assert!((p.0 - q.0).abs() < 1e-6 && (p.3 - q.3).abs() < 1e-6);
+    }
+
+    // Sharpening leaves flat areas alone and steepens edges
+    let mut step = Canvas::new(width, height);
+    step.clear((0.25, 0.25, 0.25, 1.0));
+    for y in 0..height {
+        for x in width / 2..width {
+            step.pixels_mut()[x + y * width] = (0.75, 0.75, 0.75, 1.0);
+        }
+    }
+    step.sharpen(1.0, EdgeMode::Clamp);
+    assert_eq!(step.get_pixel(2, 5), Some((0.25, 0.25, 0.25, 1.0)));
+    assert!(step.get_pixel(7, 5).unwrap().0 < 0.25);
+    assert!(step.get_pixel(8, 5).unwrap().0 > 0.75);
+}
@@ -0,0 +1,126 @@
+use crate::Canvas;
+use crate::math::Vec3;
+
+// How much further away than the surface in a pixel something can be and
+// still be drawn over it, as a fraction of the distance. Lines along the
+// edges of a surface are at the same depth as it, and without some slack
+// they'd flicker in and out as rounding goes one way or the other.
+const DEPTH_TOLERANCE: f32 = 0.01;
+
+impl Canvas {
+    // Start keeping a depth buffer, with the distance from the camera to the
+    // nearest surface in each pixel. 3D lines are hidden where they're
+    // behind a surface that's already been written into it, see
+    // Canvas::draw_mesh_depth. The buffer starts out empty, and enabling it
+    // again empties it.
+    pub fn enable_depth_buffer(&mut self) {
+        self.depth.clear();
+        self.depth.resize(self.width * self.height, f32::INFINITY);
+    }
+
+    pub fn disable_depth_buffer(&mut self) {
+        self.depth = Vec::new();
+    }
+
+    pub fn has_depth_buffer(&self) -> bool {
+        !self.depth.is_empty()
+    }
+
+    pub fn depth_buffer(&self) -> Option<&[f32]> {
+        if self.has_depth_buffer() { Some(&self.depth) } else { None }
+    }
+
+    // Forget every surface in the depth buffer, usually at the start of a
+    // frame along with clear
+    pub fn clear_depth(&mut self) {
+        for depth in &mut self.depth {
+            *depth = f32::INFINITY;
+        }
+    }
+
+    // Whether something distance away from the camera in pixel (x, y) is in
+    // front of what's in the depth buffer there. Always true without a
+    // depth buffer.
+    pub fn depth_test(&self, x: i32, y: i32, distance: f32) -> bool {
+        if !self.has_depth_buffer() || x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return true;
+        }
+        distance <= self.depth[y as usize * self.width + x as usize] * (1.0 + DEPTH_TOLERANCE)
+    }
+
+    // Write a triangle into the depth buffer without drawing it. Each corner
+    // is given in pixels, with z as its distance from the camera. Pixels are
+    // written where their centres are inside the triangle and it's nearer
+    // than what's already there.
+    pub fn fill_depth_triangle(&mut self, a: Vec3, b: Vec3, c: Vec3) {
+        if !self.has_depth_buffer() {
+            return;
+        }
+
+        let area = (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x);
+        if area == 0.0 || area.is_nan() {
+            return;
+        }
+
+        let min_x = a.x.min(b.x).min(c.x).floor().max(0.0) as usize;
+        let min_y = a.y.min(b.y).min(c.y).floor().max(0.0) as usize;
+        let max_x = (a.x.max(b.x).max(c.x).ceil().max(0.0) as usize).min(self.width);
+        let max_y = (a.y.max(b.y).max(c.y).ceil().max(0.0) as usize).min(self.height);
+
+        // How far a point is towards the corner opposite the edge from p to
+        // q, as a fraction of the whole triangle
+        let weight = |p: Vec3, q: Vec3, x: f32, y: f32| ((q.x - p.x) * (y - p.y) - (q.y - p.y) * (x - p.x)) / area;
+
+        // Distances don't change linearly across the screen under
+        // perspective, but their reciprocals do
+        let (inv_a, inv_b, inv_c) = (1.0 / a.z, 1.0 / b.z, 1.0 / c.z);
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let (wa, wb, wc) = (weight(b, c, px, py), weight(c, a, px, py), weight(a, b, px, py));
+                if wa < 0.0 || wb < 0.0 || wc < 0.0 {
+                    continue;
+                }
+                let distance = 1.0 / (wa * inv_a + wb * inv_b + wc * inv_c);
+                let nearest = &mut self.depth[y * self.width + x];
+                if distance < *nearest {
+                    *nearest = distance;
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_depth_buffer() {
+    let mut canvas = Canvas::new(10, 10);
+    assert!(canvas.depth_buffer().is_none());
+    assert!(canvas.depth_test(5, 5, 1e9));
+    canvas.fill_depth_triangle(Vec3::new(0.0, 0.0, 1.0), Vec3::new(10.0, 0.0, 1.0), Vec3::new(0.0, 10.0, 1.0));
+
+    // The nearer of two overlapping triangles wins, whichever way round
+    // they're wound, and distances are interpolated with perspective
+    canvas.enable_depth_buffer();
+    canvas.fill_depth_triangle(Vec3::new(0.0, 0.0, 10.0), Vec3::new(10.0, 0.0, 10.0), Vec3::new(0.0, 10.0, 10.0));
+    canvas.fill_depth_triangle(Vec3::new(0.0, 0.0, 20.0), Vec3::new(0.0, 10.0, 20.0), Vec3::new(10.0, 10.0, 20.0));
+    canvas.fill_depth_triangle(Vec3::new(0.0, 0.0, 5.0), Vec3::new(10.0, 0.0, 5.0), Vec3::new(10.0, 5.0, 5.0));
+    let depth = |canvas: &Canvas, x: usize, y: usize| canvas.depth_buffer().unwrap()[y * 10 + x];
+    let close = |a: f32, b: f32| (a - b).abs() < 1e-4;
+    assert!(close(depth(&canvas, 1, 1), 10.0));
+    assert!(close(depth(&canvas, 1, 7), 10.0));
+    assert!(close(depth(&canvas, 8, 1), 5.0));
+    assert_eq!(depth(&canvas, 9, 8), f32::INFINITY);
+
+    canvas.fill_depth_triangle(Vec3::new(0.0, 0.0, 100.0), Vec3::new(10.0, 0.0, 100.0), Vec3::new(5.0, 10.0, 1.0));
+    let middle = depth(&canvas, 5, 4);
+    assert!(middle > 1.0 && middle < 10.0);
+
+    assert!(canvas.depth_test(0, 7, 10.05));
+    assert!(!canvas.depth_test(0, 7, 11.0));
+    assert!(canvas.depth_test(9, 8, 1e9));
+
+    canvas.clear_depth();
+    assert_eq!(depth(&canvas, 1, 1), f32::INFINITY);
+    canvas.disable_depth_buffer();
+    assert!(!canvas.has_depth_buffer());
+}
@@ -0,0 +1,320 @@
+use rayon::prelude::*;
+
+use crate::Canvas;
+use crate::color::EncodingTable;
+
+// Ways of hiding the banding that comes from rounding smooth gradients to
+// 8 bits. Each trades the bands for a fine pattern of noise that averages
+// out to the right value. None of them are random, so the same frame always
+// dithers the same way and video encoders see a stable picture.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DitherMethod {
+    // Repeating 8x8 Bayer matrix. Cheap, but the cross-hatched pattern can
+    // be visible.
+    Bayer,
+    // Repeating 64x64 blue noise texture made with Ulichney's
+    // void-and-cluster method. Looks like fine grain with no pattern.
+    BlueNoise,
+    // Floyd-Steinberg error diffusion in serpentine order. The most
+    // accurate, but has to run one pixel at a time.
+    ErrorDiffusion,
+}
+
+// A dither method along with its threshold map, which is worth building
+// once and reusing for every frame.
+pub struct Dither {
+    method: DitherMethod,
+    size: usize,
+    // Thresholds between 0.0 and 1.0 for a size x size tile
+    thresholds: Vec<f32>,
+}
+
+impl Dither {
+    pub fn new(method: DitherMethod) -> Dither {
+        let (size, thresholds) = match method {
+            DitherMethod::Bayer => (8, bayer_matrix(8)),
+            DitherMethod::BlueNoise => (64, blue_noise(64)),
+            DitherMethod::ErrorDiffusion => (0, Vec::new()),
+        };
+
+        Dither {
+            method,
+            size,
+            thresholds,
+        }
+    }
+
+    pub fn method(&self) -> DitherMethod {
+        self.method
+    }
+
+    fn threshold(&self, x: usize, y: usize) -> f32 {
+        self.thresholds[(x % self.size) + (y % self.size) * self.size]
+    }
+}
+
+// Thresholds for a size x size Bayer matrix, where size is a power of two.
+// Each matrix is built from four copies of the one half its size.
+fn bayer_matrix(size: usize) -> Vec<f32> {
+    let mut matrix = vec![0usize];
+    let mut current = 1;
+    while current < size {
+        let next = current * 2;
+        let mut bigger = vec![0; next * next];
+        for y in 0..next {
+            for x in 0..next {
+                let quadrant = match (x >= current, y >= current) {
+                    (false, false) => 0,
+                    (true, true) => 1,
+                    (true, false) => 2,
+                    (false, true) => 3,
+                };
+                bigger[x + y * next] = 4 * matrix[x % current + (y % current) * current] + quadrant;
+            }
+        }
+        matrix = bigger;
+        current = next;
+    }
+
+    let count = matrix.len() as f32;
+    matrix.iter().map(|&rank| (rank as f32 + 0.5) / count).collect()
+}
+
+// Thresholds for a size x size tile of blue noise, using the
+// void-and-cluster method. Every pixel gets an energy from a Gaussian of
+// the pixels that are switched on around it, wrapping around the edges so
+// the tile repeats seamlessly. The tightest cluster is the on pixel with
+// the most energy, and the largest void the off pixel with the least.
+fn blue_noise(size: usize) -> Vec<f32> {
+    let count = size * size;
+
+    // Energy added to every pixel by switching on the pixel at (0, 0)
+    let sigma: f32 = 1.5;
+    let kernel: Vec<f32> = (0..count)
+        .map(|i| {
+            let wrapped = |d: usize| d.min(size - d) as f32;
+            let (dx, dy) = (wrapped(i % size), wrapped(i / size));
+            (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+
+    struct Pattern {
+        size: usize,
+        on: Vec<bool>,
+        energy: Vec<f32>,
+    }
+
+    impl Pattern {
+        fn set(&mut self, index: usize, on: bool, kernel: &[f32]) {
+            self.on[index] = on;
+            let sign = if on { 1.0 } else { -1.0 };
+            let (px, py) = (index % self.size, index / self.size);
+            for (i, energy) in self.energy.iter_mut().enumerate() {
+                let dx = (i % self.size + self.size - px) % self.size;
+                let dy = (i / self.size + self.size - py) % self.size;
+                *energy += sign * kernel[dx + dy * self.size];
+            }
+        }
+
+        fn tightest_cluster(&self) -> usize {
+            self.extreme(true, |a, b| a > b)
+        }
+
+        fn largest_void(&self) -> usize {
+            self.extreme(false, |a, b| a < b)
+        }
+
+        fn extreme(&self, on: bool, better: fn(f32, f32) -> bool) -> usize {
+            let mut best = None;
+            for (i, &energy) in self.energy.iter().enumerate() {
+                if self.on[i] == on {
+                    match best {
+                        Some((_, best_energy)) if !better(energy, best_energy) => {},
+                        _ => best = Some((i, energy)),
+                    }
+                }
+            }
+            best.unwrap().0
+        }
+    }
+
+    let mut pattern = Pattern {
+        size,
+        on: vec![false; count],
+        energy: vec![0.0; count],
+    };
+
+    // Start with a tenth of the pixels switched on, picked by a fixed
+    // linear congruential generator so the result is always the same
+    let mut seed: u32 = 1;
+    let mut placed = 0;
+    while placed < count / 10 {
+        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        let index = (seed >> 8) as usize % count;
+        if !pattern.on[index] {
+            pattern.set(index, true, &kernel);
+            placed += 1;
+        }
+    }
+
+    // Spread them out evenly by moving the tightest cluster to the largest
+    // void until that doesn't change anything
+    loop {
+        let cluster = pattern.tightest_cluster();
+        pattern.set(cluster, false, &kernel);
+        let void = pattern.largest_void();
+        pattern.set(void, true, &kernel);
+        if void == cluster {
+            break;
+        }
+    }
+
+    // Rank the starting pixels by taking away the tightest clusters, and
+    // then the rest by filling in the largest voids. The Gaussians add up to
+    // the same total everywhere, so the largest void among the off pixels is
+    // also their tightest cluster once more than half are on.
+    let mut rank = vec![0; count];
+    let start = Pattern {
+        size,
+        on: pattern.on.clone(),
+        energy: pattern.energy.clone(),
+    };
+    for r in (0..placed).rev() {
+        let cluster = pattern.tightest_cluster();
+        pattern.set(cluster, false, &kernel);
+        rank[cluster] = r;
+    }
+
+    let mut pattern = start;
+    for r in placed..count {
+        let void = pattern.largest_void();
+        pattern.set(void, true, &kernel);
+        rank[void] = r;
+    }
+
+    rank.iter().map(|&r| (r as f32 + 0.5) / count as f32).collect()
+}
+
+// Encode a buffer of linear pixels width pixels wide as 0x00RRGGBB, dithering
+// the rounding to 8 bits
+pub fn encode_buffer_dithered(
+    in_buffer: &[(f32, f32, f32, f32)],
+    width: usize,
+    out_buffer: &mut Vec<u32>,
+    table: &EncodingTable,
+    dither: &Dither,
+) {
+    let quantize = |level: f32| clamp_level(level.floor());
+
+    match dither.method {
+        DitherMethod::Bayer | DitherMethod::BlueNoise => {
+            in_buffer.par_iter()
+                .enumerate()
+                .map(|(i, &(r, g, b, _))| {
+                    let threshold = dither.threshold(i % width, i / width);
+                    quantize(table.level(r) + threshold) << 16
+                        | quantize(table.level(g) + threshold) << 8
+                        | quantize(table.level(b) + threshold)
+                })
+                .collect_into_vec(out_buffer);
+        },
+        DitherMethod::ErrorDiffusion => {
+            out_buffer.clear();
+            out_buffer.resize(in_buffer.len(), 0);
+            // A frame with no columns has no rows to carry errors along
+            if width == 0 {
+                return;
+            }
+
+            // Errors carried into the current and next row, per channel, with
+            // a pixel of padding on each side
+            let mut current = vec![[0.0f32; 3]; width + 2];
+            let mut next = vec![[0.0f32; 3]; width + 2];
+
+            for (y, row) in in_buffer.chunks(width).enumerate() {
+                let out_row = &mut out_buffer[y * width..(y + 1) * width];
+
+                // Go back and forth so errors don't all drift the same way
+                let backwards = y % 2 == 1;
+                for step in 0..row.len() {
+                    let x = if backwards { row.len() - 1 - step } else { step };
+                    let forward = |x: usize| if backwards { x - 1 } else { x + 1 };
+                    let backward = |x: usize| if backwards { x + 1 } else { x - 1 };
+
+                    let (r, g, b, _) = row[x];
+                    let mut pixel = 0;
+                    for (channel, &value) in [r, g, b].iter().enumerate() {
+                        let wanted = table.level(value) + current[x + 1][channel];
+                        let code = clamp_level(wanted.round());
+                        let error = wanted - code as f32;
+
+                        current[forward(x + 1)][channel] += error * 7.0 / 16.0;
+                        next[backward(x + 1)][channel] += error * 3.0 / 16.0;
+                        next[x + 1][channel] += error * 5.0 / 16.0;
+                        next[forward(x + 1)][channel] += error * 1.0 / 16.0;
+
+                        pixel = pixel << 8 | code;
+                    }
+                    out_row[x] = pixel;
+                }
+
+                std::mem::swap(&mut current, &mut next);
+                for error in next.iter_mut() {
+                    *error = [0.0; 3];
+                }
+            }
+        },
+    }
+}
+
+fn clamp_level(level: f32) -> u32 {
+    if level > 0.0 { level.min(255.0) as u32 } else { 0 }
+}
+
+impl Canvas {
+    // Like encode, but dithered to hide banding in smooth gradients
+    pub fn encode_dithered(&self, table: &EncodingTable, dither: &Dither, out_buffer: &mut Vec<u32>) {
+        encode_buffer_dithered(self.pixels(), self.width(), out_buffer, table, dither);
+    }
+}
+
+#[test]
+fn test_dithering() {
+    use crate::color::TransferFunction;
+
+    // Every threshold is used exactly once per tile
+    for &(method, size) in &[(DitherMethod::Bayer, 8), (DitherMethod::BlueNoise, 64)] {
+        let mut thresholds = Dither::new(method).thresholds;
+        thresholds.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let count = size * size;
+        for (i, &threshold) in thresholds.iter().enumerate() {
+            assert_eq!(threshold, (i as f32 + 0.5) / count as f32);
+        }
+    }
+    assert_eq!(&bayer_matrix(2), &[0.125, 0.625, 0.875, 0.375]);
+
+    // A flat area between two codes averages out to the right level, and
+    // dithering the same frame twice gives the same result
+    let table = EncodingTable::new(TransferFunction::Gamma(1.0));
+    let (width, height) = (128, 128);
+    let flat = vec![(100.3 / 255.0, 0.0, 1.0, 1.0); width * height];
+    for &method in &[DitherMethod::Bayer, DitherMethod::BlueNoise, DitherMethod::ErrorDiffusion] {
+        let dither = Dither::new(method);
+        let mut out = Vec::new();
+        encode_buffer_dithered(&flat, width, &mut out, &table, &dither);
+
+        let total: u32 = out.iter().map(|pixel| pixel >> 16).sum();
+        let average = total as f32 / out.len() as f32;
+        assert!((average - 100.3).abs() < 0.01, "{:?} {}", method, average);
+        assert!(out.iter().all(|pixel| pixel >> 16 == 100 || pixel >> 16 == 101));
+        assert!(out.iter().all(|pixel| pixel & 0xFFFF == 0x00FF));
+
+        let mut again = Vec::new();
+        encode_buffer_dithered(&flat, width, &mut again, &table, &dither);
+        assert_eq!(out, again);
+
+        // Empty frames encode to nothing
+        encode_buffer_dithered(&[], 0, &mut again, &table, &dither);
+        assert!(again.is_empty());
+    }
+}
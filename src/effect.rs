@@ -0,0 +1,605 @@
+use std::f32::consts::PI;
+
+use rayon::prelude::*;
+
+use crate::animation::Track;
+use crate::bloom::Bloom;
+use crate::composite::{premultiply, unpremultiply};
+use crate::tonemap::ToneMap;
+use crate::{clamp, Canvas};
+
+// A full frame post-processing pass, run on the finished float canvas
+// before it's encoded. Effects are given the scene time so their
+// parameters can be animated with tracks, and the same effect rendered at
+// the same time always gives the same result.
+pub trait Effect {
+    fn apply(&self, canvas: &mut Canvas, time: f32);
+}
+
+// Effects run one after another, in the order they were added. A chain is
+// an effect itself, so a look made of several effects can be built once
+// and reused, or nested inside another chain.
+#[derive(Default)]
+pub struct EffectChain {
+    effects: Vec<Box<dyn Effect>>,
+}
+
+impl EffectChain {
+    pub fn new() -> EffectChain {
+        EffectChain { effects: Vec::new() }
+    }
+
+    pub fn push<E: Effect + 'static>(&mut self, effect: E) {
+        self.effects.push(Box::new(effect));
+    }
+
+    // Like push, for building a chain in one expression
+    pub fn with<E: Effect + 'static>(mut self, effect: E) -> EffectChain {
+        self.push(effect);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.effects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+}
+
+impl Effect for EffectChain {
+    fn apply(&self, canvas: &mut Canvas, time: f32) {
+        for effect in &self.effects {
+            effect.apply(canvas, time);
+        }
+    }
+}
+
+impl Effect for Bloom {
+    fn apply(&self, canvas: &mut Canvas, _time: f32) {
+        canvas.bloom(self);
+    }
+}
+
+impl Effect for ToneMap {
+    fn apply(&self, canvas: &mut Canvas, _time: f32) {
+        canvas.tone_map(self);
+    }
+}
+
+// Distance of a pixel centre from the middle of the canvas, as a fraction
+// of the distance to the corners
+fn centre_distance(x: usize, y: usize, width: usize, height: usize) -> f32 {
+    let dx = x as f32 + 0.5 - width as f32 / 2.0;
+    let dy = y as f32 + 0.5 - height as f32 / 2.0;
+    let corner = (width as f32).hypot(height as f32) / 2.0;
+    dx.hypot(dy) / corner
+}
+
+// Bilinearly interpolated pixel at a point in canvas coordinates, where
+// pixel centres are at +0.5. Points past the edges repeat the edge pixels.
+fn sample(pixels: &[(f32, f32, f32, f32)], width: usize, height: usize, x: f32, y: f32) -> (f32, f32, f32, f32) {
+    let x = (x - 0.5).max(0.0).min((width - 1) as f32);
+    let y = (y - 0.5).max(0.0).min((height - 1) as f32);
+    let (x0, y0) = (x as usize, y as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (tx, ty) = (x - x0 as f32, y - y0 as f32);
+
+    let get = |x: usize, y: usize| pixels[x + y * width];
+    let lerp = |a: (f32, f32, f32, f32), b: (f32, f32, f32, f32), t: f32| (
+        a.0 + (b.0 - a.0) * t,
+        a.1 + (b.1 - a.1) * t,
+        a.2 + (b.2 - a.2) * t,
+        a.3 + (b.3 - a.3) * t,
+    );
+    lerp(lerp(get(x0, y0), get(x1, y0), tx), lerp(get(x0, y1), get(x1, y1), tx), ty)
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    if edge1 <= edge0 {
+        return if x < edge0 { 0.0 } else { 1.0 };
+    }
+    let t = clamp((x - edge0) / (edge1 - edge0), 0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// Darkens the image towards the corners
+#[derive(Clone, Debug)]
+pub struct Vignette {
+    // How dark the corners get, from 0.0 (not at all) to 1.0 (black)
+    pub strength: Track<f32>,
+    // Where the darkening starts, as a fraction of the distance from the
+    // centre to the corners
+    pub radius: Track<f32>,
+    // How far beyond radius it takes to reach full strength, in the same
+    // units
+    pub softness: Track<f32>,
+}
+
+impl Vignette {
+    pub fn new(strength: f32, radius: f32, softness: f32) -> Vignette {
+        Vignette {
+            strength: Track::constant(strength),
+            radius: Track::constant(radius),
+            softness: Track::constant(softness),
+        }
+    }
+}
+
+impl Effect for Vignette {
+    fn apply(&self, canvas: &mut Canvas, time: f32) {
+        let strength = self.strength.evaluate(time);
+        let radius = self.radius.evaluate(time);
+        let softness = self.softness.evaluate(time);
+
+        let (width, height) = (canvas.width(), canvas.height());
+        canvas.pixels_mut().par_iter_mut().enumerate().for_each(|(i, pixel)| {
+            let distance = centre_distance(i % width, i / width, width, height);
+            let scale = 1.0 - strength * smoothstep(radius, radius + softness, distance);
+            pixel.0 *= scale;
+            pixel.1 *= scale;
+            pixel.2 *= scale;
+        });
+    }
+}
+
+// Splits the red and blue channels apart towards the edges of the image,
+// like a cheap lens that focuses each color at a slightly different size
+#[derive(Clone, Debug)]
+pub struct ChromaticAberration {
+    // How far red and blue are moved in opposite directions at the corners,
+    // in pixels. The centre of the image never moves.
+    pub amount: Track<f32>,
+}
+
+impl ChromaticAberration {
+    pub fn new(amount: f32) -> ChromaticAberration {
+        ChromaticAberration {
+            amount: Track::constant(amount),
+        }
+    }
+}
+
+impl Effect for ChromaticAberration {
+    fn apply(&self, canvas: &mut Canvas, time: f32) {
+        let amount = self.amount.evaluate(time);
+        let (width, height) = (canvas.width(), canvas.height());
+        if amount == 0.0 || width == 0 || height == 0 {
+            return;
+        }
+
+        let corner = (width as f32).hypot(height as f32) / 2.0;
+        let scale = amount / corner;
+        let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+        let source = canvas.pixels().to_vec();
+
+        canvas.pixels_mut().par_iter_mut().enumerate().for_each(|(i, pixel)| {
+            let (x, y) = ((i % width) as f32 + 0.5, (i / width) as f32 + 0.5);
+            let (dx, dy) = (x - cx, y - cy);
+            // Red is drawn larger, so each pixel takes it from nearer the
+            // centre, and blue smaller
+            let red = sample(&source, width, height, cx + dx * (1.0 - scale), cy + dy * (1.0 - scale));
+            let blue = sample(&source, width, height, cx + dx * (1.0 + scale), cy + dy * (1.0 + scale));
+            pixel.0 = red.0;
+            pixel.2 = blue.2;
+        });
+    }
+}
+
+// Dark horizontal lines between rows of the picture, like a CRT whose
+// electron beam doesn't quite cover the whole screen
+#[derive(Clone, Debug)]
+pub struct Scanlines {
+    // How dark the gaps between lines are, from 0.0 to 1.0
+    pub strength: Track<f32>,
+    // Distance from one line to the next, in pixels
+    pub period: Track<f32>,
+    // Vertical shift of the lines in pixels. Animate it to make them roll.
+    pub offset: Track<f32>,
+}
+
+impl Scanlines {
+    pub fn new(strength: f32, period: f32) -> Scanlines {
+        Scanlines {
+            strength: Track::constant(strength),
+            period: Track::constant(period),
+            offset: Track::constant(0.0),
+        }
+    }
+}
+
+impl Effect for Scanlines {
+    fn apply(&self, canvas: &mut Canvas, time: f32) {
+        let strength = self.strength.evaluate(time);
+        let period = self.period.evaluate(time);
+        let offset = self.offset.evaluate(time);
+        if period.is_nan() || period <= 0.0 {
+            return;
+        }
+
+        // The brightness of each row follows a raised cosine, brightest in
+        // the middle of a line
+        let width = canvas.width().max(1);
+        let rows: Vec<f32> = (0..canvas.height())
+            .map(|y| {
+                let phase = (y as f32 + 0.5 - offset) / period;
+                let gap = 0.5 - 0.5 * (2.0 * PI * phase).cos();
+                1.0 - strength * gap
+            })
+            .collect();
+
+        canvas.pixels_mut().par_chunks_mut(width).zip(rows.par_iter()).for_each(|(row, &scale)| {
+            for pixel in row.iter_mut() {
+                pixel.0 *= scale;
+                pixel.1 *= scale;
+                pixel.2 *= scale;
+            }
+        });
+    }
+}
+
+// The curved glass and RGB phosphor stripes of a CRT. Combine it with
+// Scanlines and Vignette for the whole look.
+#[derive(Clone, Debug)]
+pub struct Crt {
+    // How much the picture bulges outwards. 0.0 is flat, and around 0.1 to
+    // 0.3 looks like a real tube. Whatever ends up outside the screen is
+    // transparent.
+    pub curvature: Track<f32>,
+    // How much each column of phosphors dims the two colors it doesn't
+    // show, from 0.0 to 1.0
+    pub mask_strength: Track<f32>,
+}
+
+impl Crt {
+    pub fn new(curvature: f32, mask_strength: f32) -> Crt {
+        Crt {
+            curvature: Track::constant(curvature),
+            mask_strength: Track::constant(mask_strength),
+        }
+    }
+}
+
+impl Effect for Crt {
+    fn apply(&self, canvas: &mut Canvas, time: f32) {
+        let curvature = self.curvature.evaluate(time);
+        let mask = self.mask_strength.evaluate(time);
+        let (width, height) = (canvas.width(), canvas.height());
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        let source = if curvature != 0.0 { canvas.pixels().to_vec() } else { Vec::new() };
+        let (half_width, half_height) = (width as f32 / 2.0, height as f32 / 2.0);
+
+        canvas.pixels_mut().par_iter_mut().enumerate().for_each(|(i, pixel)| {
+            let x = i % width;
+
+            if curvature != 0.0 {
+                // Push each point outwards by the square of its distance
+                // from the centre, with -1.0..=1.0 across the screen
+                let u = (x as f32 + 0.5) / half_width - 1.0;
+                let v = ((i / width) as f32 + 0.5) / half_height - 1.0;
+                let bulge = 1.0 + curvature * (u * u + v * v);
+                let (u, v) = (u * bulge, v * bulge);
+                *pixel = if u.abs() > 1.0 || v.abs() > 1.0 {
+                    (0.0, 0.0, 0.0, 0.0)
+                } else {
+                    sample(&source, width, height, (u + 1.0) * half_width, (v + 1.0) * half_height)
+                };
+            }
+
+            let dim = 1.0 - mask;
+            match x % 3 {
+                0 => { pixel.1 *= dim; pixel.2 *= dim; },
+                1 => { pixel.0 *= dim; pixel.2 *= dim; },
+                _ => { pixel.0 *= dim; pixel.1 *= dim; },
+            }
+        });
+    }
+}
+
+// Fine noise over the whole image, like the grain of photographic film.
+// The grain is different every frame but always the same for a given time,
+// so renders stay repeatable.
+#[derive(Clone, Debug)]
+pub struct FilmGrain {
+    // Largest change in brightness, as a fraction of the pixel's value
+    pub strength: Track<f32>,
+}
+
+impl FilmGrain {
+    pub fn new(strength: f32) -> FilmGrain {
+        FilmGrain {
+            strength: Track::constant(strength),
+        }
+    }
+}
+
+// Integer hash from Chris Wellons' hash prospector, good enough that
+// neighbouring inputs give unrelated outputs
+fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x
+}
+
+impl Effect for FilmGrain {
+    fn apply(&self, canvas: &mut Canvas, time: f32) {
+        let strength = self.strength.evaluate(time);
+        let seed = hash(time.to_bits());
+
+        canvas.pixels_mut().par_iter_mut().enumerate().for_each(|(i, pixel)| {
+            // The sum of two uniform values has a triangular distribution
+            // between -1.0 and 1.0, which looks more like real grain than
+            // uniform noise does. The same noise goes on every channel so
+            // the grain doesn't add color.
+            let a = hash(i as u32 ^ seed);
+            let b = hash(a);
+            let uniform = |h: u32| (h >> 8) as f32 / (1 << 24) as f32;
+            let noise = uniform(a) + uniform(b) - 1.0;
+
+            let scale = 1.0 + strength * noise;
+            pixel.0 *= scale;
+            pixel.1 *= scale;
+            pixel.2 *= scale;
+        });
+    }
+}
+
+// A smooth curve through control points, for remapping values like the
+// curves tool in a photo editor. Uses monotone cubic interpolation
+// (Fritsch-Carlson), so a curve through rising points never dips or
+// overshoots between them. Past the first and last points it continues in
+// a straight line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Curve {
+    points: Vec<(f32, f32)>,
+    // Slope of the curve at each point
+    tangents: Vec<f32>,
+}
+
+impl Curve {
+    // The points can be in any order. Panics if there are fewer than two,
+    // if any aren't finite, or if two have the same x.
+    pub fn new(points: &[(f32, f32)]) -> Curve {
+        assert!(points.len() >= 2, "a curve needs at least two points");
+        assert!(
+            points.iter().all(|p| p.0.is_finite() && p.1.is_finite()),
+            "curve points must be finite",
+        );
+        let mut points = points.to_vec();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        assert!(points.windows(2).all(|pair| pair[0].0 < pair[1].0), "curve points must have different x");
+
+        let secants: Vec<f32> = points
+            .windows(2)
+            .map(|pair| (pair[1].1 - pair[0].1) / (pair[1].0 - pair[0].0))
+            .collect();
+
+        // Start with the average of the secants on either side, flattening
+        // out at local extremes
+        let mut tangents: Vec<f32> = (0..points.len())
+            .map(|i| {
+                if i == 0 {
+                    secants[0]
+                } else if i == secants.len() {
+                    secants[i - 1]
+                } else if secants[i - 1] * secants[i] <= 0.0 {
+                    0.0
+                } else {
+                    (secants[i - 1] + secants[i]) / 2.0
+                }
+            })
+            .collect();
+
+        // Then scale down any pair of tangents steep enough to overshoot
+        for (i, &secant) in secants.iter().enumerate() {
+            if secant == 0.0 {
+                tangents[i] = 0.0;
+                tangents[i + 1] = 0.0;
+                continue;
+            }
+            let (alpha, beta) = (tangents[i] / secant, tangents[i + 1] / secant);
+            let length = alpha.hypot(beta);
+            if length > 3.0 {
+                tangents[i] = 3.0 * alpha / length * secant;
+                tangents[i + 1] = 3.0 * beta / length * secant;
+            }
+        }
+
+        Curve { points, tangents }
+    }
+
+    // The curve that leaves every value alone
+    pub fn identity() -> Curve {
+        Curve::new(&[(0.0, 0.0), (1.0, 1.0)])
+    }
+
+    pub fn evaluate(&self, x: f32) -> f32 {
+        let first = 0;
+        let last = self.points.len() - 1;
+        if x <= self.points[first].0 {
+            return self.points[first].1 + (x - self.points[first].0) * self.tangents[first];
+        }
+        if x >= self.points[last].0 {
+            return self.points[last].1 + (x - self.points[last].0) * self.tangents[last];
+        }
+
+        let i = self.points.iter().rposition(|p| p.0 <= x).unwrap();
+        let ((x0, y0), (x1, y1)) = (self.points[i], self.points[i + 1]);
+        let h = x1 - x0;
+        let t = (x - x0) / h;
+
+        // Cubic Hermite basis functions
+        let t2 = t * t;
+        let t3 = t2 * t;
+        (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+            + (t3 - 2.0 * t2 + t) * h * self.tangents[i]
+            + (-2.0 * t3 + 3.0 * t2) * y1
+            + (t3 - t2) * h * self.tangents[i + 1]
+    }
+}
+
+// Color grading with curves: each channel goes through its own curve and
+// then all of them through the master curve. Works on the straight alpha
+// colors, and is meant to run after tone mapping when values are between
+// 0.0 and 1.0.
+#[derive(Clone, Debug)]
+pub struct ColorGrade {
+    pub master: Curve,
+    pub red: Curve,
+    pub green: Curve,
+    pub blue: Curve,
+    // How much of the graded image to use, from 0.0 (the original) to 1.0.
+    // Animate it to fade a grade in or out.
+    pub mix: Track<f32>,
+}
+
+impl ColorGrade {
+    pub fn new(master: Curve) -> ColorGrade {
+        ColorGrade {
+            master,
+            red: Curve::identity(),
+            green: Curve::identity(),
+            blue: Curve::identity(),
+            mix: Track::constant(1.0),
+        }
+    }
+
+    // Grade a single straight alpha pixel at full strength
+    pub fn grade(&self, (r, g, b, a): (f32, f32, f32, f32)) -> (f32, f32, f32, f32) {
+        (
+            self.master.evaluate(self.red.evaluate(r)),
+            self.master.evaluate(self.green.evaluate(g)),
+            self.master.evaluate(self.blue.evaluate(b)),
+            a,
+        )
+    }
+}
+
+impl Effect for ColorGrade {
+    fn apply(&self, canvas: &mut Canvas, time: f32) {
+        let mix = self.mix.evaluate(time);
+        if mix == 0.0 {
+            return;
+        }
+
+        canvas.pixels_mut().par_iter_mut().for_each(|pixel| {
+            if pixel.3 == 0.0 {
+                return;
+            }
+            let original = unpremultiply(*pixel);
+            let graded = self.grade(original);
+            *pixel = premultiply((
+                original.0 + (graded.0 - original.0) * mix,
+                original.1 + (graded.1 - original.1) * mix,
+                original.2 + (graded.2 - original.2) * mix,
+                original.3,
+            ));
+        });
+    }
+}
+
+#[test]
+fn test_effects() {
+    use crate::animation::{Easing, Keyframe};
+
+    let grey = (0.5, 0.5, 0.5, 1.0);
+    let flat = || {
+        let mut canvas = Canvas::new(40, 30);
+        canvas.clear(grey);
+        canvas
+    };
+
+    // The vignette leaves the centre alone and darkens the corners
+    let mut canvas = flat();
+    Vignette::new(0.8, 0.2, 0.6).apply(&mut canvas, 0.0);
+    assert_eq!(canvas.get_pixel(20, 15), Some(grey));
+    let corner = canvas.get_pixel(0, 0).unwrap();
+    assert!(corner.0 < 0.15 && corner.3 == 1.0);
+
+    // Chromatic aberration can't change a flat image, and moves red and
+    // blue in opposite directions
+    let mut canvas = flat();
+    ChromaticAberration::new(3.0).apply(&mut canvas, 0.0);
+    assert!(canvas.pixels().iter().all(|p| (p.0 - 0.5).abs() < 1e-6 && (p.2 - 0.5).abs() < 1e-6));
+    let mut canvas = Canvas::new(40, 30);
+    canvas.clear((0.0, 0.0, 0.0, 1.0));
+    canvas.pixels_mut()[35 + 15 * 40] = (1.0, 1.0, 1.0, 1.0);
+    ChromaticAberration::new(3.0).apply(&mut canvas, 0.0);
+    let red_x = (0..40).max_by(|&a, &b| {
+        canvas.get_pixel(a, 15).unwrap().0.partial_cmp(&canvas.get_pixel(b, 15).unwrap().0).unwrap()
+    });
+    let blue_x = (0..40).max_by(|&a, &b| {
+        canvas.get_pixel(a, 15).unwrap().2.partial_cmp(&canvas.get_pixel(b, 15).unwrap().2).unwrap()
+    });
+    assert!(red_x > Some(35) && blue_x < Some(35));
+
+    // Scanlines darken by half their strength on average
+    let mut canvas = flat();
+    Scanlines::new(0.5, 3.0).apply(&mut canvas, 0.0);
+    let average = canvas.pixels().iter().map(|p| p.0).sum::<f32>() / canvas.pixels().len() as f32;
+    assert!((average - 0.5 * 0.75).abs() < 0.01, "{}", average);
+
+    // A flat CRT with no mask changes nothing, and curvature pushes the
+    // corners off the screen
+    let mut canvas = flat();
+    Crt::new(0.0, 0.0).apply(&mut canvas, 0.0);
+    assert!(canvas.pixels().iter().all(|&p| p == grey));
+    Crt::new(0.3, 0.0).apply(&mut canvas, 0.0);
+    assert_eq!(canvas.get_pixel(0, 0), Some((0.0, 0.0, 0.0, 0.0)));
+    assert_eq!(canvas.get_pixel(20, 15), Some(grey));
+
+    // Grain is repeatable for the same time, different for another, and
+    // averages out to the original image
+    let grain = FilmGrain::new(0.2);
+    let (mut a, mut b, mut c) = (flat(), flat(), flat());
+    grain.apply(&mut a, 1.0);
+    grain.apply(&mut b, 1.0);
+    grain.apply(&mut c, 1.5);
+    assert_eq!(a.pixels(), b.pixels());
+    assert_ne!(a.pixels(), c.pixels());
+    let average = a.pixels().iter().map(|p| p.0).sum::<f32>() / a.pixels().len() as f32;
+    assert!((average - 0.5).abs() < 0.01);
+    assert!(a.pixels().iter().all(|p| p.0 == p.1 && (p.0 - 0.5).abs() <= 0.1));
+
+    // Curves pass through their points without overshooting
+    let curve = Curve::new(&[(0.0, 0.0), (0.25, 0.1), (0.75, 0.9), (1.0, 1.0)]);
+    assert!((curve.evaluate(0.25) - 0.1).abs() < 1e-6);
+    assert!((curve.evaluate(0.5) - 0.5).abs() < 1e-6);
+    let mut last = curve.evaluate(0.0);
+    for i in 1..=100 {
+        let value = curve.evaluate(i as f32 / 100.0);
+        assert!(value >= last && value <= 1.0);
+        last = value;
+    }
+    assert_eq!(Curve::identity().evaluate(2.0), 2.0);
+
+    // Points that aren't numbers or that share an x are rejected up front
+    // rather than panicking while sorting or dividing by zero
+    assert!(std::panic::catch_unwind(|| Curve::new(&[(0.0, 0.0), (f32::NAN, 0.5), (1.0, 1.0)])).is_err());
+    assert!(std::panic::catch_unwind(|| Curve::new(&[(0.0, 0.0), (0.5, 0.2), (0.5, 0.8)])).is_err());
+
+    // Grading works on straight alpha colors, and its mix can be animated
+    // as part of a chain
+    let mut grade = ColorGrade::new(Curve::new(&[(0.0, 0.0), (0.5, 0.8), (1.0, 1.0)]));
+    grade.mix = Track::new(vec![
+        Keyframe::new(0.0, 0.0, Easing::Linear),
+        Keyframe::new(1.0, 1.0, Easing::Linear),
+    ]);
+    let chain = EffectChain::new().with(grade).with(Vignette::new(0.0, 0.5, 0.5));
+    assert_eq!(chain.len(), 2);
+
+    let mut canvas = Canvas::new(2, 2);
+    canvas.clear((0.5, 0.5, 0.5, 0.5));
+    chain.apply(&mut canvas, 0.0);
+    assert_eq!(canvas.get_pixel(0, 0), Some((0.25, 0.25, 0.25, 0.5)));
+    chain.apply(&mut canvas, 1.0);
+    let pixel = canvas.get_pixel(0, 0).unwrap();
+    assert!((pixel.0 - 0.4).abs() < 1e-6 && pixel.3 == 0.5);
+}
@@ -0,0 +1,249 @@
+use std::f32::consts::PI;
+
+use crate::{clamp, Canvas};
+use crate::composite::BlendMode;
+use crate::raster::{FillRule, Mask};
+
+impl Canvas {
+    // Draw the outline of a circle as a one pixel wide anti-aliased line.
+    // Coordinates are in pixels where (0.0, 0.0) is the top left corner of
+    // the top left pixel, the same as for wu_line_f.
+    pub fn circle(&mut self, color: (f32, f32, f32, f32), blend: BlendMode, center: (f32, f32), radius: f32) {
+        self.ellipse(color, blend, center, (radius, radius));
+    }
+
+    // Draw the outline of an axis aligned ellipse with the given horizontal
+    // and vertical radii
+    pub fn ellipse(&mut self, color: (f32, f32, f32, f32), blend: BlendMode, center: (f32, f32), radii: (f32, f32)) {
+        self.arc(color, blend, center, radii, 0.0, 2.0 * PI);
+    }
+
+    // Draw part of the outline of an ellipse, going clockwise from
+    // start_angle to end_angle. Angles are in radians with 0.0 pointing
+    // right, and like in the HTML canvas API they're measured before the
+    // circle is squashed into an ellipse.
+    pub fn arc(
+        &mut self,
+        color: (f32, f32, f32, f32),
+        blend: BlendMode,
+        center: (f32, f32),
+        radii: (f32, f32),
+        start_angle: f32,
+        end_angle: f32,
+    ) {
+        let sweep = match arc_sweep(center, radii, start_angle, end_angle) {
+            None => return,
+            Some(sweep) => sweep,
+        };
+
+        // The outline is only an ellipse on the canvas without a transform.
+        // Otherwise draw it as the lines it would be flattened into.
+        if !self.transform().is_identity() {
+            let points = arc_points(center, radii, start_angle, sweep, 1.0);
+            for pair in points.windows(2) {
+                self.wu_line_f(color, blend, pair[0], pair[1]);
+            }
+            return;
+        }
+
+        let mask = self.outline_mask(center, radii, |angle| {
+            (angle - start_angle).rem_euclid(2.0 * PI) <= sweep
+        });
+        if let Some(mask) = mask {
+            self.fill_mask(color, blend, &mask);
+        }
+    }
+
+    pub fn fill_circle(&mut self, color: (f32, f32, f32, f32), blend: BlendMode, center: (f32, f32), radius: f32) {
+        self.fill_ellipse(color, blend, center, (radius, radius));
+    }
+
+    pub fn fill_ellipse(&mut self, color: (f32, f32, f32, f32), blend: BlendMode, center: (f32, f32), radii: (f32, f32)) {
+        self.fill_arc(color, blend, center, radii, 0.0, 2.0 * PI);
+    }
+
+    // Fill the pie slice between the center and the arc from start_angle to
+    // end_angle. Angles work the same as for arc.
+    pub fn fill_arc(
+        &mut self,
+        color: (f32, f32, f32, f32),
+        blend: BlendMode,
+        center: (f32, f32),
+        radii: (f32, f32),
+        start_angle: f32,
+        end_angle: f32,
+    ) {
+        let sweep = match arc_sweep(center, radii, start_angle, end_angle) {
+            None => return,
+            Some(sweep) => sweep,
+        };
+
+        // A polygon with its corners on the ellipse is slightly smaller than
+        // it, so push the corners out until the areas match
+        let step = sweep / arc_segments(radii, sweep) as f32;
+        let mut points = arc_points(center, radii, start_angle, sweep, (step / step.sin()).sqrt());
+        if sweep < 2.0 * PI {
+            points.push(center);
+        }
+
+        self.fill_polygon(color, blend, &points, FillRule::NonZero);
+    }
+
+    // Wu's algorithm adapted to ellipses. Where the outline is closer to
+    // horizontal we find where it crosses every column and split that
+    // column's coverage between the two pixels above and below, and where
+    // it's closer to vertical we do the same for every row. Only the part
+    // of the outline over the canvas is visited, so huge ellipses are as
+    // cheap as ones that just fit.
+    fn outline_mask<F>(&self, (cx, cy): (f32, f32), (rx, ry): (f32, f32), in_arc: F) -> Option<Mask>
+        where F: Fn(f32) -> bool
+    {
+        let x0 = clamp((cx - rx).floor() - 1.0, 0.0, self.width() as f32) as i32;
+        let y0 = clamp((cy - ry).floor() - 1.0, 0.0, self.height() as f32) as i32;
+        let x1 = clamp((cx + rx).ceil() + 1.0, 0.0, self.width() as f32) as i32;
+        let y1 = clamp((cy + ry).ceil() + 1.0, 0.0, self.height() as f32) as i32;
+        if x1 <= x0 || y1 <= y0 {
+            return None;
+        }
+        let mut mask = Mask::new(x0, y0, (x1 - x0) as usize, (y1 - y0) as usize);
+
+        // The outline is at 45 degrees where it passes through these offsets
+        // from the center
+        let length = (rx * rx + ry * ry).sqrt();
+        let (split_x, split_y) = (rx * rx / length, ry * ry / length);
+
+        // Split a point on the outline between two neighbouring pixels. The
+        // point is given along the axis we're stepping across, so pixel
+        // centres are half way between whole numbers.
+        let plot = |mask: &mut Mask, along: i32, across: f32, vertical: bool| {
+            let (low, high) = if vertical { (y0, y1) } else { (x0, x1) };
+            if across < low as f32 - 1.0 || across > high as f32 + 1.0 {
+                return;
+            }
+            let p = across - 0.5;
+            let (pixel, frac) = (p.floor() as i32, p - p.floor());
+            for &(i, coverage) in &[(pixel, 1.0 - frac), (pixel + 1, frac)] {
+                if vertical {
+                    mask.cover(along, i, coverage);
+                } else {
+                    mask.cover(i, along, coverage);
+                }
+            }
+        };
+
+        // Top and bottom
+        let first = ((cx - split_x - 0.5).ceil() as i32).max(x0);
+        let last = ((cx + split_x - 0.5).floor() as i32).min(x1 - 1);
+        for x in first..=last {
+            let u = x as f32 + 0.5 - cx;
+            let v = ry * (1.0 - (u / rx).powi(2)).max(0.0).sqrt();
+            for &v in &[-v, v] {
+                if in_arc((v / ry).atan2(u / rx)) {
+                    plot(&mut mask, x, cy + v, true);
+                }
+            }
+        }
+
+        // Left and right
+        let first = ((cy - split_y - 0.5).ceil() as i32).max(y0);
+        let last = ((cy + split_y - 0.5).floor() as i32).min(y1 - 1);
+        for y in first..=last {
+            let v = y as f32 + 0.5 - cy;
+            let u = rx * (1.0 - (v / ry).powi(2)).max(0.0).sqrt();
+            for &u in &[-u, u] {
+                if in_arc((v / ry).atan2(u / rx)) {
+                    plot(&mut mask, y, cx + u, false);
+                }
+            }
+        }
+
+        Some(mask)
+    }
+}
+
+// How many straight lines to flatten an arc into so that it's well within
+// a tenth of a pixel of the real curve
+fn arc_segments(radii: (f32, f32), sweep: f32) -> usize {
+    let tolerance = 0.1;
+    let radius = radii.0.max(radii.1);
+    let step = (2.0 * (1.0 - tolerance / radius).max(-1.0).acos()).min(PI / 4.0);
+    clamp((sweep / step).ceil(), 1.0, 4096.0) as usize
+}
+
+// Points along an arc, from start_angle to start_angle + sweep, with the
+// radii scaled by scale
+fn arc_points(center: (f32, f32), radii: (f32, f32), start_angle: f32, sweep: f32, scale: f32) -> Vec<(f32, f32)> {
+    let segments = arc_segments(radii, sweep);
+    let step = sweep / segments as f32;
+    (0..=segments)
+        .map(|i| {
+            let angle = start_angle + step * i as f32;
+            (
+                center.0 + scale * radii.0 * angle.cos(),
+                center.1 + scale * radii.1 * angle.sin(),
+            )
+        })
+        .collect()
+}
+
+// How far an arc from start_angle to end_angle sweeps clockwise, or None if
+// there's nothing to draw
+fn arc_sweep(center: (f32, f32), radii: (f32, f32), start_angle: f32, end_angle: f32) -> Option<f32> {
+    let finite = center.0.is_finite() && center.1.is_finite()
+        && radii.0.is_finite() && radii.1.is_finite()
+        && start_angle.is_finite() && end_angle.is_finite();
+    if !finite || radii.0 <= 0.0 || radii.1 <= 0.0 {
+        return None;
+    }
+
+    let sweep = if end_angle - start_angle >= 2.0 * PI {
+        2.0 * PI
+    } else {
+        (end_angle - start_angle).rem_euclid(2.0 * PI)
+    };
+    if sweep > 0.0 { Some(sweep) } else { None }
+}
+
+#[test]
+fn test_ellipses() {
+    let brightness = |draw: &dyn Fn(&mut Canvas)| {
+        let mut canvas = Canvas::new(32, 32);
+        canvas.clear((0.0, 0.0, 0.0, 1.0));
+        draw(&mut canvas);
+        move |x, y| canvas.get_pixel(x, y).unwrap().0
+    };
+    let white = (1.0, 1.0, 1.0, 1.0);
+
+    // The top of the circle is at y = 6.0125 in column 15, so it's split
+    // between pixel rows 5 and 6
+    let circle = brightness(&|canvas| canvas.circle(white, BlendMode::SourceOver, (16.0, 16.0), 10.0));
+    assert!((circle(15, 5) - 0.4875).abs() < 1e-3);
+    assert!((circle(15, 6) - 0.5125).abs() < 1e-3);
+    assert!((circle(26, 16) + circle(25, 16) - 1.0).abs() < 1e-3);
+    assert_eq!(circle(16, 16), 0.0);
+
+    // The arc from the right to the bottom only covers that quarter
+    let arc = brightness(&|canvas| canvas.arc(white, BlendMode::SourceOver, (16.0, 16.0), (10.0, 10.0), 0.0, PI / 2.0));
+    assert!(arc(16, 26) > 0.4);
+    assert!(arc(25, 16) > 0.4);
+    for &(x, y) in &[(15, 5), (15, 6), (5, 16), (6, 16), (8, 8), (23, 8), (8, 23)] {
+        assert_eq!(arc(x, y), 0.0, "{} {}", x, y);
+    }
+
+    // Filled circles cover their area
+    let disc = brightness(&|canvas| canvas.fill_circle(white, BlendMode::SourceOver, (16.0, 16.0), 10.0));
+    let area: f32 = (0..32).flat_map(|y| (0..32).map(move |x| (x, y))).map(|(x, y)| disc(x, y)).sum();
+    assert!((area - PI * 100.0).abs() < 0.1);
+    assert_eq!(disc(16, 16), 1.0);
+    assert_eq!(disc(0, 0), 0.0);
+
+    // Nothing off the canvas or degenerate is drawn, and nothing panics
+    let mut canvas = Canvas::new(32, 32);
+    canvas.clear((0.0, 0.0, 0.0, 1.0));
+    canvas.circle(white, BlendMode::SourceOver, (-1e9, 16.0), 1e9 - 100.0);
+    canvas.ellipse(white, BlendMode::SourceOver, (16.0, 16.0), (0.0, 10.0));
+    canvas.arc(white, BlendMode::SourceOver, (f32::NAN, 16.0), (10.0, 10.0), 0.0, 1.0);
+    canvas.fill_ellipse(white, BlendMode::SourceOver, (1e30, 1e30), (1e30, 5.0));
+    canvas.fill_arc(white, BlendMode::SourceOver, (16.0, 16.0), (10.0, 10.0), 1.0, 1.0);
+    assert!(canvas.pixels().iter().all(|&pixel| pixel == (0.0, 0.0, 0.0, 1.0)));
+}
@@ -0,0 +1,83 @@
+use crate::Canvas;
+use crate::composite::BlendMode;
+use crate::path::Path;
+use crate::raster::{FillRule, Rasterizer};
+
+impl Canvas {
+    // Fill the inside of a polygon given in pixel coordinates, where (0.0,
+    // 0.0) is the top left corner of the top left pixel. The last point is
+    // joined back to the first. Edge pixels are blended by exactly how much
+    // of them is inside the polygon.
+    pub fn fill_polygon(&mut self, color: (f32, f32, f32, f32), blend: BlendMode, points: &[(f32, f32)], rule: FillRule) {
+        let mut path = Path::new();
+        for &p in points {
+            path.line_to(p);
+        }
+        self.fill_path(color, blend, &path, rule);
+    }
+
+    // Fill the inside of a path. Every subpath is treated as closed, and
+    // the fill rule decides how overlapping subpaths combine.
+    pub fn fill_path(&mut self, color: (f32, f32, f32, f32), blend: BlendMode, path: &Path, rule: FillRule) {
+        let path = &self.transform().project_fill_path(path);
+        let (min, max) = match path.bounds() {
+            None => return,
+            Some(bounds) => bounds,
+        };
+
+        let mut rasterizer = match Rasterizer::for_bounds(self, min, max) {
+            None => return,
+            Some(rasterizer) => rasterizer,
+        };
+
+        for subpath in path.subpaths() {
+            if subpath.points.len() > 2 {
+                rasterizer.polygon(&subpath.points);
+            }
+        }
+
+        self.fill_mask(color, blend, &rasterizer.into_mask(rule));
+    }
+}
+
+#[test]
+fn test_fill_rules() {
+    // A five pointed star, where the pentagon in the middle is wound around
+    // twice
+    let star: Vec<(f32, f32)> = (0..5)
+        .map(|i| {
+            let angle = (i * 2) as f32 * std::f32::consts::PI * 2.0 / 5.0;
+            (16.0 + 14.0 * angle.sin(), 16.0 - 14.0 * angle.cos())
+        })
+        .collect();
+
+    let brightness = |rule| {
+        let mut canvas = Canvas::new(32, 32);
+        canvas.clear((0.0, 0.0, 0.0, 1.0));
+        canvas.fill_polygon((1.0, 1.0, 1.0, 1.0), BlendMode::SourceOver, &star, rule);
+        move |x, y| canvas.get_pixel(x, y).unwrap().0
+    };
+
+    let non_zero = brightness(FillRule::NonZero);
+    assert!(non_zero(16, 16) > 0.999);
+    assert!(non_zero(16, 9) > 0.999);
+    assert_eq!(non_zero(1, 30), 0.0);
+
+    let even_odd = brightness(FillRule::EvenOdd);
+    assert!(even_odd(16, 16) < 0.001);
+    assert!(even_odd(16, 9) > 0.999);
+
+    // Edge pixels are blended by their coverage, and polygons hanging off
+    // the canvas are clipped
+    let mut canvas = Canvas::new(8, 8);
+    canvas.clear((0.0, 0.0, 0.0, 1.0));
+    canvas.fill_polygon((1.0, 1.0, 1.0, 1.0), BlendMode::SourceOver, &[(-5.0, -5.0), (4.5, -5.0), (4.5, 20.0), (-5.0, 20.0)], FillRule::NonZero);
+    assert_eq!(canvas.get_pixel(3, 7), Some((1.0, 1.0, 1.0, 1.0)));
+    assert_eq!(canvas.get_pixel(4, 7), Some((0.5, 0.5, 0.5, 1.0)));
+    assert_eq!(canvas.get_pixel(5, 7), Some((0.0, 0.0, 0.0, 1.0)));
+
+    // Points that aren't numbers are fine
+    canvas.fill_polygon((1.0, 1.0, 1.0, 1.0), BlendMode::SourceOver, &[(f32::NAN, 0.0), (-5.0, 5.0), (5.0, 10.0)], FillRule::NonZero);
+    canvas.fill_polygon((1.0, 1.0, 1.0, 1.0), BlendMode::SourceOver, &[(f32::INFINITY, 0.0), (0.0, 5.0), (5.0, 5.0)], FillRule::NonZero);
+    assert!(canvas.pixels().iter().all(|pixel| pixel.0.is_finite()));
+}
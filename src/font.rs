@@ -0,0 +1,775 @@
+use std::collections::HashMap;
+use std::io;
+
+use crate::Canvas;
+use crate::composite::BlendMode;
+use crate::path::Path;
+use crate::raster::{FillRule, Mask, Rasterizer};
+
+// Components of composite glyphs can be composites themselves. Real fonts
+// only nest a couple of levels, so anything deeper is a loop.
+const MAX_COMPONENT_DEPTH: usize = 8;
+
+// Real composite glyphs have a handful of components, so a glyph made of
+// more than this, counting the components of components, is one built to
+// take forever to read
+const MAX_COMPONENTS: usize = 256;
+
+// Far more characters than any real font maps, while keeping a malformed
+// character map from filling memory
+const MAX_MAPPED_CHARACTERS: usize = 0x40000;
+
+// A TrueType font, or an OpenType font with TrueType outlines, loaded into
+// memory. Glyphs are looked up by index, where index 0 is the font's
+// "missing glyph" box. All metrics are in font units, of which there are
+// units_per_em to the em.
+#[derive(Clone, Debug)]
+pub struct Font {
+    data: Vec<u8>,
+    units_per_em: u16,
+    ascender: i16,
+    descender: i16,
+    line_gap: i16,
+    glyph_count: u16,
+    // Offsets of the tables glyphs are read from on demand
+    glyf: usize,
+    loca: usize,
+    long_loca: bool,
+    hmtx: usize,
+    h_metric_count: u16,
+    // Characters to glyph indices, from the best Unicode cmap subtable
+    cmap: HashMap<u32, u16>,
+    // Extra space between pairs of glyphs from the kern table
+    kerning: HashMap<(u16, u16), i16>,
+}
+
+// A point on a glyph outline in font units, with y going up. Points that
+// are off the curve are control points of quadratic Béziers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OutlinePoint {
+    pub x: f32,
+    pub y: f32,
+    pub on_curve: bool,
+}
+
+impl Font {
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> io::Result<Font> {
+        Font::from_bytes(std::fs::read(path)?)
+    }
+
+    // Parse a font file. For font collections the first font is loaded.
+    pub fn from_bytes(data: Vec<u8>) -> io::Result<Font> {
+        let mut start = 0;
+        match read_u32(&data, 0)? {
+            0x0001_0000 | 0x7472_7565 => {},
+            // "ttcf"
+            0x7474_6366 => start = read_u32(&data, 12)? as usize,
+            // "OTTO"
+            0x4F54_544F => return Err(invalid("fonts with CFF outlines aren't supported")),
+            _ => return Err(invalid("not a TrueType or OpenType font")),
+        }
+
+        let table_count = read_u16(&data, start + 4)? as usize;
+        let mut tables = HashMap::new();
+        for i in 0..table_count {
+            let record = start + 12 + i * 16;
+            let tag = data.get(record..record + 4).ok_or_else(|| invalid("truncated table directory"))?;
+            let offset = read_u32(&data, record + 8)? as usize;
+            let length = read_u32(&data, record + 12)? as usize;
+            if offset.checked_add(length).is_none_or(|end| end > data.len()) {
+                return Err(invalid("table runs past the end of the font"));
+            }
+            tables.insert([tag[0], tag[1], tag[2], tag[3]], offset);
+        }
+        let table = |tag: &[u8; 4]| {
+            tables.get(tag).copied().ok_or_else(|| {
+                invalid(&format!("missing {} table", String::from_utf8_lossy(tag)))
+            })
+        };
+
+        let head = table(b"head")?;
+        let hhea = table(b"hhea")?;
+        let units_per_em = read_u16(&data, head + 18)?;
+        if units_per_em == 0 {
+            return Err(invalid("units per em is zero"));
+        }
+
+        let font = Font {
+            units_per_em,
+            ascender: read_u16(&data, hhea + 4)? as i16,
+            descender: read_u16(&data, hhea + 6)? as i16,
+            line_gap: read_u16(&data, hhea + 8)? as i16,
+            glyph_count: read_u16(&data, table(b"maxp")? + 4)?,
+            glyf: table(b"glyf")?,
+            loca: table(b"loca")?,
+            long_loca: read_u16(&data, head + 50)? != 0,
+            hmtx: table(b"hmtx")?,
+            h_metric_count: read_u16(&data, hhea + 34)?,
+            cmap: parse_cmap(&data, table(b"cmap")?)?,
+            kerning: match tables.get(b"kern") {
+                Some(&kern) => parse_kern(&data, kern)?,
+                None => HashMap::new(),
+            },
+            data,
+        };
+        if font.h_metric_count == 0 {
+            return Err(invalid("no horizontal metrics"));
+        }
+        Ok(font)
+    }
+
+    pub fn units_per_em(&self) -> u16 {
+        self.units_per_em
+    }
+
+    // Height above the baseline the font is designed to reach
+    pub fn ascender(&self) -> i16 {
+        self.ascender
+    }
+
+    // Depth below the baseline the font is designed to reach, as a
+    // negative number
+    pub fn descender(&self) -> i16 {
+        self.descender
+    }
+
+    // Extra space the font asks for between lines
+    pub fn line_gap(&self) -> i16 {
+        self.line_gap
+    }
+
+    pub fn glyph_count(&self) -> u16 {
+        self.glyph_count
+    }
+
+    // The glyph for a character, or 0 for the missing glyph
+    pub fn glyph_index(&self, c: char) -> u16 {
+        self.cmap.get(&(c as u32)).copied().filter(|&glyph| glyph < self.glyph_count).unwrap_or(0)
+    }
+
+    pub fn has_glyph(&self, c: char) -> bool {
+        self.glyph_index(c) != 0
+    }
+
+    // How far to move along the baseline after drawing a glyph
+    pub fn advance_width(&self, glyph: u16) -> u16 {
+        // Glyphs past the end of the long metrics share the last advance
+        let metric = glyph.min(self.h_metric_count - 1) as usize;
+        read_u16(&self.data, self.hmtx + metric * 4).unwrap_or(0)
+    }
+
+    // Space between the glyph's origin and the left of its outline
+    pub fn left_side_bearing(&self, glyph: u16) -> i16 {
+        let offset = if glyph < self.h_metric_count {
+            self.hmtx + glyph as usize * 4 + 2
+        } else {
+            self.hmtx + self.h_metric_count as usize * 4 + (glyph - self.h_metric_count) as usize * 2
+        };
+        read_u16(&self.data, offset).unwrap_or(0) as i16
+    }
+
+    // Adjustment to the advance between left and right when right follows
+    // left, usually negative to tuck pairs like "AV" together
+    pub fn kerning(&self, left: u16, right: u16) -> i16 {
+        self.kerning.get(&(left, right)).copied().unwrap_or(0)
+    }
+
+    // The contours of a glyph's outline in font units. Glyphs without an
+    // outline, like the space, have no contours, and so do glyphs that
+    // can't be read.
+    pub fn glyph_outline(&self, glyph: u16) -> Vec<Vec<OutlinePoint>> {
+        let mut contours = Vec::new();
+        let mut components = 0;
+        if self.read_outline(glyph, [1.0, 0.0, 0.0, 1.0, 0.0, 0.0], 0, &mut components, &mut contours).is_none() {
+            contours.clear();
+        }
+        contours
+    }
+
+    // Append the contours of glyph to contours, transformed by the matrix
+    // [a, b, c, d, e, f] which maps (x, y) to (ax + cy + e, bx + dy + f).
+    // components counts every component read so far.
+    fn read_outline(
+        &self,
+        glyph: u16,
+        matrix: [f32; 6],
+        depth: usize,
+        components: &mut usize,
+        contours: &mut Vec<Vec<OutlinePoint>>,
+    ) -> Option<()> {
+        if glyph >= self.glyph_count || depth > MAX_COMPONENT_DEPTH || *components > MAX_COMPONENTS {
+            return None;
+        }
+        let (start, end) = if self.long_loca {
+            let entry = self.loca + glyph as usize * 4;
+            (read_u32(&self.data, entry).ok()? as usize, read_u32(&self.data, entry + 4).ok()? as usize)
+        } else {
+            let entry = self.loca + glyph as usize * 2;
+            (read_u16(&self.data, entry).ok()? as usize * 2, read_u16(&self.data, entry + 2).ok()? as usize * 2)
+        };
+        if end <= start {
+            return Some(());
+        }
+        let data = self.data.get(self.glyf + start..self.glyf + end)?;
+        let transform = |x: f32, y: f32| {
+            (matrix[0] * x + matrix[2] * y + matrix[4], matrix[1] * x + matrix[3] * y + matrix[5])
+        };
+
+        let contour_count = read_u16(data, 0).ok()? as i16;
+        if contour_count >= 0 {
+            for contour in parse_simple_glyph(data, contour_count as usize)? {
+                contours.push(
+                    contour
+                        .into_iter()
+                        .map(|point| {
+                            let (x, y) = transform(point.x, point.y);
+                            OutlinePoint { x, y, on_curve: point.on_curve }
+                        })
+                        .collect(),
+                );
+            }
+            return Some(());
+        }
+
+        // A composite glyph, made of other glyphs each with their own
+        // transform
+        const ARGS_ARE_WORDS: u16 = 0x0001;
+        const ARGS_ARE_XY_VALUES: u16 = 0x0002;
+        const HAVE_A_SCALE: u16 = 0x0008;
+        const MORE_COMPONENTS: u16 = 0x0020;
+        const HAVE_XY_SCALE: u16 = 0x0040;
+        const HAVE_TWO_BY_TWO: u16 = 0x0080;
+
+        let f2dot14 = |offset: usize| Some(read_u16(data, offset).ok()? as i16 as f32 / 16384.0);
+        let mut offset = 10;
+        loop {
+            let flags = read_u16(data, offset).ok()?;
+            let component = read_u16(data, offset + 2).ok()?;
+            offset += 4;
+
+            let (arg1, arg2) = if flags & ARGS_ARE_WORDS != 0 {
+                offset += 4;
+                (read_u16(data, offset - 4).ok()? as i16 as f32, read_u16(data, offset - 2).ok()? as i16 as f32)
+            } else {
+                offset += 2;
+                (*data.get(offset - 2)? as i8 as f32, *data.get(offset - 1)? as i8 as f32)
+            };
+            // Components can also be placed by matching up points, which
+            // is rare enough that those are just left where they are
+            let (dx, dy) = if flags & ARGS_ARE_XY_VALUES != 0 { (arg1, arg2) } else { (0.0, 0.0) };
+
+            let (a, b, c, d) = if flags & HAVE_A_SCALE != 0 {
+                offset += 2;
+                let scale = f2dot14(offset - 2)?;
+                (scale, 0.0, 0.0, scale)
+            } else if flags & HAVE_XY_SCALE != 0 {
+                offset += 4;
+                (f2dot14(offset - 4)?, 0.0, 0.0, f2dot14(offset - 2)?)
+            } else if flags & HAVE_TWO_BY_TWO != 0 {
+                offset += 8;
+                (f2dot14(offset - 8)?, f2dot14(offset - 6)?, f2dot14(offset - 4)?, f2dot14(offset - 2)?)
+            } else {
+                (1.0, 0.0, 0.0, 1.0)
+            };
+
+            // The component's transform applies first, then this glyph's
+            let (ox, oy) = transform(dx, dy);
+            let combined = [
+                matrix[0] * a + matrix[2] * b,
+                matrix[1] * a + matrix[3] * b,
+                matrix[0] * c + matrix[2] * d,
+                matrix[1] * c + matrix[3] * d,
+                ox,
+                oy,
+            ];
+            *components += 1;
+            self.read_outline(component, combined, depth + 1, components, contours)?;
+
+            if flags & MORE_COMPONENTS == 0 {
+                return Some(());
+            }
+        }
+    }
+
+    // The outline of a glyph as a path on the canvas, size pixels to the em
+    // with its origin on the baseline at origin
+    pub fn glyph_path(&self, glyph: u16, size: f32, origin: (f32, f32)) -> Path {
+        let mut path = Path::new();
+        self.append_glyph_path(&mut path, glyph, size, origin);
+        path
+    }
+
+    fn append_glyph_path(&self, path: &mut Path, glyph: u16, size: f32, origin: (f32, f32)) {
+        let scale = size / self.units_per_em as f32;
+        let to_canvas = |point: OutlinePoint| (origin.0 + point.x * scale, origin.1 - point.y * scale);
+        for contour in self.glyph_outline(glyph) {
+            append_contour(path, &contour, to_canvas);
+        }
+    }
+
+    // The outlines of a line of text, size pixels to the em, starting on
+    // the baseline at origin. Glyphs are spaced by their advance widths and
+    // kerning.
+    pub fn text_path(&self, size: f32, origin: (f32, f32), text: &str) -> Path {
+        let mut path = Path::new();
+        self.layout(size, text, |glyph, x| self.append_glyph_path(&mut path, glyph, size, (origin.0 + x, origin.1)));
+        path
+    }
+
+    // How far along the baseline a line of text goes, in pixels
+    pub fn text_width(&self, size: f32, text: &str) -> f32 {
+        self.layout(size, text, |_, _| {})
+    }
+
+    // Call place with each glyph of text and how far along the line it goes
+    // in pixels, returning the total width
+    fn layout<F: FnMut(u16, f32)>(&self, size: f32, text: &str, mut place: F) -> f32 {
+        let scale = size / self.units_per_em as f32;
+        let mut x = 0.0;
+        let mut previous = None;
+        for c in text.chars() {
+            let glyph = self.glyph_index(c);
+            if let Some(previous) = previous {
+                x += self.kerning(previous, glyph) as f32 * scale;
+            }
+            place(glyph, x);
+            x += self.advance_width(glyph) as f32 * scale;
+            previous = Some(glyph);
+        }
+        x
+    }
+
+    // Rasterize a glyph with anti-aliasing, size pixels to the em with its
+    // origin on the baseline at origin. The mask covers just the pixels the
+    // outline touches, or is None if the glyph has no outline.
+    pub fn rasterize_glyph(&self, glyph: u16, size: f32, origin: (f32, f32)) -> Option<Mask> {
+        let path = self.glyph_path(glyph, size, origin);
+        let (min, max) = path.bounds()?;
+        let (x, y) = (min.0.floor() as i32, min.1.floor() as i32);
+        let width = (max.0.ceil() as i32 - x).max(1) as usize;
+        let height = (max.1.ceil() as i32 - y).max(1) as usize;
+
+        let mut rasterizer = Rasterizer::new(x, y, width, height);
+        for subpath in path.subpaths() {
+            if subpath.points.len() > 2 {
+                rasterizer.polygon(&subpath.points);
+            }
+        }
+        Some(rasterizer.into_mask(FillRule::NonZero))
+    }
+}
+
+// Add one closed contour to path. Between two control points in a row
+// there's an implied point on the curve halfway between them.
+fn append_contour<F: Fn(OutlinePoint) -> (f32, f32)>(path: &mut Path, contour: &[OutlinePoint], to_canvas: F) {
+    let (first, last) = match (contour.first(), contour.last()) {
+        (Some(&first), Some(&last)) => (first, last),
+        _ => return,
+    };
+    let midpoint = |a: (f32, f32), b: (f32, f32)| ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+
+    // Start on a point that's on the curve, making one up if need be
+    let (start, rest) = if first.on_curve {
+        (to_canvas(first), &contour[1..])
+    } else if last.on_curve {
+        (to_canvas(last), &contour[..contour.len() - 1])
+    } else {
+        (midpoint(to_canvas(first), to_canvas(last)), contour)
+    };
+
+    path.move_to(start);
+    let mut control = None;
+    let points = rest.iter().map(|&point| (to_canvas(point), point.on_curve));
+    for (p, on_curve) in points.chain(std::iter::once((start, true))) {
+        match (control, on_curve) {
+            (None, true) => {
+                path.line_to(p);
+            },
+            (Some(c), true) => {
+                path.quad_to(c, p);
+            },
+            (None, false) => {},
+            (Some(c), false) => {
+                path.quad_to(c, midpoint(c, p));
+            },
+        }
+        control = if on_curve { None } else { Some(p) };
+    }
+    path.close();
+}
+
+// The contours of a glyph made of points rather than other glyphs
+fn parse_simple_glyph(data: &[u8], contour_count: usize) -> Option<Vec<Vec<OutlinePoint>>> {
+    const ON_CURVE: u8 = 0x01;
+    const X_SHORT: u8 = 0x02;
+    const Y_SHORT: u8 = 0x04;
+    const REPEAT: u8 = 0x08;
+    const X_SAME_OR_POSITIVE: u8 = 0x10;
+    const Y_SAME_OR_POSITIVE: u8 = 0x20;
+
+    let mut ends = Vec::with_capacity(contour_count);
+    for i in 0..contour_count {
+        ends.push(read_u16(data, 10 + i * 2).ok()? as usize);
+    }
+    let point_count = match ends.last() {
+        None => return Some(Vec::new()),
+        Some(&last) => last + 1,
+    };
+
+    // Skip the hinting instructions
+    let instructions = 10 + contour_count * 2;
+    let mut offset = instructions + 2 + read_u16(data, instructions).ok()? as usize;
+
+    let mut flags = Vec::with_capacity(point_count);
+    while flags.len() < point_count {
+        let flag = *data.get(offset)?;
+        offset += 1;
+        let repeats = if flag & REPEAT != 0 {
+            offset += 1;
+            *data.get(offset - 1)? as usize
+        } else {
+            0
+        };
+        for _ in 0..=repeats {
+            flags.push(flag);
+        }
+    }
+    flags.truncate(point_count);
+
+    // Coordinates are stored as deltas from the previous point, all the x
+    // coordinates first and then all the y
+    let mut read_coordinates = |short: u8, same_or_positive: u8| {
+        let mut value = 0i32;
+        let mut coordinates = Vec::with_capacity(point_count);
+        for &flag in &flags {
+            if flag & short != 0 {
+                let delta = *data.get(offset)? as i32;
+                offset += 1;
+                value += if flag & same_or_positive != 0 { delta } else { -delta };
+            } else if flag & same_or_positive == 0 {
+                value += read_u16(data, offset).ok()? as i16 as i32;
+                offset += 2;
+            }
+            coordinates.push(value as f32);
+        }
+        Some(coordinates)
+    };
+    let xs = read_coordinates(X_SHORT, X_SAME_OR_POSITIVE)?;
+    let ys = read_coordinates(Y_SHORT, Y_SAME_OR_POSITIVE)?;
+
+    let mut contours = Vec::with_capacity(contour_count);
+    let mut start = 0;
+    for &end in &ends {
+        if end < start || end >= point_count {
+            return None;
+        }
+        contours.push(
+            (start..=end)
+                .map(|i| OutlinePoint { x: xs[i], y: ys[i], on_curve: flags[i] & ON_CURVE != 0 })
+                .collect(),
+        );
+        start = end + 1;
+    }
+    Some(contours)
+}
+
+// Read the best Unicode subtable of the cmap table
+fn parse_cmap(data: &[u8], cmap: usize) -> io::Result<HashMap<u32, u16>> {
+    let subtable_count = read_u16(data, cmap + 2)? as usize;
+    let mut best = None;
+    for i in 0..subtable_count {
+        let record = cmap + 4 + i * 8;
+        let platform = read_u16(data, record)?;
+        let encoding = read_u16(data, record + 2)?;
+        let offset = cmap + read_u32(data, record + 4)? as usize;
+        let format = read_u16(data, offset)?;
+
+        // Prefer subtables that cover all of Unicode over ones that only
+        // cover the Basic Multilingual Plane
+        let rank = match (platform, encoding, format) {
+            (0, _, 12) | (3, 10, 12) => 2,
+            (0, _, 4) | (3, 1, 4) => 1,
+            _ => continue,
+        };
+        if best.is_none_or(|(best_rank, _)| rank > best_rank) {
+            best = Some((rank, offset));
+        }
+    }
+
+    let mut map = HashMap::new();
+    match best {
+        None => return Err(invalid("no Unicode character map")),
+        Some((2, offset)) => {
+            let group_count = read_u32(data, offset + 12)? as usize;
+            let mut mapped = 0;
+            for i in 0..group_count {
+                let group = offset + 16 + i * 12;
+                let (first, last) = (read_u32(data, group)?, read_u32(data, group + 4)?);
+                let first_glyph = read_u32(data, group + 8)?;
+                let last = last.min(char::MAX as u32);
+
+                // Groups running past the last glyph a font can have are
+                // broken, and skipped
+                let last_glyph = last.checked_sub(first).and_then(|length| first_glyph.checked_add(length));
+                if last_glyph.is_none_or(|glyph| glyph > u16::MAX as u32) {
+                    continue;
+                }
+                mapped += (last - first) as usize + 1;
+                if mapped > MAX_MAPPED_CHARACTERS {
+                    return Err(invalid("character map covers too many characters"));
+                }
+                for c in first..=last {
+                    map.insert(c, (first_glyph + (c - first)) as u16);
+                }
+            }
+        },
+        Some((_, offset)) => {
+            let segment_count = read_u16(data, offset + 6)? as usize / 2;
+            let ends = offset + 14;
+            let starts = ends + segment_count * 2 + 2;
+            let deltas = starts + segment_count * 2;
+            let range_offsets = deltas + segment_count * 2;
+            let mut mapped = 0;
+            for i in 0..segment_count {
+                let (first, last) = (read_u16(data, starts + i * 2)?, read_u16(data, ends + i * 2)?);
+                mapped += (last as usize + 1).saturating_sub(first as usize);
+                if mapped > MAX_MAPPED_CHARACTERS {
+                    return Err(invalid("character map covers too many characters"));
+                }
+                let delta = read_u16(data, deltas + i * 2)?;
+                let range_offset = read_u16(data, range_offsets + i * 2)? as usize;
+                for c in first..=last {
+                    if c == 0xFFFF {
+                        break;
+                    }
+                    // Glyphs are either the character offset by delta, or
+                    // looked up in an array found relative to where the
+                    // range offset itself is stored
+                    let glyph = if range_offset == 0 {
+                        c.wrapping_add(delta)
+                    } else {
+                        let address = range_offsets + i * 2 + range_offset + (c - first) as usize * 2;
+                        match read_u16(data, address)? {
+                            0 => 0,
+                            glyph => glyph.wrapping_add(delta),
+                        }
+                    };
+                    if glyph != 0 {
+                        map.insert(c as u32, glyph);
+                    }
+                }
+            }
+        },
+    }
+    Ok(map)
+}
+
+// Read the horizontal kerning pairs of an old style kern table. Fonts that
+// only kern with GPOS get no kerning.
+fn parse_kern(data: &[u8], kern: usize) -> io::Result<HashMap<(u16, u16), i16>> {
+    let mut pairs = HashMap::new();
+    // Only Microsoft's version 0 layout is understood, not Apple's
+    if read_u16(data, kern)? != 0 {
+        return Ok(pairs);
+    }
+
+    let subtable_count = read_u16(data, kern + 2)?;
+    let mut offset = kern + 4;
+    for _ in 0..subtable_count {
+        let length = read_u16(data, offset + 2)? as usize;
+        let coverage = read_u16(data, offset + 4)?;
+        // Horizontal, format 0 pairs that aren't minimums or cross-stream
+        if coverage & 0xFF07 == 0x0001 {
+            let pair_count = read_u16(data, offset + 6)? as usize;
+            for i in 0..pair_count {
+                let pair = offset + 14 + i * 6;
+                let key = (read_u16(data, pair)?, read_u16(data, pair + 2)?);
+                let value = read_u16(data, pair + 4)? as i16;
+                // Pairs kerned by more than one subtable add up
+                let total = pairs.entry(key).or_insert(0i16);
+                *total = total.saturating_add(value);
+            }
+        }
+        offset += length;
+    }
+    Ok(pairs)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Font files are big endian
+fn read_u16(data: &[u8], offset: usize) -> io::Result<u16> {
+    match data.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+        None => Err(invalid("unexpected end of font data")),
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> io::Result<u32> {
+    match data.get(offset..offset + 4) {
+        Some(bytes) => Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+        None => Err(invalid("unexpected end of font data")),
+    }
+}
+
+impl Canvas {
+    // Fill a line of text in a font, size pixels to the em, starting on the
+    // baseline at origin. Like the other filled shapes, the text goes
+    // through the canvas transform.
+    pub fn fill_text(
+        &mut self,
+        (r, g, b): (f32, f32, f32),
+        blend: BlendMode,
+        font: &Font,
+        origin: (f32, f32),
+        size: f32,
+        text: &str,
+    ) {
+        let path = font.text_path(size, origin, text);
+        self.fill_path((r, g, b, 1.0), blend, &path, FillRule::NonZero);
+    }
+}
+
+#[test]
+fn test_font() {
+    // A font with a square glyph for 'A', and a composite glyph for 'B'
+    // that's the same square moved right by 100 units
+    let be16 = |bytes: &mut Vec<u8>, values: &[i32]| {
+        for &value in values {
+            bytes.extend_from_slice(&(value as u16).to_be_bytes());
+        }
+    };
+
+    let mut head = vec![0; 54];
+    head[18..20].copy_from_slice(&1000u16.to_be_bytes());
+    let mut hhea = vec![0; 36];
+    hhea[4..10].copy_from_slice(&[800u16.to_be_bytes(), (-200i16 as u16).to_be_bytes(), [0, 0]].concat());
+    hhea[34..36].copy_from_slice(&3u16.to_be_bytes());
+    let mut maxp = vec![0, 0, 0x50, 0];
+    be16(&mut maxp, &[3]);
+    let mut hmtx = Vec::new();
+    be16(&mut hmtx, &[500, 0, 700, 100, 800, 200]);
+
+    // Format 4, with the segment 'A'..='B' mapped to glyphs 1 and 2
+    let mut cmap = Vec::new();
+    be16(&mut cmap, &[0, 1, 3, 1, 0, 12]);
+    be16(&mut cmap, &[4, 32, 0, 4, 0, 0, 0]);
+    be16(&mut cmap, &[0x42, 0xFFFF, 0, 0x41, 0xFFFF, 1 - 0x41, 1, 0, 0]);
+
+    // The square has a control point in the middle of its top edge, so it
+    // stays square
+    let mut square = Vec::new();
+    be16(&mut square, &[1, 100, 100, 600, 600, 4, 0]);
+    square.extend_from_slice(&[1, 1, 1, 0, 1]);
+    be16(&mut square, &[100, 500, 0, -250, -250, 100, 0, 500, 0, 0]);
+    square.push(0);
+    let mut composite = Vec::new();
+    be16(&mut composite, &[-1, 200, 100, 700, 600, 0x0003, 1, 100, 0]);
+    let mut loca = Vec::new();
+    be16(&mut loca, &[0, 0, square.len() as i32 / 2, (square.len() + composite.len()) as i32 / 2]);
+    let glyf = [square.clone(), composite].concat();
+
+    let mut kern = Vec::new();
+    be16(&mut kern, &[0, 1, 0, 20, 0x0001, 1, 0, 0, 0, 1, 2, -50]);
+
+    let tables: Vec<(&[u8; 4], Vec<u8>)> = vec![
+        (b"cmap", cmap), (b"glyf", glyf), (b"head", head), (b"hhea", hhea),
+        (b"hmtx", hmtx), (b"kern", kern), (b"loca", loca), (b"maxp", maxp),
+    ];
+    let build = |tables: &[(&[u8; 4], Vec<u8>)]| {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+        be16(&mut data, &[tables.len() as i32, 0, 0, 0]);
+        let mut offset = 12 + tables.len() * 16;
+        for (tag, table) in tables {
+            data.extend_from_slice(&tag[..]);
+            data.extend_from_slice(&[0; 4]);
+            data.extend_from_slice(&(offset as u32).to_be_bytes());
+            data.extend_from_slice(&(table.len() as u32).to_be_bytes());
+            offset += table.len().div_ceil(4) * 4;
+        }
+        for (_, table) in tables {
+            data.extend_from_slice(table);
+            data.resize(data.len().div_ceil(4) * 4, 0);
+        }
+        data
+    };
+    let data = build(&tables);
+
+    assert!(Font::from_bytes(b"OTTO0000".to_vec()).is_err());
+    assert!(Font::from_bytes(data[..100].to_vec()).is_err());
+    let font = Font::from_bytes(data).unwrap();
+
+    assert_eq!((font.units_per_em(), font.ascender(), font.descender()), (1000, 800, -200));
+    assert_eq!((font.glyph_index('A'), font.glyph_index('B'), font.glyph_index('C')), (1, 2, 0));
+    assert_eq!((font.advance_width(1), font.left_side_bearing(2)), (700, 200));
+    assert_eq!((font.kerning(1, 2), font.kerning(2, 1)), (-50, 0));
+    assert_eq!(font.glyph_outline(1).len(), 1);
+    assert_eq!(font.glyph_outline(2)[0][0], OutlinePoint { x: 200.0, y: 100.0, on_curve: true });
+    assert!(font.glyph_outline(0).is_empty());
+
+    // At 100 pixels to the em the square covers 10..60 across and 10..60
+    // above the baseline. The next glyph starts 70 pixels along, less 5 for
+    // kerning, and its square is another 10 pixels further on.
+    assert_eq!(font.text_width(100.0, "AB"), 145.0);
+    let mask = font.rasterize_glyph(1, 100.0, (0.0, 80.0)).unwrap();
+    assert_eq!((mask.origin(), mask.width(), mask.height()), ((10, 20), 50, 50));
+    assert_eq!(mask.get(30, 40), 1.0);
+
+    let mut canvas = Canvas::new(150, 100);
+    canvas.clear((0.0, 0.0, 0.0, 1.0));
+    canvas.fill_text((1.0, 1.0, 1.0), BlendMode::SourceOver, &font, (0.0, 80.0), 100.0, "AB");
+    let value = |x, y| canvas.get_pixel(x, y).unwrap().0;
+    assert_eq!((value(30, 40), value(30, 20), value(30, 69)), (1.0, 1.0, 1.0));
+    assert_eq!((value(5, 40), value(30, 19), value(30, 70), value(80, 40)), (0.0, 0.0, 0.0, 0.0));
+    assert_eq!((value(85, 40), value(134, 40), value(135, 40)), (1.0, 1.0, 0.0));
+
+    // Malformed fonts are read without panicking or taking forever. Format
+    // 12 character map groups whose glyphs run past 0xFFFF, or that end
+    // before they start, are skipped.
+    let format_12 = |groups: &[(u32, u32, u32)]| {
+        let mut cmap = Vec::new();
+        be16(&mut cmap, &[0, 1, 3, 10, 0, 12, 12, 0]);
+        cmap.extend_from_slice(&(16 + groups.len() as u32 * 12).to_be_bytes());
+        cmap.extend_from_slice(&0u32.to_be_bytes());
+        cmap.extend_from_slice(&(groups.len() as u32).to_be_bytes());
+        for &(first, last, glyph) in groups {
+            for value in [first, last, glyph] {
+                cmap.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        cmap
+    };
+    let mut malformed = tables.clone();
+    malformed[0].1 = format_12(&[(0x41, 0x41, 1), (0x42, 0x42, 0xFFFF_FFFF), (0x5A, 0x59, 1), (0, 0x10FFFF, 0)]);
+
+    // A composite glyph made of itself eight times over
+    let mut bomb = Vec::new();
+    be16(&mut bomb, &[-1, 0, 0, 0, 0]);
+    for i in 0..8 {
+        be16(&mut bomb, &[if i < 7 { 0x0022 } else { 0x0002 }, 2, 0]);
+    }
+    let mut loca = Vec::new();
+    be16(&mut loca, &[0, 0, square.len() as i32 / 2, (square.len() + bomb.len()) as i32 / 2]);
+    malformed[1].1 = [square, bomb].concat();
+    malformed[6].1 = loca;
+
+    let font = Font::from_bytes(build(&malformed)).unwrap();
+    assert_eq!((font.glyph_index('A'), font.glyph_index('B'), font.glyph_index('Y'), font.glyph_index('C')), (1, 0, 0, 0));
+    assert_eq!(font.glyph_outline(1).len(), 1);
+    assert!(font.glyph_outline(2).is_empty());
+
+    // Groups or segments that map more characters than any real font are an
+    // error
+    malformed[0].1 = format_12(&[(0, 0xFFFF, 0); 5]);
+    assert!(Font::from_bytes(build(&malformed)).is_err());
+    let mut cmap = Vec::new();
+    be16(&mut cmap, &[0, 1, 3, 1, 0, 12]);
+    be16(&mut cmap, &[4, 14 + 5 * 8 + 2, 0, 5 * 2, 0, 0, 0]);
+    be16(&mut cmap, &[0xFFFE; 5]);
+    be16(&mut cmap, &[0; 1 + 5 * 3]);
+    malformed[0].1 = cmap;
+    assert!(Font::from_bytes(build(&malformed)).is_err());
+}
@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+
+use crate::{Canvas, TextSpacing, for_each_text_stroke, layout_text, text_outline, text_stroke_style};
+use crate::composite::BlendMode;
+use crate::raster::Mask;
+
+// Something hashable that's different for every text style that looks
+// different
+type StyleKey = (u32, Option<u32>);
+
+// The size and look of text in the stroke font
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextStyle {
+    // Height of a capital letter in pixels
+    pub size: f32,
+    // Width of the strokes in pixels like draw_text_stroked, or None for
+    // the thin lines of draw_text
+    pub stroke_width: Option<f32>,
+    pub spacing: TextSpacing,
+}
+
+impl TextStyle {
+    pub fn new(size: f32) -> TextStyle {
+        TextStyle { size, stroke_width: None, spacing: TextSpacing::Monospace }
+    }
+
+    pub fn stroked(size: f32, stroke_width: f32) -> TextStyle {
+        TextStyle { size, stroke_width: Some(stroke_width), spacing: TextSpacing::Monospace }
+    }
+
+    // Spacing only moves glyphs around, so it's left out of the key
+    fn key(self) -> StyleKey {
+        (self.size.to_bits(), self.stroke_width.map(f32::to_bits))
+    }
+}
+
+// Stroke font glyphs rasterized into coverage masks, so text drawn over
+// and over again only has its strokes worked out once per character and
+// style. Glyphs are rasterized the first time they're drawn and kept until
+// the cache is cleared.
+#[derive(Debug, Default)]
+pub struct GlyphCache {
+    // None for characters with nothing to draw, like the space
+    glyphs: HashMap<(char, StyleKey), Option<Mask>>,
+}
+
+impl GlyphCache {
+    pub fn new() -> GlyphCache {
+        GlyphCache {
+            glyphs: HashMap::new(),
+        }
+    }
+
+    // How many glyphs have been rasterized
+    pub fn len(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.glyphs.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.glyphs.clear();
+    }
+
+    // The coverage of a character drawn with its origin at (0, 0), or None
+    // if there's nothing to draw
+    pub fn glyph(&mut self, c: char, style: TextStyle) -> Option<&Mask> {
+        self.glyphs
+            .entry((c, style.key()))
+            .or_insert_with(|| rasterize(c, style))
+            .as_ref()
+    }
+}
+
+// Draw a character white on a black canvas just big enough to hold it, the
+// same way the uncached text functions would, and keep how bright each
+// pixel ends up as its coverage
+fn rasterize(c: char, style: TextStyle) -> Option<Mask> {
+    let mut text = [0; 4];
+    let text = c.encode_utf8(&mut text);
+    let style = TextStyle { spacing: TextSpacing::Monospace, ..style };
+
+    let mut bounds: Option<((f32, f32), (f32, f32))> = None;
+    for_each_text_stroke(style.size, style.spacing, text, |p0, p1| {
+        for &(x, y) in &[p0, p1] {
+            bounds = Some(match bounds {
+                None => ((x, y), (x, y)),
+                Some((min, max)) => ((min.0.min(x), min.1.min(y)), (max.0.max(x), max.1.max(y))),
+            });
+        }
+    });
+    let (min, max) = bounds?;
+
+    // Room for anti-aliasing, and for wide strokes sticking out past the
+    // lines they follow
+    let margin = 2.0 + style.stroke_width.map_or(0.0, |width| text_stroke_style(width).reach());
+    let x = (min.0 - margin).floor() as i32;
+    let y = (min.1 - margin).floor() as i32;
+    let width = ((max.0 + margin).ceil() as i32 - x) as usize;
+    let height = ((max.1 + margin).ceil() as i32 - y) as usize;
+
+    let mut canvas = Canvas::new(width, height);
+    draw_uncached(&mut canvas, (1.0, 1.0, 1.0), BlendMode::SourceOver, (-x, -y), style, text);
+
+    // Keep just the pixels that were drawn on, since every pixel in the
+    // mask is visited each time the glyph is drawn
+    let drawn = |i: usize| canvas.pixels()[i].0 > 0.0;
+    let columns: Vec<usize> = (0..width).filter(|&cx| (0..height).any(|cy| drawn(cx + cy * width))).collect();
+    let rows: Vec<usize> = (0..height).filter(|&cy| (0..width).any(|cx| drawn(cx + cy * width))).collect();
+    let (left, right) = (*columns.first()?, *columns.last()?);
+    let (top, bottom) = (*rows.first()?, *rows.last()?);
+
+    let mut mask = Mask::new(x + left as i32, y + top as i32, right - left + 1, bottom - top + 1);
+    for cy in top..=bottom {
+        for cx in left..=right {
+            mask.cover(x + cx as i32, y + cy as i32, canvas.pixels()[cx + cy * width].0);
+        }
+    }
+    Some(mask)
+}
+
+fn draw_uncached(
+    canvas: &mut Canvas,
+    color: (f32, f32, f32),
+    blend: BlendMode,
+    origin: (i32, i32),
+    style: TextStyle,
+    text: &str,
+) {
+    match style.stroke_width {
+        None => canvas.draw_text(color, blend, origin, style.size, style.spacing, text),
+        Some(width) => {
+            let origin = (origin.0 as f32, origin.1 as f32);
+            let outline = text_outline(style.size, style.spacing, origin, text);
+            canvas.stroke_path((color.0, color.1, color.2, 1.0), blend, &outline, &text_stroke_style(width));
+        },
+    }
+}
+
+impl Canvas {
+    // Draw text in the stroke font from glyphs kept in cache. It looks the
+    // same as draw_text, or draw_text_stroked for stroked styles, but is
+    // much faster for text that's drawn again and again. Each glyph is
+    // blended as a whole, so blend modes like Add don't brighten the
+    // corners where strokes meet.
+    // Cached glyphs only line up with whole pixels, so when the canvas is
+    // transformed the text is drawn without the cache.
+    pub fn draw_text_cached(
+        &mut self,
+        cache: &mut GlyphCache,
+        (r, g, b): (f32, f32, f32),
+        blend: BlendMode,
+        origin: (i32, i32),
+        style: TextStyle,
+        text: &str,
+    ) {
+        if !self.transform().is_identity() {
+            draw_uncached(self, (r, g, b), blend, origin, style, text);
+            return;
+        }
+
+        layout_text(style.size, style.spacing, text, |c, x| {
+            if let Some(mask) = cache.glyph(c, style) {
+                self.fill_mask_at((r, g, b, 1.0), blend, mask, (origin.0 + x, origin.1));
+            }
+        });
+    }
+}
+
+#[test]
+fn test_glyph_cache() {
+    let mut cache = GlyphCache::new();
+    assert!(cache.glyph(' ', TextStyle::new(20.0)).is_none());
+    assert!(cache.glyph('A', TextStyle::new(20.0)).is_some());
+    cache.glyph('A', TextStyle::new(20.0));
+    cache.glyph('A', TextStyle::stroked(20.0, 2.0));
+    cache.glyph('A', TextStyle::new(21.0));
+    assert_eq!(cache.len(), 4);
+
+    // Cached text looks just like uncached text, whether it's drawn in thin
+    // lines or thick strokes with miters that stick out, and however it's
+    // spaced
+    let text = "Hello, 123 {g}";
+    let proportional = TextStyle { spacing: TextSpacing::Proportional, ..TextStyle::stroked(30.0, 3.0) };
+    for &style in &[TextStyle::new(30.0), TextStyle::stroked(30.0, 3.0), proportional] {
+        let mut expected = Canvas::new(400, 60);
+        let mut cached = Canvas::new(400, 60);
+        expected.clear((0.1, 0.2, 0.3, 1.0));
+        cached.clear((0.1, 0.2, 0.3, 1.0));
+        draw_uncached(&mut expected, (1.0, 0.5, 0.0), BlendMode::SourceOver, (10, 10), style, text);
+        cached.draw_text_cached(&mut cache, (1.0, 0.5, 0.0), BlendMode::SourceOver, (10, 10), style, text);
+        for (a, b) in expected.pixels().iter().zip(cached.pixels()) {
+            assert!((a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4 && (a.2 - b.2).abs() < 1e-4);
+        }
+    }
+
+    cache.clear();
+    assert!(cache.is_empty());
+}
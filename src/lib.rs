@@ -1,4 +1,29 @@
-use rayon::prelude::*;
+use composite::BlendMode;
+use std::sync::OnceLock;
+
+pub mod animation;
+pub mod bezier;
+pub mod bloom;
+pub mod color;
+pub mod composite;
+pub mod convolve;
+pub mod depth;
+pub mod dither;
+pub mod effect;
+pub mod ellipse;
+pub mod fill;
+pub mod font;
+pub mod glyph_cache;
+pub mod math;
+pub mod path;
+pub mod png;
+pub mod raster;
+pub mod stroke;
+pub mod text;
+pub mod tonemap;
+pub mod transform;
+pub mod wireframe;
+pub mod y4m;
 
 pub fn clamp<T: PartialOrd>(value: T, low: T, high: T) -> T {
     if value < low {
@@ -174,200 +199,731 @@ pub fn line_segment_in_rect(
     }
 }
 
-pub fn wu_line(
-    (r,g,b,_): (f32,f32,f32,f32),
-    (x0, y0): (i32, i32),
-    (x1, y1): (i32, i32),
+// The pixels Xiaolin Wu's algorithm covers for a line between end points in
+// pixels, as in Canvas::wu_line_f. plot is called with each pixel inside a
+// width x height canvas and how much of it the line covers.
+pub(crate) fn wu_line_coverage<F: FnMut(i32, i32, f32)>(
     width: usize,
-    buffer: &mut Vec<(f32,f32,f32,f32)>,
+    height: usize,
+    p0: (f32, f32),
+    p1: (f32, f32),
+    mut plot_pixel: F,
 ) {
-    let height = buffer.len() / width;
-
-    let ((x0, y0), (x1, y1)) = match line_segment_in_rect(
-        (x0, y0),
-        (x1, y1),
-        width,
-        height
+    // Anything more than a couple of pixels outside the canvas can't
+    // affect it, so throw that part of the line away first.
+    let ((x0, y0), (x1, y1)) = match clip_line_to_rect(
+        p0,
+        p1,
+        (-2.0, -2.0),
+        (width as f32 + 2.0, height as f32 + 2.0),
     ) {
         None => return,
         Some(segment) => segment
     };
 
-    // These checks will only fail if line_segment_in_rect
-    // was implemented improperly. Once more confidence has
-    // been built up the it _has_ been implemented properly,
-    // then this check could be removed.
-    if !in_range(x0, 0, width as i32 - 1) {
-        panic!("x0 not in range {}", x0);
+    // Move to coordinates where pixel centres are on whole numbers,
+    // which is what the algorithm is written in terms of
+    let (x0, y0, x1, y1) = (x0 - 0.5, y0 - 0.5, x1 - 0.5, y1 - 0.5);
+
+    // Walk along the major axis. For Y-major lines we swap x and y here,
+    // and swap them back when plotting.
+    let steep = (y1 - y0).abs() > (x1 - x0).abs();
+    let (x0, y0, x1, y1) = if steep {
+        (y0, x0, y1, x1)
+    } else {
+        (x0, y0, x1, y1)
     };
-    if !in_range(x1, 0, width as i32 - 1) {
-        panic!("x1 not in range {}", x1);
+    let (x0, y0, x1, y1) = if x0 > x1 {
+        (x1, y1, x0, y0)
+    } else {
+        (x0, y0, x1, y1)
     };
-    if !in_range(y0, 0, height as i32 - 1) {
-        panic!("y0 not in range {}", y0);
+
+    let mut plot = |major: i32, minor: i32, coverage: f32| {
+        let (x, y) = if steep { (minor, major) } else { (major, minor) };
+        if coverage > 0.0 && x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height {
+            plot_pixel(x, y, coverage);
+        }
     };
-    if !in_range(y1, 0, height as i32 - 1) {
-        panic!("y1 not in range {}", y1);
+
+    let dx = x1 - x0;
+    let dy = y1 - y0;
+    let gradient = if dx == 0.0 { 0.0 } else { dy / dx };
+
+    // How far along the minor axis the line is at a point on the major
+    // axis, split into a pixel and the fraction of the way into it
+    let minor_at = |x: f32| {
+        let y = y0 + gradient * (x - x0);
+        (y.floor() as i32, y - y.floor())
     };
 
-    // Update the second point to ensure it's within bounds
+    let first = x0.round();
+    let last = x1.round();
+
+    // Very short lines start and end in the same pixel, and should only
+    // be drawn as bright as they are long
+    if first == last {
+        let (y, frac) = minor_at((x0 + x1) / 2.0);
+        plot(first as i32, y, (1.0 - frac) * dx);
+        plot(first as i32, y + 1, frac * dx);
+        return;
+    }
+
+    // End points are only drawn as much as the line covers their pixels
+    // along the major axis
+    let first_gap = 1.0 - (x0 + 0.5 - first);
+    let (y, frac) = minor_at(first);
+    plot(first as i32, y, (1.0 - frac) * first_gap);
+    plot(first as i32, y + 1, frac * first_gap);
+
+    let last_gap = x1 + 0.5 - last;
+    let (y, frac) = minor_at(last);
+    plot(last as i32, y, (1.0 - frac) * last_gap);
+    plot(last as i32, y + 1, frac * last_gap);
+
+    for x in (first as i32 + 1)..(last as i32) {
+        let (y, frac) = minor_at(x as f32);
+        plot(x, y, 1.0 - frac);
+        plot(x, y + 1, frac);
+    }
+}
+
+// Floating point version of line_segment_in_rect: clip the segment from
+// p0 to p1 to the rectangle from min to max (inclusive) using the
+// Liang-Barsky algorithm. If the line segment is completely outside the
+// rectangle, None is returned.
+pub fn clip_line_to_rect(
+        (x0, y0): (f32, f32),
+        (x1, y1): (f32, f32),
+        (min_x, min_y): (f32, f32),
+        (max_x, max_y): (f32, f32),
+) -> Option<((f32, f32), (f32, f32))> {
+    if !(x0.is_finite() && y0.is_finite() && x1.is_finite() && y1.is_finite()) {
+        return None;
+    }
 
     let dx = x1 - x0;
     let dy = y1 - y0;
 
-    // Vertical line
-    if dx == 0 {
-        for y in y0.min(y1)..=y0.max(y1) {
-            set_pixel((r,g,b,1.0), x0, y, width, buffer);
+    // The segment is p0 + t*(p1 - p0) for t in [t0, t1]. Each edge of the
+    // rectangle can only shrink that range.
+    let mut t0: f32 = 0.0;
+    let mut t1: f32 = 1.0;
+    for &(p, q) in &[
+        (-dx, x0 - min_x),
+        (dx, max_x - x0),
+        (-dy, y0 - min_y),
+        (dy, max_y - y0),
+    ] {
+        if p == 0.0 {
+            // Parallel to this edge, so it's either all in or all out
+            if q < 0.0 {
+                return None;
+            }
+        } else {
+            let t = q / p;
+            if p < 0.0 {
+                t0 = t0.max(t);
+            } else {
+                t1 = t1.min(t);
+            }
         }
     }
 
-    // Horizontal line
-    else if dy == 0 {
-        let start_index = coord_to_index(x0.min(x1) as usize, y0 as usize, width);
-        let end_index = coord_to_index(x0.max(x1) as usize, y0 as usize, width);
-        for x in buffer[start_index..=end_index].iter_mut() {
-            *x = (r,g,b,1.0);
+    if t0 > t1 {
+        return None;
+    }
+
+    Some((
+        (x0 + t0 * dx, y0 + t0 * dy),
+        (x0 + t1 * dx, y0 + t1 * dy),
+    ))
+}
+
+// The sRGB transfer function, see color::TransferFunction for others
+pub fn linear_to_srgb(x: f32) -> f32 {
+    color::TransferFunction::Srgb.encode(x)
+}
+
+pub fn srgb_to_linear(x: f32) -> f32 {
+    color::TransferFunction::Srgb.decode(x)
+}
+
+pub fn interp(t: f32, x0: u32, x1: u32) -> u32 {
+    ((1.0 - t) * x0 as f32 + t * x1 as f32).round() as u32
+}
+
+pub fn interpf(t: f32, x0: f32, x1: f32) -> f32 {
+    (1.0 - t) * x0 + t * x1
+}
+
+pub fn coord_to_index(x: usize, y: usize, width: usize) -> usize {
+    x + y*width
+}
+
+// Encode linear pixels as sRGB 0x00RRGGBB. Values outside 0.0..=1.0 are
+// clamped.
+pub fn gamma_correct_buffer(in_buffer: &[(f32,f32,f32,f32)], out_buffer: &mut Vec<u32>) {
+    // The table is built the first time it's needed rather than every frame
+    static TABLE: OnceLock<color::EncodingTable> = OnceLock::new();
+    let table = TABLE.get_or_init(|| color::EncodingTable::new(color::TransferFunction::Srgb));
+    color::encode_buffer(in_buffer, out_buffer, table);
+}
+
+// A frame of linear RGBA pixels along with its dimensions. Every drawing
+// operation goes through a Canvas so the width used to index the pixels
+// can never disagree with the width the pixels were allocated with.
+pub struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<(f32,f32,f32,f32)>,
+    transform: transform::TransformStack,
+    // Distance to the nearest surface drawn into each pixel, empty when
+    // there's no depth buffer. See the depth module.
+    depth: Vec<f32>,
+}
+
+impl Canvas {
+    pub fn new(width: usize, height: usize) -> Canvas {
+        Canvas {
+            width,
+            height,
+            pixels: vec![(0.0, 0.0, 0.0, 1.0); width * height],
+            transform: transform::TransformStack::new(),
+            depth: Vec::new(),
         }
     }
 
-    // Special case diagonal lines since they are common and
-    // don't need anti-aliasing
-    else if dx.abs() == dy.abs() {
-        let xdir = dx.signum();
-        let ydir = dy.signum();
-        for i in 0..=dx.abs() {
-            set_pixel((r,g,b,1.0), i*xdir + x0, i*ydir + y0, width, buffer);
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[(f32,f32,f32,f32)] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [(f32,f32,f32,f32)] {
+        &mut self.pixels
+    }
+
+    pub fn get_pixel(&self, x: i32, y: i32) -> Option<(f32,f32,f32,f32)> {
+        if in_range(x, 0, self.width as i32 - 1) && in_range(y, 0, self.height as i32 - 1) {
+            Some(self.pixels[coord_to_index(x as usize, y as usize, self.width)])
+        } else {
+            None
         }
     }
 
-    // X-major
-    else if dx.abs() > dy.abs() {
-        // We already know that dx is non-zero
-        let error_step = (dy as f32 / dx as f32).abs();
-        let xdir = dx.signum();
-        let ydir = dy.signum();
-
-        let mut error = 0.0;
-        let mut y = y0;
-        let mut x = x0;
-        while x != x1 {
-            set_pixel((r,g,b,error), x, y+ydir, width, buffer);
-            set_pixel((r,g,b,1.0-error), x, y, width, buffer);
-            error += error_step;
-            if error >= 1.0 {
-                y += ydir;
-                error -= 1.0;
+    // Fill the whole canvas with a straight alpha color
+    pub fn clear(&mut self, color: (f32,f32,f32,f32)) {
+        let color = composite::premultiply(color);
+        for p in self.pixels.iter_mut() {
+            *p = color;
+        }
+    }
+
+    // Composite a straight alpha color onto a pixel. Pixels are stored with
+    // premultiplied alpha, see the composite module.
+    pub fn set_pixel(&mut self, color: (f32,f32,f32,f32), blend: BlendMode, x: i32, y: i32) {
+        self.blend_pixel(color, blend, 1.0, x, y);
+    }
+
+    pub fn gamma_correct(&self, out_buffer: &mut Vec<u32>) {
+        gamma_correct_buffer(&self.pixels, out_buffer);
+    }
+
+    pub fn wu_line(
+        &mut self,
+        (r,g,b,_): (f32,f32,f32,f32),
+        blend: BlendMode,
+        (x0, y0): (i32, i32),
+        (x1, y1): (i32, i32),
+    ) {
+        let width = self.width;
+        let height = self.height;
+
+        let ((x0, y0), (x1, y1)) = match line_segment_in_rect(
+            (x0, y0),
+            (x1, y1),
+            width,
+            height
+        ) {
+            None => return,
+            Some(segment) => segment
+        };
+
+        // These checks will only fail if line_segment_in_rect
+        // was implemented improperly. Once more confidence has
+        // been built up the it _has_ been implemented properly,
+        // then this check could be removed.
+        if !in_range(x0, 0, width as i32 - 1) {
+            panic!("x0 not in range {}", x0);
+        };
+        if !in_range(x1, 0, width as i32 - 1) {
+            panic!("x1 not in range {}", x1);
+        };
+        if !in_range(y0, 0, height as i32 - 1) {
+            panic!("y0 not in range {}", y0);
+        };
+        if !in_range(y1, 0, height as i32 - 1) {
+            panic!("y1 not in range {}", y1);
+        };
+
+        // Update the second point to ensure it's within bounds
+
+        let dx = x1 - x0;
+        let dy = y1 - y0;
+
+        // Vertical line
+        if dx == 0 {
+            for y in y0.min(y1)..=y0.max(y1) {
+                self.set_pixel((r,g,b,1.0), blend, x0, y);
             }
+        }
 
-            x += xdir;
+        // Horizontal line
+        else if dy == 0 {
+            for x in x0.min(x1)..=x0.max(x1) {
+                self.set_pixel((r,g,b,1.0), blend, x, y0);
+            }
         }
-    }
 
-    // Y-major
-    else {
-        // We already know that dx is non-zero
-        let error_step = (dx as f32 / dy as f32).abs();
-        let xdir = dx.signum();
-        let ydir = dy.signum();
-
-        let mut error = 0.0;
-        let mut y = y0;
-        let mut x = x0;
-        while y != y1 {
-            set_pixel((r,g,b,error), x+xdir, y, width, buffer);
-            set_pixel((r,g,b,1.0-error), x, y, width, buffer);
-            error += error_step;
-            if error >= 1.0 {
+        // Special case diagonal lines since they are common and
+        // don't need anti-aliasing
+        else if dx.abs() == dy.abs() {
+            let xdir = dx.signum();
+            let ydir = dy.signum();
+            for i in 0..=dx.abs() {
+                self.set_pixel((r,g,b,1.0), blend, i*xdir + x0, i*ydir + y0);
+            }
+        }
+
+        // X-major
+        else if dx.abs() > dy.abs() {
+            // We already know that dx is non-zero
+            let error_step = (dy as f32 / dx as f32).abs();
+            let xdir = dx.signum();
+            let ydir = dy.signum();
+
+            let mut error = 0.0;
+            let mut y = y0;
+            let mut x = x0;
+            while x != x1 {
+                self.blend_pixel((r,g,b,1.0), blend, error, x, y+ydir);
+                self.blend_pixel((r,g,b,1.0), blend, 1.0-error, x, y);
+                error += error_step;
+                if error >= 1.0 {
+                    y += ydir;
+                    error -= 1.0;
+                }
+
                 x += xdir;
-                error -= 1.0;
+            }
+        }
+
+        // Y-major
+        else {
+            // We already know that dx is non-zero
+            let error_step = (dx as f32 / dy as f32).abs();
+            let xdir = dx.signum();
+            let ydir = dy.signum();
+
+            let mut error = 0.0;
+            let mut y = y0;
+            let mut x = x0;
+            while y != y1 {
+                self.blend_pixel((r,g,b,1.0), blend, error, x+xdir, y);
+                self.blend_pixel((r,g,b,1.0), blend, 1.0-error, x, y);
+                error += error_step;
+                if error >= 1.0 {
+                    x += xdir;
+                    error -= 1.0;
+                }
+
+                y += ydir;
             }
 
-            y += ydir;
         }
 
+        self.set_pixel((r,g,b,1.0), blend, x1, y1);
     }
 
-    set_pixel((r,g,b,1.0), x1, y1, width, buffer);
+    // Xiaolin Wu's line algorithm for end points with subpixel precision.
+    // Coordinates are in pixels where (0.0, 0.0) is the top left corner of
+    // the top left pixel, so pixel (x, y) is centred on (x + 0.5, y + 0.5).
+    // Unlike wu_line the end points are anti-aliased as well, so lines move
+    // smoothly when animated by fractions of a pixel. The end points go
+    // through the canvas transform first, see the transform module.
+    pub fn wu_line_f(
+        &mut self,
+        color: (f32,f32,f32,f32),
+        blend: BlendMode,
+        p0: (f32, f32),
+        p1: (f32, f32),
+    ) {
+        if let Some((p0, p1)) = self.transform.project_line(p0, p1) {
+            self.wu_line_pixels(color, blend, p0, p1);
+        }
+    }
+
+    // wu_line_f for end points already in pixels
+    fn wu_line_pixels(
+        &mut self,
+        color: (f32,f32,f32,f32),
+        blend: BlendMode,
+        p0: (f32, f32),
+        p1: (f32, f32),
+    ) {
+        let (width, height) = (self.width, self.height);
+        wu_line_coverage(width, height, p0, p1, |x, y, coverage| {
+            self.blend_pixel(color, blend, coverage, x, y);
+        });
+    }
+
+    pub fn draw_text(
+        &mut self,
+        (r, g, b): (f32, f32, f32),
+        blend: BlendMode,
+        origin: (i32, i32),
+        size: f32,
+        spacing: TextSpacing,
+        text: &str,
+    ) {
+        // Text is snapped to whole pixels to keep it crisp, unless it's
+        // being transformed
+        let snap = self.transform.is_identity();
+        for_each_text_stroke(size, spacing, text, |p0, p1| {
+            if snap {
+                self.wu_line(
+                    (r,g,b,1.0),
+                    blend,
+                    (origin.0 + p0.0.round() as i32, origin.1 + p0.1.round() as i32),
+                    (origin.0 + p1.0.round() as i32, origin.1 + p1.1.round() as i32),
+                );
+            } else {
+                let origin = (origin.0 as f32, origin.1 as f32);
+                self.wu_line_f(
+                    (r,g,b,1.0),
+                    blend,
+                    (origin.0 + p0.0, origin.1 + p0.1),
+                    (origin.0 + p1.0, origin.1 + p1.1),
+                );
+            }
+        });
+    }
+
+    // Like draw_text, but with strokes of the given width in pixels so that
+    // large text doesn't look spindly. Connected strokes are joined into
+    // paths, and the text is composited all at once so corners where
+    // strokes meet don't get blended twice.
+    pub fn draw_text_stroked(
+        &mut self,
+        (r, g, b): (f32, f32, f32),
+        blend: BlendMode,
+        origin: (f32, f32),
+        size: f32,
+        text: &str,
+        stroke_width: f32,
+    ) {
+        let outline = text_outline(size, TextSpacing::Monospace, origin, text);
+        self.stroke_path((r,g,b,1.0), blend, &outline, &text_stroke_style(stroke_width));
+    }
 }
 
-pub fn linear_to_srgb(x: f32) -> f32 {
-    ((-0.9192 * x) + 1.9192) * x
+// How draw_text_stroked strokes the stroke font
+pub(crate) fn text_stroke_style(width: f32) -> stroke::StrokeStyle {
+    stroke::StrokeStyle {
+        cap: stroke::LineCap::Square,
+        ..stroke::StrokeStyle::new(width)
+    }
 }
 
-pub fn interp(t: f32, x0: u32, x1: u32) -> u32 {
-    ((1.0 - t) * x0 as f32 + t * x1 as f32).round() as u32
+// How the characters of the stroke font are spaced out along a line
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextSpacing {
+    // Every character takes up the same room, like on a terminal
+    Monospace,
+    // Each character takes up as much room as it needs, and some pairs of
+    // characters are moved closer together, see text_glyph_metrics and
+    // text_kerning
+    Proportional,
 }
 
-pub fn interpf(t: f32, x0: f32, x1: f32) -> f32 {
-    (1.0 - t) * x0 + t * x1
+// Call draw with the end points of every line in the stroke font needed to
+// draw text, in pixels relative to the text's origin. Curved strokes come
+// through as a run of short connected lines.
+fn for_each_text_stroke<F>(size: f32, spacing: TextSpacing, text: &str, mut draw: F)
+    where F: FnMut((f32, f32), (f32, f32))
+{
+    let char_width = text_char_width(size);
+    let char_height = size as i32;
+
+    layout_text(size, spacing, text, |c, x| {
+        let scale = |p: (f32, f32)| {
+            (x as f32 + p.0 * char_width as f32, p.1 * char_height as f32)
+        };
+
+        let glyph = Glyph::new(c);
+        for &(p0, p1) in glyph.lines {
+            draw(scale(p0), scale(p1));
+        }
+
+        // Curves are flattened after scaling so they stay smooth at any size
+        for curve in glyph.curves {
+            let start = scale(curve[0]);
+            let mut points = vec![start];
+            bezier::flatten_cubic(
+                start,
+                scale(curve[1]),
+                scale(curve[2]),
+                scale(curve[3]),
+                bezier::CURVE_TOLERANCE,
+                &mut points,
+            );
+            for pair in points.windows(2) {
+                draw(pair[0], pair[1]);
+            }
+        }
+
+        glyph.for_each_mark_line(|p0, p1| draw(scale(p0), scale(p1)));
+    });
 }
 
-pub fn clear(color: (f32,f32,f32,f32), buffer: &mut Vec<(f32,f32,f32,f32)>) {
-    for p in buffer.iter_mut() {
-        *p = color;
+// Call place with each character of text and how far along from the text's
+// origin it's drawn, in whole pixels. Returns the width of the text, from
+// the left of the first character to the right of the last.
+pub(crate) fn layout_text<F>(size: f32, spacing: TextSpacing, text: &str, mut place: F) -> i32
+    where F: FnMut(char, i32)
+{
+    let mut pen = TextPen::new(size, spacing);
+    for c in text.chars() {
+        place(c, pen.place(c));
     }
+    pen.width()
 }
 
-pub fn coord_to_index(x: usize, y: usize, width: usize) -> usize {
-    x + y*width
+// Lays out text one character at a time, so text can be measured as it's
+// read without going back over it
+pub(crate) struct TextPen {
+    size: f32,
+    spacing: TextSpacing,
+    count: i32,
+    pen: f32,
+    right: f32,
+    previous: Option<char>,
 }
 
-pub fn set_pixel((new_r, new_g, new_b, a): (f32,f32,f32,f32), x: i32, y: i32, width: usize, buffer: &mut Vec<(f32,f32,f32,f32)>) {
-    let x = x as usize;
-    let y = y as usize;
-    let index = x + y * width;
-    if index < buffer.len() {
-        let (old_r, old_g, old_b, _) = buffer[index];
-        buffer[index] = (
-            new_r * a + old_r * (1.0-a),
-            new_g * a + old_g * (1.0-a),
-            new_b * a + old_b * (1.0-a),
-            1.0,
-        );
-    } else {
-        panic!("Point out of range x: {}   y: {}", x, y)
+impl TextPen {
+    pub(crate) fn new(size: f32, spacing: TextSpacing) -> TextPen {
+        TextPen { size, spacing, count: 0, pen: 0.0, right: 0.0, previous: None }
+    }
+
+    // Add c to the end of the text, returning how far along it's drawn
+    pub(crate) fn place(&mut self, c: char) -> i32 {
+        self.count += 1;
+        match self.spacing {
+            TextSpacing::Monospace => (self.count - 1) * text_advance(self.size),
+            TextSpacing::Proportional => {
+                let char_width = text_char_width(self.size) as f32;
+                let metrics = text_glyph_metrics(c);
+                if let Some(previous) = self.previous {
+                    self.pen += (metrics.left_bearing + text_kerning(previous, c)) * char_width;
+                }
+                let x = (self.pen - metrics.left * char_width).round() as i32;
+                self.right = self.pen + (metrics.right - metrics.left) * char_width;
+                self.pen = self.right + metrics.right_bearing * char_width;
+                self.previous = Some(c);
+                x
+            },
+        }
+    }
+
+    // The width of the text so far, from the left of the first character to
+    // the right of the last
+    pub(crate) fn width(&self) -> i32 {
+        match self.spacing {
+            _ if self.count == 0 => 0,
+            TextSpacing::Monospace => (self.count - 1) * text_advance(self.size) + text_char_width(self.size),
+            TextSpacing::Proportional => self.right.round() as i32,
+        }
     }
 }
 
-pub fn gamma_correct_buffer(in_buffer: &[(f32,f32,f32,f32)], out_buffer: &mut Vec<u32>) {
-    in_buffer.par_iter()
-        .map(|(r,g,b,_a)| {
-            ((linear_to_srgb(*r) * 255.0) as u32) << 16 |
-            ((linear_to_srgb(*g) * 255.0) as u32) << 8 |
-             (linear_to_srgb(*b) * 255.0) as u32
-        })
-        .collect_into_vec(out_buffer);
-}
-
-pub fn draw_text(
-    (r, g, b): (f32, f32, f32),
-    mut origin: (i32, i32),
-    size: f32,
-    text: &str,
-    width: usize,
-    buffer: &mut Vec<(f32,f32,f32,f32)>)
-{
-    let char_width = (size / 1.618) as i32;
-    let char_height = size as i32;
-    let spacing = (size / 1.618 / 1.618) as i32;
+// The strokes of text as a path, for stroking with text_stroke_style
+pub(crate) fn text_outline(size: f32, spacing: TextSpacing, origin: (f32, f32), text: &str) -> path::Path {
+    let mut outline = path::Path::new();
+    for_each_text_stroke(size, spacing, text, |p0, p1| {
+        let p0 = (origin.0 + p0.0, origin.1 + p0.1);
+        let p1 = (origin.0 + p1.0, origin.1 + p1.1);
+        if outline.current_point() != Some(p0) {
+            outline.move_to(p0);
+        }
+        outline.line_to(p1);
+    });
+    outline
+}
 
-    for c in text.chars() {
-        for (p0, p1) in char_lines(c) {
-            wu_line(
-                (r,g,b,1.0),
-                (origin.0 + (p0.0 * char_width as f32).round() as i32, origin.1 + (p0.1 * char_height as f32).round() as i32),
-                (origin.0 + (p1.0 * char_width as f32).round() as i32, origin.1 + (p1.1 * char_height as f32).round() as i32),
-                width,
-                buffer
-            );
+// What's drawn for a character in the stroke font, in the same units as
+// char_lines
+struct Glyph {
+    lines: &'static [GlyphLine],
+    curves: &'static [GlyphCurve],
+    marks: &'static [Mark],
+}
+
+impl Glyph {
+    fn new(c: char) -> Glyph {
+        if !has_text_glyph(c) {
+            return Glyph { lines: &MISSING_GLYPH, curves: &[], marks: &[] };
+        }
+
+        // Accented letters and the like are drawn as the character they're
+        // based on, with marks added
+        let (base, marks) = decompose(c).unwrap_or((c, &[]));
+        Glyph { lines: char_lines(base), curves: char_curves(base), marks }
+    }
+
+    // Call draw with the end points of the lines of each mark, placed on
+    // top of the character or below it
+    fn for_each_mark_line<F>(&self, mut draw: F)
+        where F: FnMut((f32, f32), (f32, f32))
+    {
+        if self.marks.is_empty() {
+            return;
+        }
+
+        let mut top = f32::INFINITY;
+        for &(p0, p1) in self.lines {
+            top = top.min(p0.1).min(p1.1);
+        }
+        for curve in self.curves {
+            for p in curve {
+                top = top.min(p.1);
+            }
+        }
+        for &mark in self.marks {
+            for &(p0, p1) in mark.lines() {
+                draw(mark.place(p0, top), mark.place(p1, top));
+            }
+        }
+    }
+
+    // How far left and right the glyph reaches, or None if there's nothing
+    // to draw. Curves are taken to reach as far as their control points.
+    fn extent(&self) -> Option<(f32, f32)> {
+        let mut extent: Option<(f32, f32)> = None;
+        let mut reach = |x: f32| {
+            extent = Some(extent.map_or((x, x), |(left, right)| (left.min(x), right.max(x))));
+        };
+        for &(p0, p1) in self.lines {
+            reach(p0.0);
+            reach(p1.0);
+        }
+        for curve in self.curves {
+            for p in curve {
+                reach(p.0);
+            }
         }
+        self.for_each_mark_line(|p0, p1| {
+            reach(p0.0);
+            reach(p1.0);
+        });
+        extent
+    }
+}
+
+// How much room a character of the stroke font takes up in proportional
+// text, in multiples of the character width
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GlyphMetrics {
+    // Room kept clear to the left of the glyph
+    pub left_bearing: f32,
+    // Where the glyph's strokes start and end across its cell
+    pub left: f32,
+    pub right: f32,
+    // Room kept clear to the right of the glyph
+    pub right_bearing: f32,
+}
+
+impl GlyphMetrics {
+    // How far along the next character goes, before kerning
+    pub fn advance(&self) -> f32 {
+        self.left_bearing + self.right - self.left + self.right_bearing
+    }
+}
+
+// Half the gap between characters in monospaced text, as a multiple of the
+// character width. Characters as wide as their cells take up the same room
+// whether the text is monospaced or proportional.
+const SIDE_BEARING: f32 = 0.309;
+
+pub fn text_glyph_metrics(c: char) -> GlyphMetrics {
+    // Strokes reaching out of the cell, like the tail of a q or the lines
+    // of box drawing characters, are left to overhang. Spaces are half as
+    // wide as a character.
+    let (left, right) = match Glyph::new(c).extent() {
+        Some((left, right)) => (left.max(0.0), right.min(1.0)),
+        None => (0.0, 0.5),
+    };
 
-        origin.0 += char_width + spacing;
+    // Thin upright strokes look cramped with the usual bearings
+    let bearing = match c {
+        'l' | '|' | '!' | '¡' | '.' | ',' | ':' | ';' | '\'' | '"' | '`' => 0.45,
+        _ => SIDE_BEARING,
+    };
+
+    GlyphMetrics {
+        left_bearing: bearing,
+        left,
+        right,
+        right_bearing: bearing,
     }
 }
 
-fn char_lines(c: char) -> &'static [((f32, f32), (f32, f32))] {
+// How much further apart two characters are in proportional text than
+// their bearings alone would put them, as a multiple of the character
+// width. It's negative for pairs that leave a gap that looks too wide, like
+// a V next to an A. Accented letters are kerned like the letters they're
+// based on.
+pub fn text_kerning(left: char, right: char) -> f32 {
+    let base = |c: char| decompose(c).map_or(c, |(base, _)| base);
+    match (base(left), base(right)) {
+        ('A', 'T' | 'V' | 'W' | 'Y') | ('T' | 'V' | 'W' | 'Y', 'A') => -0.3,
+        ('F' | 'P', 'A') => -0.25,
+        ('L', 'T' | 'V' | 'W' | 'Y') => -0.4,
+        ('F' | 'P' | 'T' | 'V' | 'W' | 'Y', '.' | ',') => -0.4,
+        ('T', 'a' | 'c' | 'e' | 'g' | 'm' | 'n' | 'o' | 'p' | 'q' | 'r' | 's' | 'u' | 'v' | 'w' | 'x' | 'y' | 'z') => -0.4,
+        ('V' | 'W' | 'Y', 'a' | 'c' | 'e' | 'o' | 's' | 'u') => -0.25,
+        ('r' | 'v' | 'w' | 'y', '.' | ',') => -0.25,
+        _ => 0.0,
+    }
+}
+
+// Whether draw_text has something to draw for a character, rather than the
+// box it draws for characters it's missing. Spaces and control characters
+// count, even though nothing is drawn for them.
+pub fn has_text_glyph(c: char) -> bool {
+    let base = decompose(c).map_or(c, |(base, _)| base);
+    c.is_whitespace() || c.is_control() || !char_lines(base).is_empty() || !char_curves(base).is_empty()
+}
+
+// How far along the stroke font moves from one character to the next, in
+// whole pixels
+pub(crate) fn text_advance(size: f32) -> i32 {
+    let spacing = (size / 1.618 / 1.618) as i32;
+    text_char_width(size) + spacing
+}
+
+// How wide each character in the stroke font is, in whole pixels
+pub(crate) fn text_char_width(size: f32) -> i32 {
+    (size / 1.618) as i32
+}
+
+fn char_lines(c: char) -> &'static [GlyphLine] {
     match c {
         ' ' => &[],
         '!' => &[
@@ -580,11 +1136,6 @@ fn char_lines(c: char) -> &'static [((f32, f32), (f32, f32))] {
             ((0.0, 0.0), (0.0, 1.0)),
             ((0.0, 0.5), (2./3., 0.5)),
         ],
-        'C' => &[
-            ((0.0, 0.0), (1.0, 0.0)),
-            ((0.0, 0.0), (0.0, 1.0)),
-            ((0.0, 1.0), (1.0, 1.0)),
-        ],
         'D' => &[
             ((0.0, 0.0), (0.0, 1.0)),
             ((0.0, 0.0), (2./3., 0.0)),
@@ -605,10 +1156,7 @@ fn char_lines(c: char) -> &'static [((f32, f32), (f32, f32))] {
             ((0.0, 0.5), (2./3., 0.5)),
         ],
         'G' => &[
-            ((0.0, 0.0), (1.0, 0.0)),
-            ((0.0, 0.0), (0.0, 1.0)),
-            ((0.0, 1.0), (1.0, 1.0)),
-            ((1.0, 1.0), (1.0, 0.5)),
+            ((1.0, 0.85), (1.0, 0.5)),
             ((1.0, 0.5), (0.5, 0.5)),
         ],
         'H' => &[
@@ -646,12 +1194,6 @@ fn char_lines(c: char) -> &'static [((f32, f32), (f32, f32))] {
             ((0.0, 0.0), (1.0, 1.0)),
             ((1.0, 0.0), (1.0, 1.0)),
         ],
-        'O' => &[
-            ((0.0, 0.0), (0.0, 1.0)),
-            ((0.0, 1.0), (1.0, 1.0)),
-            ((1.0, 1.0), (1.0, 0.0)),
-            ((1.0, 0.0), (0.0, 0.0)),
-        ],
         'P' => &[
             ((0.0, 0.0), (0.0, 1.0)),
             ((0.0, 0.0), (1.0, 0.0)),
@@ -660,11 +1202,6 @@ fn char_lines(c: char) -> &'static [((f32, f32), (f32, f32))] {
         ],
         'Q' => &[
             ((2./3., 2./3.), (1.1, 1.1)),
-
-            ((0.0, 0.0), (0.0, 1.0)),
-            ((0.0, 1.0), (1.0, 1.0)),
-            ((1.0, 1.0), (1.0, 0.0)),
-            ((1.0, 0.0), (0.0, 0.0)),
         ],
         'R' => &[
             ((0.0, 0.0), (0.0, 1.0)),
@@ -673,17 +1210,6 @@ fn char_lines(c: char) -> &'static [((f32, f32), (f32, f32))] {
             ((1.0, 0.5), (0.0, 0.5)),
             ((0.0, 0.5), (1.0, 1.0)),
         ],
-        'S' => &[
-            ((1.0, 0.0), (1.0, 0.2)),
-
-            ((0.0, 0.0), (1.0, 0.0)),
-            ((0.0, 0.0), (0.0, 0.5)),
-            ((0.0, 0.5), (1.0, 0.5)),
-            ((1.0, 0.5), (1.0, 1.0)),
-            ((1.0, 1.0), (0.0, 1.0)),
-
-            ((0.0, 1.0), (0.0, 0.8)),
-        ],
         'T' => &[
             ((0.0, 0.0), (1.0, 0.0)),
             ((0.5, 0.0), (0.5, 1.0)),
@@ -905,23 +1431,959 @@ fn char_lines(c: char) -> &'static [((f32, f32), (f32, f32))] {
             ((0.25, 0.4), (0.75, 0.6)),
             ((0.75, 0.6), (1.0, 0.5)),
         ],
+
+        // Latin letters that aren't just a letter with a mark on it
+        'ı' => &[
+            ((0.5, 0.5), (0.5, 1.0)),
+            ((0.3, 0.5), (0.5, 0.5)),
+            ((0.2, 1.0), (0.8, 1.0)),
+        ],
+        'ȷ' => &[
+            ((1.0, 0.5), (1.0, 1.5)),
+            ((1.0, 1.5), (0.0, 1.5)),
+            ((0.0, 1.5), (0.0, 4./3.)),
+        ],
+        'ß' => &[
+            ((0.0, 1.0), (0.0, 0.0)),
+            ((0.0, 0.0), (0.8, 0.0)),
+            ((0.8, 0.0), (0.8, 0.4)),
+            ((0.8, 0.4), (0.5, 0.4)),
+            ((0.5, 0.4), (1.0, 0.55)),
+            ((1.0, 0.55), (1.0, 1.0)),
+            ((1.0, 1.0), (0.5, 1.0)),
+        ],
+        'Æ' => &[
+            ((0.0, 1.0), (0.5, 0.0)),
+            ((0.5, 0.0), (1.0, 0.0)),
+            ((0.5, 0.0), (0.5, 1.0)),
+            ((0.5, 1.0), (1.0, 1.0)),
+            ((0.25, 0.5), (1.0, 0.5)),
+        ],
+        'æ' => &[
+            ((0.5, 0.75), (0.0, 0.75)),
+            ((0.0, 0.75), (0.0, 1.0)),
+            ((0.0, 1.0), (1.0, 1.0)),
+            ((0.5, 0.5), (0.5, 1.0)),
+            ((0.0, 0.5), (1.0, 0.5)),
+            ((1.0, 0.5), (1.0, 0.75)),
+            ((1.0, 0.75), (0.5, 0.75)),
+        ],
+        'Œ' => &[
+            ((1.0, 0.0), (0.0, 0.0)),
+            ((0.0, 0.0), (0.0, 1.0)),
+            ((0.0, 1.0), (1.0, 1.0)),
+            ((0.5, 0.0), (0.5, 1.0)),
+            ((0.5, 0.5), (0.9, 0.5)),
+        ],
+        'œ' => &[
+            ((0.5, 0.5), (0.0, 0.5)),
+            ((0.0, 0.5), (0.0, 1.0)),
+            ((0.0, 1.0), (1.0, 1.0)),
+            ((0.5, 0.5), (0.5, 1.0)),
+            ((0.5, 0.5), (1.0, 0.5)),
+            ((1.0, 0.5), (1.0, 0.75)),
+            ((1.0, 0.75), (0.5, 0.75)),
+        ],
+        'Ð' | 'Đ' => &[
+            ((0.0, 0.0), (0.0, 1.0)),
+            ((0.0, 0.0), (2./3., 0.0)),
+            ((2./3., 0.0), (1.0, 1./3.)),
+            ((1.0, 1./3.), (1.0, 2./3.)),
+            ((1.0, 2./3.), (2./3., 1.0)),
+            ((2./3., 1.0), (0.0, 1.0)),
+            ((-0.2, 0.5), (0.4, 0.5)),
+        ],
+        'đ' => &[
+            ((1.0, 0.0), (1.0, 1.0)),
+            ((1.0, 1.0), (0.0, 1.0)),
+            ((0.0, 1.0), (0.0, 0.5)),
+            ((0.0, 0.5), (1.0, 0.5)),
+            ((0.7, 0.2), (1.3, 0.2)),
+        ],
+        'Ł' => &[
+            ((0.0, 0.0), (0.0, 1.0)),
+            ((0.0, 1.0), (1.0, 1.0)),
+            ((-0.2, 0.65), (0.3, 0.35)),
+        ],
+        'ł' => &[
+            ((0.5, 0.0), (0.5, 1.0)),
+            ((0.25, 0.65), (0.75, 0.35)),
+        ],
+
+        // Punctuation and symbols from Latin-1
+        '¡' => &[
+            ((0.5, 0.0), (0.5, 0.1)),
+            ((0.5, 0.4), (0.5, 1.0)),
+        ],
+        '¿' => &[
+            ((1.0, 0.75), (1.0, 1.0)),
+            ((1.0, 1.0), (0.0, 1.0)),
+            ((0.0, 1.0), (0.0, 0.5)),
+            ((0.0, 0.5), (0.5, 0.5)),
+            ((0.5, 0.5), (0.5, 0.3)),
+            ((0.5, 0.2), (0.5, 0.0)),
+        ],
+        '«' => &[
+            ((0.5, 0.5), (0.2, 0.7)),
+            ((0.2, 0.7), (0.5, 0.9)),
+            ((0.9, 0.5), (0.6, 0.7)),
+            ((0.6, 0.7), (0.9, 0.9)),
+        ],
+        '»' => &[
+            ((0.1, 0.5), (0.4, 0.7)),
+            ((0.4, 0.7), (0.1, 0.9)),
+            ((0.5, 0.5), (0.8, 0.7)),
+            ((0.8, 0.7), (0.5, 0.9)),
+        ],
+        '¬' => &[
+            ((0.0, 0.5), (1.0, 0.5)),
+            ((1.0, 0.5), (1.0, 0.7)),
+        ],
+        '°' => &[
+            ((0.3, 0.0), (0.7, 0.0)),
+            ((0.7, 0.0), (0.7, 0.3)),
+            ((0.7, 0.3), (0.3, 0.3)),
+            ((0.3, 0.3), (0.3, 0.0)),
+        ],
+        '±' => &[
+            ((0.0, 0.4), (1.0, 0.4)),
+            ((0.5, 0.1), (0.5, 0.7)),
+            ((0.0, 0.9), (1.0, 0.9)),
+        ],
+        '·' => &[
+            ((0.5, 0.55), (0.5, 0.65)),
+        ],
+        '×' => &[
+            ((0.2, 0.3), (0.8, 0.9)),
+            ((0.8, 0.3), (0.2, 0.9)),
+        ],
+        '÷' => &[
+            ((0.0, 0.6), (1.0, 0.6)),
+            ((0.5, 0.3), (0.5, 0.4)),
+            ((0.5, 0.8), (0.5, 0.9)),
+        ],
+        '€' => &[
+            ((-0.1, 0.4), (0.6, 0.4)),
+            ((-0.1, 0.6), (0.6, 0.6)),
+        ],
+
+        // Greek letters that don't look like Latin ones
+        'Γ' => &[
+            ((0.0, 0.0), (0.0, 1.0)),
+            ((0.0, 0.0), (1.0, 0.0)),
+        ],
+        'Δ' => &[
+            ((0.5, 0.0), (0.0, 1.0)),
+            ((0.5, 0.0), (1.0, 1.0)),
+            ((0.0, 1.0), (1.0, 1.0)),
+        ],
+        'Θ' => &[
+            ((0.25, 0.5), (0.75, 0.5)),
+        ],
+        'Λ' => &[
+            ((0.5, 0.0), (0.0, 1.0)),
+            ((0.5, 0.0), (1.0, 1.0)),
+        ],
+        'Ξ' => &[
+            ((0.0, 0.0), (1.0, 0.0)),
+            ((0.2, 0.5), (0.8, 0.5)),
+            ((0.0, 1.0), (1.0, 1.0)),
+        ],
+        'Π' => &[
+            ((0.0, 0.0), (1.0, 0.0)),
+            ((0.0, 0.0), (0.0, 1.0)),
+            ((1.0, 0.0), (1.0, 1.0)),
+        ],
+        'Σ' => &[
+            ((1.0, 0.0), (0.0, 0.0)),
+            ((0.0, 0.0), (0.5, 0.5)),
+            ((0.5, 0.5), (0.0, 1.0)),
+            ((0.0, 1.0), (1.0, 1.0)),
+        ],
+        'Φ' => &[
+            ((0.5, 0.0), (0.5, 1.0)),
+            ((0.0, 0.2), (1.0, 0.2)),
+            ((1.0, 0.2), (1.0, 0.8)),
+            ((1.0, 0.8), (0.0, 0.8)),
+            ((0.0, 0.8), (0.0, 0.2)),
+        ],
+        'Ψ' => &[
+            ((0.5, 0.0), (0.5, 1.0)),
+            ((0.0, 0.0), (0.0, 0.5)),
+            ((0.0, 0.5), (1.0, 0.5)),
+            ((1.0, 0.5), (1.0, 0.0)),
+        ],
+        'Ω' => &[
+            ((0.0, 1.0), (0.3, 1.0)),
+            ((0.7, 1.0), (1.0, 1.0)),
+        ],
+        'α' => &[
+            ((0.0, 0.5), (0.75, 0.5)),
+            ((0.0, 0.5), (0.0, 1.0)),
+            ((0.0, 1.0), (0.75, 1.0)),
+            ((0.75, 0.5), (0.75, 1.0)),
+            ((0.75, 0.75), (1.0, 0.5)),
+            ((0.75, 0.75), (1.0, 1.0)),
+        ],
+        'β' => &[
+            ((0.0, 0.0), (0.0, 1.5)),
+            ((0.0, 0.0), (0.8, 0.0)),
+            ((0.8, 0.0), (0.8, 0.45)),
+            ((0.0, 0.45), (1.0, 0.45)),
+            ((1.0, 0.45), (1.0, 1.0)),
+            ((1.0, 1.0), (0.0, 1.0)),
+        ],
+        'γ' => &[
+            ((0.0, 0.5), (0.5, 1.0)),
+            ((1.0, 0.5), (0.5, 1.0)),
+            ((0.5, 1.0), (0.5, 1.5)),
+        ],
+        'δ' => &[
+            ((1.0, 0.0), (0.0, 0.0)),
+            ((0.0, 0.0), (1.0, 0.5)),
+            ((0.0, 0.5), (1.0, 0.5)),
+            ((1.0, 0.5), (1.0, 1.0)),
+            ((1.0, 1.0), (0.0, 1.0)),
+            ((0.0, 1.0), (0.0, 0.5)),
+        ],
+        'ε' => &[
+            ((1.0, 0.5), (0.0, 0.5)),
+            ((0.0, 0.5), (0.0, 1.0)),
+            ((0.0, 1.0), (1.0, 1.0)),
+            ((0.0, 0.75), (0.7, 0.75)),
+        ],
+        'ζ' => &[
+            ((0.0, 0.0), (1.0, 0.0)),
+            ((1.0, 0.0), (0.0, 1.0)),
+            ((0.0, 1.0), (1.0, 1.0)),
+            ((1.0, 1.0), (1.0, 1.5)),
+        ],
+        'η' => &[
+            ((0.0, 0.5), (0.0, 1.0)),
+            ((0.0, 0.5), (1.0, 0.5)),
+            ((1.0, 0.5), (1.0, 1.5)),
+        ],
+        'θ' => &[
+            ((0.0, 0.0), (1.0, 0.0)),
+            ((1.0, 0.0), (1.0, 1.0)),
+            ((1.0, 1.0), (0.0, 1.0)),
+            ((0.0, 1.0), (0.0, 0.0)),
+            ((0.0, 0.5), (1.0, 0.5)),
+        ],
+        'ι' => &[
+            ((0.5, 0.5), (0.5, 1.0)),
+            ((0.5, 1.0), (0.8, 1.0)),
+        ],
+        'κ' => &[
+            ((0.0, 0.5), (0.0, 1.0)),
+            ((0.0, 0.75), (1.0, 0.5)),
+            ((0.0, 0.75), (1.0, 1.0)),
+        ],
+        'λ' => &[
+            ((0.0, 0.0), (0.3, 0.0)),
+            ((0.3, 0.0), (1.0, 1.0)),
+            ((0.5, 0.4), (0.0, 1.0)),
+        ],
+        'μ' => &[
+            ((0.0, 0.5), (0.0, 1.5)),
+            ((0.0, 1.0), (1.0, 1.0)),
+            ((1.0, 1.0), (1.0, 0.5)),
+        ],
+        'ξ' => &[
+            ((1.0, 0.0), (0.0, 0.0)),
+            ((0.0, 0.0), (0.0, 1.0)),
+            ((0.0, 0.45), (0.8, 0.45)),
+            ((0.0, 1.0), (1.0, 1.0)),
+            ((1.0, 1.0), (1.0, 1.5)),
+        ],
+        'π' => &[
+            ((0.0, 0.5), (1.0, 0.5)),
+            ((0.25, 0.5), (0.25, 1.0)),
+            ((0.75, 0.5), (0.75, 1.0)),
+        ],
+        'σ' => &[
+            ((0.0, 0.5), (1.0, 0.5)),
+            ((0.0, 0.5), (0.0, 1.0)),
+            ((0.0, 1.0), (0.8, 1.0)),
+            ((0.8, 1.0), (0.8, 0.5)),
+        ],
+        'ς' => &[
+            ((1.0, 0.5), (0.0, 0.5)),
+            ((0.0, 0.5), (0.0, 1.0)),
+            ((0.0, 1.0), (1.0, 1.0)),
+            ((1.0, 1.0), (1.0, 1.5)),
+        ],
+        'τ' => &[
+            ((0.0, 0.5), (1.0, 0.5)),
+            ((0.5, 0.5), (0.5, 1.0)),
+        ],
+        'φ' => &[
+            ((0.5, 0.3), (0.5, 1.5)),
+            ((0.0, 0.5), (1.0, 0.5)),
+            ((1.0, 0.5), (1.0, 1.0)),
+            ((1.0, 1.0), (0.0, 1.0)),
+            ((0.0, 1.0), (0.0, 0.5)),
+        ],
+        'χ' => &[
+            ((0.0, 0.5), (1.0, 1.5)),
+            ((1.0, 0.5), (0.0, 1.5)),
+        ],
+        'ψ' => &[
+            ((0.5, 0.3), (0.5, 1.5)),
+            ((0.0, 0.5), (0.0, 1.0)),
+            ((0.0, 1.0), (1.0, 1.0)),
+            ((1.0, 1.0), (1.0, 0.5)),
+        ],
+
+        // Maths
+        '≠' => &[
+            ((0.0, 0.4), (1.0, 0.4)),
+            ((0.0, 0.6), (1.0, 0.6)),
+            ((0.3, 0.9), (0.7, 0.1)),
+        ],
+        '≤' => &[
+            ((1.0, 0.1), (0.0, 0.4)),
+            ((0.0, 0.4), (1.0, 0.7)),
+            ((0.0, 0.9), (1.0, 0.9)),
+        ],
+        '≥' => &[
+            ((0.0, 0.1), (1.0, 0.4)),
+            ((1.0, 0.4), (0.0, 0.7)),
+            ((0.0, 0.9), (1.0, 0.9)),
+        ],
+        '≈' => &[
+            ((0.0, 0.4), (0.25, 0.3)),
+            ((0.25, 0.3), (0.75, 0.5)),
+            ((0.75, 0.5), (1.0, 0.4)),
+            ((0.0, 0.7), (0.25, 0.6)),
+            ((0.25, 0.6), (0.75, 0.8)),
+            ((0.75, 0.8), (1.0, 0.7)),
+        ],
+        '∞' => &[
+            ((0.5, 0.55), (0.25, 0.4)),
+            ((0.25, 0.4), (0.0, 0.55)),
+            ((0.0, 0.55), (0.25, 0.7)),
+            ((0.25, 0.7), (0.75, 0.4)),
+            ((0.75, 0.4), (1.0, 0.55)),
+            ((1.0, 0.55), (0.75, 0.7)),
+            ((0.75, 0.7), (0.5, 0.55)),
+        ],
+        '√' => &[
+            ((0.0, 0.6), (0.2, 0.5)),
+            ((0.2, 0.5), (0.4, 1.0)),
+            ((0.4, 1.0), (0.8, 0.0)),
+            ((0.8, 0.0), (1.0, 0.0)),
+        ],
+
+        // Arrows
+        '←' => &[
+            ((0.0, 0.5), (1.0, 0.5)),
+            ((0.0, 0.5), (0.3, 0.3)),
+            ((0.0, 0.5), (0.3, 0.7)),
+        ],
+        '↑' => &[
+            ((0.5, 0.0), (0.5, 1.0)),
+            ((0.5, 0.0), (0.2, 0.3)),
+            ((0.5, 0.0), (0.8, 0.3)),
+        ],
+        '→' => &[
+            ((0.0, 0.5), (1.0, 0.5)),
+            ((1.0, 0.5), (0.7, 0.3)),
+            ((1.0, 0.5), (0.7, 0.7)),
+        ],
+        '↓' => &[
+            ((0.5, 0.0), (0.5, 1.0)),
+            ((0.5, 1.0), (0.2, 0.7)),
+            ((0.5, 1.0), (0.8, 0.7)),
+        ],
+        '↔' => &[
+            ((0.0, 0.5), (1.0, 0.5)),
+            ((0.0, 0.5), (0.3, 0.3)),
+            ((0.0, 0.5), (0.3, 0.7)),
+            ((1.0, 0.5), (0.7, 0.3)),
+            ((1.0, 0.5), (0.7, 0.7)),
+        ],
+        '↕' => &[
+            ((0.5, 0.0), (0.5, 1.0)),
+            ((0.5, 0.0), (0.2, 0.3)),
+            ((0.5, 0.0), (0.8, 0.3)),
+            ((0.5, 1.0), (0.2, 0.7)),
+            ((0.5, 1.0), (0.8, 0.7)),
+        ],
+        '⇐' => &[
+            ((1.0, 0.4), (0.2, 0.4)),
+            ((1.0, 0.6), (0.2, 0.6)),
+            ((0.3, 0.2), (0.0, 0.5)),
+            ((0.0, 0.5), (0.3, 0.8)),
+        ],
+        '⇒' => &[
+            ((0.0, 0.4), (0.8, 0.4)),
+            ((0.0, 0.6), (0.8, 0.6)),
+            ((0.7, 0.2), (1.0, 0.5)),
+            ((1.0, 0.5), (0.7, 0.8)),
+        ],
+        '⇔' => &[
+            ((0.2, 0.4), (0.8, 0.4)),
+            ((0.2, 0.6), (0.8, 0.6)),
+            ((0.3, 0.2), (0.0, 0.5)),
+            ((0.0, 0.5), (0.3, 0.8)),
+            ((0.7, 0.2), (1.0, 0.5)),
+            ((1.0, 0.5), (0.7, 0.8)),
+        ],
+
+        // Box drawing, reaching out to the edges of the space each character
+        // takes up so that neighbouring characters join up
+        '─' => &[
+            ((BOX_LEFT, 0.5), (BOX_RIGHT, 0.5)),
+        ],
+        '│' => &[
+            ((0.5, BOX_TOP), (0.5, BOX_BOTTOM)),
+        ],
+        '┌' => &[
+            ((0.5, 0.5), (BOX_RIGHT, 0.5)),
+            ((0.5, 0.5), (0.5, BOX_BOTTOM)),
+        ],
+        '┐' => &[
+            ((BOX_LEFT, 0.5), (0.5, 0.5)),
+            ((0.5, 0.5), (0.5, BOX_BOTTOM)),
+        ],
+        '└' => &[
+            ((0.5, BOX_TOP), (0.5, 0.5)),
+            ((0.5, 0.5), (BOX_RIGHT, 0.5)),
+        ],
+        '┘' => &[
+            ((0.5, BOX_TOP), (0.5, 0.5)),
+            ((0.5, 0.5), (BOX_LEFT, 0.5)),
+        ],
+        '├' => &[
+            ((0.5, BOX_TOP), (0.5, BOX_BOTTOM)),
+            ((0.5, 0.5), (BOX_RIGHT, 0.5)),
+        ],
+        '┤' => &[
+            ((0.5, BOX_TOP), (0.5, BOX_BOTTOM)),
+            ((BOX_LEFT, 0.5), (0.5, 0.5)),
+        ],
+        '┬' => &[
+            ((BOX_LEFT, 0.5), (BOX_RIGHT, 0.5)),
+            ((0.5, 0.5), (0.5, BOX_BOTTOM)),
+        ],
+        '┴' => &[
+            ((BOX_LEFT, 0.5), (BOX_RIGHT, 0.5)),
+            ((0.5, BOX_TOP), (0.5, 0.5)),
+        ],
+        '┼' => &[
+            ((BOX_LEFT, 0.5), (BOX_RIGHT, 0.5)),
+            ((0.5, BOX_TOP), (0.5, BOX_BOTTOM)),
+        ],
+        '═' => &[
+            ((BOX_LEFT, 0.4), (BOX_RIGHT, 0.4)),
+            ((BOX_LEFT, 0.6), (BOX_RIGHT, 0.6)),
+        ],
+        '║' => &[
+            ((0.4, BOX_TOP), (0.4, BOX_BOTTOM)),
+            ((0.6, BOX_TOP), (0.6, BOX_BOTTOM)),
+        ],
+        '╔' => &[
+            ((0.4, 0.4), (BOX_RIGHT, 0.4)),
+            ((0.4, 0.4), (0.4, BOX_BOTTOM)),
+            ((0.6, 0.6), (BOX_RIGHT, 0.6)),
+            ((0.6, 0.6), (0.6, BOX_BOTTOM)),
+        ],
+        '╗' => &[
+            ((BOX_LEFT, 0.4), (0.6, 0.4)),
+            ((0.6, 0.4), (0.6, BOX_BOTTOM)),
+            ((BOX_LEFT, 0.6), (0.4, 0.6)),
+            ((0.4, 0.6), (0.4, BOX_BOTTOM)),
+        ],
+        '╚' => &[
+            ((0.4, BOX_TOP), (0.4, 0.6)),
+            ((0.4, 0.6), (BOX_RIGHT, 0.6)),
+            ((0.6, BOX_TOP), (0.6, 0.4)),
+            ((0.6, 0.4), (BOX_RIGHT, 0.4)),
+        ],
+        '╝' => &[
+            ((0.6, BOX_TOP), (0.6, 0.6)),
+            ((0.6, 0.6), (BOX_LEFT, 0.6)),
+            ((0.4, BOX_TOP), (0.4, 0.4)),
+            ((0.4, 0.4), (BOX_LEFT, 0.4)),
+        ],
+        _ => &[],
+    }
+}
+
+// The end points of a straight stroke of a glyph, in units of the width and
+// height of a character, with (0, 0) at the top left of its cell
+type GlyphLine = ((f32, f32), (f32, f32));
+
+// Control points of a cubic Bézier curve on a glyph, in the same units as
+// char_lines
+type GlyphCurve = [(f32, f32); 4];
+
+// Quarters of an ellipse filling the whole character cell, starting at the
+// top and going clockwise. 0.276 is half the usual 0.552 for approximating
+// a circle with cubic curves.
+const BULGE: f32 = 0.276;
+const ELLIPSE: [GlyphCurve; 4] = [
+    [(0.5, 0.0), (0.5 + BULGE, 0.0), (1.0, 0.5 - BULGE), (1.0, 0.5)],
+    [(1.0, 0.5), (1.0, 0.5 + BULGE), (0.5 + BULGE, 1.0), (0.5, 1.0)],
+    [(0.5, 1.0), (0.5 - BULGE, 1.0), (0.0, 0.5 + BULGE), (0.0, 0.5)],
+    [(0.0, 0.5), (0.0, 0.5 - BULGE), (0.5 - BULGE, 0.0), (0.5, 0.0)],
+];
+const OPEN_BOWL: [GlyphCurve; 4] = [
+    [(1.0, 0.15), (0.88, 0.03), (0.7, 0.0), (0.5, 0.0)],
+    [(0.5, 0.0), (0.5 - BULGE, 0.0), (0.0, 0.5 - BULGE), (0.0, 0.5)],
+    [(0.0, 0.5), (0.0, 0.5 + BULGE), (0.5 - BULGE, 1.0), (0.5, 1.0)],
+    [(0.5, 1.0), (0.7, 1.0), (0.88, 0.97), (1.0, 0.85)],
+];
+
+// The curved strokes of the round letters, drawn along with their straight
+// lines from char_lines
+fn char_curves(c: char) -> &'static [GlyphCurve] {
+    match c {
+        'O' | 'Q' | 'Θ' => &ELLIPSE,
+        'C' | 'G' | '€' => &OPEN_BOWL,
+        'Ω' => &[
+            [(0.3, 1.0), (-0.1, 0.8), (-0.05, 0.0), (0.5, 0.0)],
+            [(0.5, 0.0), (1.05, 0.0), (1.1, 0.8), (0.7, 1.0)],
+        ],
+        'S' => &[
+            [(1.0, 0.15), (0.88, 0.03), (0.7, 0.0), (0.5, 0.0)],
+            [(0.5, 0.0), (0.22, 0.0), (0.0, 0.11), (0.0, 0.25)],
+            [(0.0, 0.25), (0.0, 0.39), (0.22, 0.5), (0.5, 0.5)],
+            [(0.5, 0.5), (0.78, 0.5), (1.0, 0.61), (1.0, 0.75)],
+            [(1.0, 0.75), (1.0, 0.89), (0.78, 1.0), (0.5, 1.0)],
+            [(0.5, 1.0), (0.3, 1.0), (0.12, 0.97), (0.0, 0.85)],
+        ],
         _ => &[],
     }
 }
 
+// How far box drawing characters reach past their own cell, so that they
+// meet the next character along, or the next line down with the usual line
+// spacing of TextLayout
+const BOX_LEFT: f32 = -SIDE_BEARING;
+const BOX_RIGHT: f32 = 1.0 + SIDE_BEARING;
+const BOX_TOP: f32 = -0.25;
+const BOX_BOTTOM: f32 = 1.25;
+
+// A crossed out box drawn for characters the stroke font doesn't have, so
+// that they stand out instead of quietly disappearing
+const MISSING_GLYPH: [GlyphLine; 6] = [
+    ((0.0, 0.0), (1.0, 0.0)),
+    ((1.0, 0.0), (1.0, 1.0)),
+    ((1.0, 1.0), (0.0, 1.0)),
+    ((0.0, 1.0), (0.0, 0.0)),
+    ((0.0, 0.0), (1.0, 1.0)),
+    ((1.0, 0.0), (0.0, 1.0)),
+];
+
+// Accents and other marks added to a character to make another one
+#[derive(Clone, Copy, Debug)]
+enum Mark {
+    Grave,
+    Acute,
+    Circumflex,
+    Tilde,
+    Macron,
+    Breve,
+    DotAbove,
+    Diaeresis,
+    Ring,
+    DoubleAcute,
+    Caron,
+    Cedilla,
+    Ogonek,
+    Slash,
+}
+
+impl Mark {
+    // The strokes of the mark. Marks above a character are given as if its
+    // top was at 0, marks below are where they'd be under the baseline, and
+    // the slash is given across a character filling the whole cell.
+    fn lines(self) -> &'static [GlyphLine] {
+        match self {
+            Mark::Grave => &[
+                ((0.3, -0.25), (0.6, -0.05)),
+            ],
+            Mark::Acute => &[
+                ((0.4, -0.05), (0.7, -0.25)),
+            ],
+            Mark::Circumflex => &[
+                ((0.2, -0.05), (0.5, -0.25)),
+                ((0.5, -0.25), (0.8, -0.05)),
+            ],
+            Mark::Tilde => &[
+                ((0.1, -0.1), (0.35, -0.2)),
+                ((0.35, -0.2), (0.65, -0.1)),
+                ((0.65, -0.1), (0.9, -0.2)),
+            ],
+            Mark::Macron => &[
+                ((0.2, -0.15), (0.8, -0.15)),
+            ],
+            Mark::Breve => &[
+                ((0.2, -0.25), (0.3, -0.1)),
+                ((0.3, -0.1), (0.7, -0.1)),
+                ((0.7, -0.1), (0.8, -0.25)),
+            ],
+            Mark::DotAbove => &[
+                ((0.5, -0.2), (0.5, -0.1)),
+            ],
+            Mark::Diaeresis => &[
+                ((0.25, -0.2), (0.25, -0.1)),
+                ((0.75, -0.2), (0.75, -0.1)),
+            ],
+            Mark::Ring => &[
+                ((0.35, -0.28), (0.65, -0.28)),
+                ((0.65, -0.28), (0.65, -0.05)),
+                ((0.65, -0.05), (0.35, -0.05)),
+                ((0.35, -0.05), (0.35, -0.28)),
+            ],
+            Mark::DoubleAcute => &[
+                ((0.25, -0.05), (0.45, -0.25)),
+                ((0.55, -0.05), (0.75, -0.25)),
+            ],
+            Mark::Caron => &[
+                ((0.2, -0.25), (0.5, -0.05)),
+                ((0.5, -0.05), (0.8, -0.25)),
+            ],
+            Mark::Cedilla => &[
+                ((0.5, 1.0), (0.5, 1.1)),
+                ((0.5, 1.1), (0.7, 1.2)),
+                ((0.7, 1.2), (0.4, 1.3)),
+            ],
+            Mark::Ogonek => &[
+                ((0.9, 1.0), (0.7, 1.15)),
+                ((0.7, 1.15), (0.9, 1.3)),
+            ],
+            Mark::Slash => &[
+                ((-0.1, 1.1), (1.1, -0.1)),
+            ],
+        }
+    }
+
+    // Where a point of the mark goes on a character whose top is at top
+    fn place(self, (x, y): (f32, f32), top: f32) -> (f32, f32) {
+        match self {
+            Mark::Cedilla | Mark::Ogonek => (x, y),
+            Mark::Slash => (x, top + y * (1.0 - top)),
+            _ => (x, top + y),
+        }
+    }
+}
+
+// The character another one is drawn as, and the marks added to it.
+// Accents go on the dotless i and j. Greek letters that look like Latin
+// ones are drawn as them.
+fn decompose(c: char) -> Option<(char, &'static [Mark])> {
+    Some(match c {
+        'À' => ('A', &[Mark::Grave]),
+        'Á' => ('A', &[Mark::Acute]),
+        'Â' => ('A', &[Mark::Circumflex]),
+        'Ã' => ('A', &[Mark::Tilde]),
+        'Ä' => ('A', &[Mark::Diaeresis]),
+        'Å' => ('A', &[Mark::Ring]),
+        'Ç' => ('C', &[Mark::Cedilla]),
+        'È' => ('E', &[Mark::Grave]),
+        'É' => ('E', &[Mark::Acute]),
+        'Ê' => ('E', &[Mark::Circumflex]),
+        'Ë' => ('E', &[Mark::Diaeresis]),
+        'Ì' => ('I', &[Mark::Grave]),
+        'Í' => ('I', &[Mark::Acute]),
+        'Î' => ('I', &[Mark::Circumflex]),
+        'Ï' => ('I', &[Mark::Diaeresis]),
+        'Ñ' => ('N', &[Mark::Tilde]),
+        'Ò' => ('O', &[Mark::Grave]),
+        'Ó' => ('O', &[Mark::Acute]),
+        'Ô' => ('O', &[Mark::Circumflex]),
+        'Õ' => ('O', &[Mark::Tilde]),
+        'Ö' => ('O', &[Mark::Diaeresis]),
+        'Ù' => ('U', &[Mark::Grave]),
+        'Ú' => ('U', &[Mark::Acute]),
+        'Û' => ('U', &[Mark::Circumflex]),
+        'Ü' => ('U', &[Mark::Diaeresis]),
+        'Ý' => ('Y', &[Mark::Acute]),
+        'à' => ('a', &[Mark::Grave]),
+        'á' => ('a', &[Mark::Acute]),
+        'â' => ('a', &[Mark::Circumflex]),
+        'ã' => ('a', &[Mark::Tilde]),
+        'ä' => ('a', &[Mark::Diaeresis]),
+        'å' => ('a', &[Mark::Ring]),
+        'ç' => ('c', &[Mark::Cedilla]),
+        'è' => ('e', &[Mark::Grave]),
+        'é' => ('e', &[Mark::Acute]),
+        'ê' => ('e', &[Mark::Circumflex]),
+        'ë' => ('e', &[Mark::Diaeresis]),
+        'ì' => ('ı', &[Mark::Grave]),
+        'í' => ('ı', &[Mark::Acute]),
+        'î' => ('ı', &[Mark::Circumflex]),
+        'ï' => ('ı', &[Mark::Diaeresis]),
+        'ñ' => ('n', &[Mark::Tilde]),
+        'ò' => ('o', &[Mark::Grave]),
+        'ó' => ('o', &[Mark::Acute]),
+        'ô' => ('o', &[Mark::Circumflex]),
+        'õ' => ('o', &[Mark::Tilde]),
+        'ö' => ('o', &[Mark::Diaeresis]),
+        'ù' => ('u', &[Mark::Grave]),
+        'ú' => ('u', &[Mark::Acute]),
+        'û' => ('u', &[Mark::Circumflex]),
+        'ü' => ('u', &[Mark::Diaeresis]),
+        'ý' => ('y', &[Mark::Acute]),
+        'ÿ' => ('y', &[Mark::Diaeresis]),
+        'Ā' => ('A', &[Mark::Macron]),
+        'ā' => ('a', &[Mark::Macron]),
+        'Ă' => ('A', &[Mark::Breve]),
+        'ă' => ('a', &[Mark::Breve]),
+        'Ą' => ('A', &[Mark::Ogonek]),
+        'ą' => ('a', &[Mark::Ogonek]),
+        'Ć' => ('C', &[Mark::Acute]),
+        'ć' => ('c', &[Mark::Acute]),
+        'Ĉ' => ('C', &[Mark::Circumflex]),
+        'ĉ' => ('c', &[Mark::Circumflex]),
+        'Ċ' => ('C', &[Mark::DotAbove]),
+        'ċ' => ('c', &[Mark::DotAbove]),
+        'Č' => ('C', &[Mark::Caron]),
+        'č' => ('c', &[Mark::Caron]),
+        'Ď' => ('D', &[Mark::Caron]),
+        'ď' => ('d', &[Mark::Caron]),
+        'Ē' => ('E', &[Mark::Macron]),
+        'ē' => ('e', &[Mark::Macron]),
+        'Ĕ' => ('E', &[Mark::Breve]),
+        'ĕ' => ('e', &[Mark::Breve]),
+        'Ė' => ('E', &[Mark::DotAbove]),
+        'ė' => ('e', &[Mark::DotAbove]),
+        'Ę' => ('E', &[Mark::Ogonek]),
+        'ę' => ('e', &[Mark::Ogonek]),
+        'Ě' => ('E', &[Mark::Caron]),
+        'ě' => ('e', &[Mark::Caron]),
+        'Ĝ' => ('G', &[Mark::Circumflex]),
+        'ĝ' => ('g', &[Mark::Circumflex]),
+        'Ğ' => ('G', &[Mark::Breve]),
+        'ğ' => ('g', &[Mark::Breve]),
+        'Ġ' => ('G', &[Mark::DotAbove]),
+        'ġ' => ('g', &[Mark::DotAbove]),
+        'Ģ' => ('G', &[Mark::Cedilla]),
+        'ģ' => ('g', &[Mark::Cedilla]),
+        'Ĥ' => ('H', &[Mark::Circumflex]),
+        'ĥ' => ('h', &[Mark::Circumflex]),
+        'Ĩ' => ('I', &[Mark::Tilde]),
+        'ĩ' => ('ı', &[Mark::Tilde]),
+        'Ī' => ('I', &[Mark::Macron]),
+        'ī' => ('ı', &[Mark::Macron]),
+        'Ĭ' => ('I', &[Mark::Breve]),
+        'ĭ' => ('ı', &[Mark::Breve]),
+        'Į' => ('I', &[Mark::Ogonek]),
+        'į' => ('i', &[Mark::Ogonek]),
+        'İ' => ('I', &[Mark::DotAbove]),
+        'Ĵ' => ('J', &[Mark::Circumflex]),
+        'ĵ' => ('ȷ', &[Mark::Circumflex]),
+        'Ķ' => ('K', &[Mark::Cedilla]),
+        'ķ' => ('k', &[Mark::Cedilla]),
+        'Ĺ' => ('L', &[Mark::Acute]),
+        'ĺ' => ('l', &[Mark::Acute]),
+        'Ļ' => ('L', &[Mark::Cedilla]),
+        'ļ' => ('l', &[Mark::Cedilla]),
+        'Ľ' => ('L', &[Mark::Caron]),
+        'ľ' => ('l', &[Mark::Caron]),
+        'Ń' => ('N', &[Mark::Acute]),
+        'ń' => ('n', &[Mark::Acute]),
+        'Ņ' => ('N', &[Mark::Cedilla]),
+        'ņ' => ('n', &[Mark::Cedilla]),
+        'Ň' => ('N', &[Mark::Caron]),
+        'ň' => ('n', &[Mark::Caron]),
+        'Ō' => ('O', &[Mark::Macron]),
+        'ō' => ('o', &[Mark::Macron]),
+        'Ŏ' => ('O', &[Mark::Breve]),
+        'ŏ' => ('o', &[Mark::Breve]),
+        'Ő' => ('O', &[Mark::DoubleAcute]),
+        'ő' => ('o', &[Mark::DoubleAcute]),
+        'Ŕ' => ('R', &[Mark::Acute]),
+        'ŕ' => ('r', &[Mark::Acute]),
+        'Ŗ' => ('R', &[Mark::Cedilla]),
+        'ŗ' => ('r', &[Mark::Cedilla]),
+        'Ř' => ('R', &[Mark::Caron]),
+        'ř' => ('r', &[Mark::Caron]),
+        'Ś' => ('S', &[Mark::Acute]),
+        'ś' => ('s', &[Mark::Acute]),
+        'Ŝ' => ('S', &[Mark::Circumflex]),
+        'ŝ' => ('s', &[Mark::Circumflex]),
+        'Ş' => ('S', &[Mark::Cedilla]),
+        'ş' => ('s', &[Mark::Cedilla]),
+        'Š' => ('S', &[Mark::Caron]),
+        'š' => ('s', &[Mark::Caron]),
+        'Ţ' => ('T', &[Mark::Cedilla]),
+        'ţ' => ('t', &[Mark::Cedilla]),
+        'Ť' => ('T', &[Mark::Caron]),
+        'ť' => ('t', &[Mark::Caron]),
+        'Ũ' => ('U', &[Mark::Tilde]),
+        'ũ' => ('u', &[Mark::Tilde]),
+        'Ū' => ('U', &[Mark::Macron]),
+        'ū' => ('u', &[Mark::Macron]),
+        'Ŭ' => ('U', &[Mark::Breve]),
+        'ŭ' => ('u', &[Mark::Breve]),
+        'Ů' => ('U', &[Mark::Ring]),
+        'ů' => ('u', &[Mark::Ring]),
+        'Ű' => ('U', &[Mark::DoubleAcute]),
+        'ű' => ('u', &[Mark::DoubleAcute]),
+        'Ų' => ('U', &[Mark::Ogonek]),
+        'ų' => ('u', &[Mark::Ogonek]),
+        'Ŵ' => ('W', &[Mark::Circumflex]),
+        'ŵ' => ('w', &[Mark::Circumflex]),
+        'Ŷ' => ('Y', &[Mark::Circumflex]),
+        'ŷ' => ('y', &[Mark::Circumflex]),
+        'Ÿ' => ('Y', &[Mark::Diaeresis]),
+        'Ź' => ('Z', &[Mark::Acute]),
+        'ź' => ('z', &[Mark::Acute]),
+        'Ż' => ('Z', &[Mark::DotAbove]),
+        'ż' => ('z', &[Mark::DotAbove]),
+        'Ž' => ('Z', &[Mark::Caron]),
+        'ž' => ('z', &[Mark::Caron]),
+        'Ά' => ('A', &[Mark::Acute]),
+        'Έ' => ('E', &[Mark::Acute]),
+        'Ή' => ('H', &[Mark::Acute]),
+        'Ί' => ('I', &[Mark::Acute]),
+        'Ό' => ('O', &[Mark::Acute]),
+        'Ύ' => ('Y', &[Mark::Acute]),
+        'Ώ' => ('Ω', &[Mark::Acute]),
+        'Ϊ' => ('I', &[Mark::Diaeresis]),
+        'Ϋ' => ('Y', &[Mark::Diaeresis]),
+        'ά' => ('α', &[Mark::Acute]),
+        'έ' => ('ε', &[Mark::Acute]),
+        'ή' => ('η', &[Mark::Acute]),
+        'ί' => ('ι', &[Mark::Acute]),
+        'ϊ' => ('ι', &[Mark::Diaeresis]),
+        'ϋ' => ('u', &[Mark::Diaeresis]),
+        'ό' => ('o', &[Mark::Acute]),
+        'ύ' => ('u', &[Mark::Acute]),
+        'ώ' => ('w', &[Mark::Acute]),
+        'Ø' => ('O', &[Mark::Slash]),
+        'ø' => ('o', &[Mark::Slash]),
+        'Α' => ('A', &[]),
+        'Β' => ('B', &[]),
+        'Ε' => ('E', &[]),
+        'Ζ' => ('Z', &[]),
+        'Η' => ('H', &[]),
+        'Ι' => ('I', &[]),
+        'Κ' => ('K', &[]),
+        'Μ' => ('M', &[]),
+        'Ν' => ('N', &[]),
+        'Ο' => ('O', &[]),
+        'Ρ' => ('P', &[]),
+        'Τ' => ('T', &[]),
+        'Υ' => ('Y', &[]),
+        'Χ' => ('X', &[]),
+        'ν' => ('v', &[]),
+        'ο' => ('o', &[]),
+        'ρ' => ('p', &[]),
+        'υ' => ('u', &[]),
+        'ω' => ('w', &[]),
+        '\u{B5}' => ('μ', &[]),
+        '\u{2206}' => ('Δ', &[]),
+        '\u{2126}' => ('Ω', &[]),
+        '\u{220F}' => ('Π', &[]),
+        '\u{2211}' => ('Σ', &[]),
+        _ => return None,
+    })
+}
+
 #[test]
 fn test_all_points() {
     let width = 71;
     let height = 43;
 
-    let mut buffer: Vec<(f32,f32,f32,f32)> = vec![(0.0, 0.0, 0.0, 0.0); width * height];
+    let mut canvas = Canvas::new(width, height);
     for y0 in 0..height {
         for x0 in 0..width {
             for y1 in 0..height {
                 for x1 in 0..width {
-                    wu_line((1.0,1.0,1.0,1.0), (x0 as i32, y0 as i32), (x1 as i32, y1 as i32), width, &mut buffer);
+                    canvas.wu_line((1.0,1.0,1.0,1.0), BlendMode::SourceOver, (x0 as i32, y0 as i32), (x1 as i32, y1 as i32));
                 }
             }
         }
     }
 }
+
+#[test]
+fn test_subpixel_line() {
+    let mut canvas = Canvas::new(16, 16);
+    canvas.clear((0.0, 0.0, 0.0, 1.0));
+
+    // A line on the boundary between rows 10 and 11, a quarter of the way
+    // into row 10
+    canvas.wu_line_f((1.0, 1.0, 1.0, 1.0), BlendMode::SourceOver, (2.5, 10.75), (8.5, 10.75));
+    assert_eq!(canvas.get_pixel(5, 10), Some((0.75, 0.75, 0.75, 1.0)));
+    assert_eq!(canvas.get_pixel(5, 11), Some((0.25, 0.25, 0.25, 1.0)));
+
+    // The end points only cover half of their pixels
+    assert_eq!(canvas.get_pixel(2, 10), Some((0.375, 0.375, 0.375, 1.0)));
+    assert_eq!(canvas.get_pixel(8, 10), Some((0.375, 0.375, 0.375, 1.0)));
+    assert_eq!(canvas.get_pixel(1, 10), Some((0.0, 0.0, 0.0, 1.0)));
+    assert_eq!(canvas.get_pixel(9, 10), Some((0.0, 0.0, 0.0, 1.0)));
+
+    // Off canvas and degenerate lines are fine
+    canvas.wu_line_f((1.0, 1.0, 1.0, 1.0), BlendMode::SourceOver, (-100.0, -3.0), (100.0, 40.0));
+    canvas.wu_line_f((1.0, 1.0, 1.0, 1.0), BlendMode::SourceOver, (-100.0, -3.0), (-50.0, 40.0));
+    canvas.wu_line_f((1.0, 1.0, 1.0, 1.0), BlendMode::SourceOver, (15.9, 15.9), (15.9, 15.9));
+    canvas.wu_line_f((1.0, 1.0, 1.0, 1.0), BlendMode::SourceOver, (3.0, f32::NAN), (5.0, 5.0));
+}
+
+#[test]
+fn test_text_glyphs() {
+    for &c in &['A', 'é', 'Ø', 'ı', 'λ', 'Σ', 'Ω', '→', '┼', '≤', ' ', '\n'] {
+        assert!(has_text_glyph(c), "{:?}", c);
+    }
+
+    // Symbols that look like Greek letters are drawn as them
+    for &c in &['\u{B5}', '\u{2126}', '\u{2206}', '\u{220F}', '\u{2211}'] {
+        assert!(has_text_glyph(c), "{:?}", c);
+    }
+    assert!(!has_text_glyph('日'));
+    assert!(!has_text_glyph('\u{301}'));
+
+    // The rows each character draws in
+    let rows = |text: &str| {
+        let mut canvas = Canvas::new(40, 60);
+        canvas.draw_text((1.0, 1.0, 1.0), BlendMode::SourceOver, (10, 20), 20.0, TextSpacing::Monospace, text);
+        (0..60).filter(|&y| (0..40).any(|x| canvas.get_pixel(x, y).unwrap().0 > 0.0)).collect::<Vec<_>>()
+    };
+
+    // Accents go above lowercase letters, and higher still above capitals
+    let (e, e_acute) = (rows("e"), rows("é"));
+    assert!(e_acute[0] < e[0] && e_acute[0] >= 20);
+    assert!(rows("É")[0] < 20);
+    assert_eq!(rows("ç").last(), Some(&46));
+
+    // Missing characters are drawn as something, but control characters
+    // aren't
+    assert_eq!(rows("日"), (20..=40).collect::<Vec<_>>());
+    assert!(rows("\n").is_empty());
+}
+
+#[test]
+fn test_text_spacing() {
+    // Glyphs are as wide as their strokes, with room either side
+    let m = text_glyph_metrics('M');
+    assert_eq!((m.left, m.right), (0.0, 1.0));
+    assert!((m.advance() - 1.618).abs() < 1e-6);
+    let l = text_glyph_metrics('l');
+    assert_eq!((l.left, l.right), (0.5, 0.5));
+    assert!(l.advance() < text_glyph_metrics('i').advance());
+    let q = text_glyph_metrics('q');
+    assert_eq!((q.left, q.right), (0.0, 1.0));
+
+    assert!(text_kerning('A', 'V') < 0.0);
+    assert_eq!(text_kerning('Á', 'V'), text_kerning('A', 'V'));
+    assert_eq!(text_kerning('V', 'V'), 0.0);
+
+    // Wide characters take up the same room either way, narrow ones don't,
+    // and kerned pairs are closer together
+    let width = |spacing, text| layout_text(20.0, spacing, text, |_, _| {});
+    assert_eq!(width(TextSpacing::Monospace, "MM"), 31);
+    assert_eq!(width(TextSpacing::Proportional, "MM"), 31);
+    assert!(width(TextSpacing::Proportional, "ll") < width(TextSpacing::Monospace, "ll"));
+    assert!(width(TextSpacing::Proportional, "AV") < width(TextSpacing::Proportional, "AM"));
+    assert_eq!(width(TextSpacing::Proportional, ""), 0);
+
+    let mut positions = Vec::new();
+    layout_text(20.0, TextSpacing::Proportional, "Mil", |c, x| positions.push((c, x)));
+    assert_eq!(positions, vec![('M', 0), ('i', 17), ('l', 30)]);
+}
@@ -2,12 +2,242 @@ extern crate minifb;
 
 use minifb::{Key, Window, WindowOptions};
 use graphics_vid::*;
+use graphics_vid::animation::{Clock, Easing, Keyframe, Track};
+use graphics_vid::bloom::Bloom;
+use graphics_vid::color::{EncodingTable, TransferFunction};
+use graphics_vid::composite::BlendMode;
+use graphics_vid::dither::{Dither, DitherMethod};
+use graphics_vid::math::{Mat4, Vec3};
+use graphics_vid::font::Font;
+use graphics_vid::glyph_cache::{GlyphCache, TextStyle};
+use graphics_vid::text::TextLayout;
+use graphics_vid::effect::{ChromaticAberration, Effect, EffectChain, FilmGrain, Scanlines, Vignette};
+use graphics_vid::png::write_png;
+use graphics_vid::tonemap::{ToneMap, ToneMapOperator};
+use graphics_vid::wireframe::{Camera, DepthCue, Mesh};
+use graphics_vid::y4m::Y4mWriter;
+
+use std::cell::RefCell;
+use std::f32::consts::PI;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
 
 const WIDTH: usize = 1920;
 const HEIGHT: usize = 1080;
+const FRAME_RATE: u32 = 60;
+
+struct Options {
+    png_dir: Option<String>,
+    // "-" means stdout
+    y4m_path: Option<String>,
+    frames: i32,
+    transfer: TransferFunction,
+    tone_map: ToneMap,
+    dither: Option<DitherMethod>,
+    // TrueType font for the title, which isn't drawn without one
+    font_path: Option<String>,
+}
+
+fn parse_options() -> Options {
+    let mut options = Options {
+        png_dir: None,
+        y4m_path: None,
+        frames: 600,
+        transfer: TransferFunction::Srgb,
+        tone_map: ToneMap::new(ToneMapOperator::Clamp),
+        dither: None,
+        font_path: None,
+    };
+
+    let mut args = std::env::args().skip(1).peekable();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--png" => {
+                let dir = match args.next() {
+                    Some(dir) if !dir.starts_with("--") => dir,
+                    _ => usage(),
+                };
+                options.png_dir = Some(dir);
+            },
+            "--y4m" => {
+                // The output path is optional so that `--y4m | ffmpeg ...` works
+                let path = match args.peek() {
+                    Some(next) if !next.starts_with("--") => args.next().unwrap(),
+                    _ => "-".to_string(),
+                };
+                options.y4m_path = Some(path);
+            },
+            "--frames" => {
+                options.frames = args.next()
+                    .and_then(|frames| frames.parse().ok())
+                    .unwrap_or_else(|| usage())
+            },
+            "--transfer" => {
+                options.transfer = args.next()
+                    .and_then(|transfer| parse_transfer(&transfer))
+                    .unwrap_or_else(|| usage())
+            },
+            "--tonemap" => {
+                options.tone_map.operator = args.next()
+                    .and_then(|operator| parse_tone_map_operator(&operator))
+                    .unwrap_or_else(|| usage())
+            },
+            "--dither" => {
+                options.dither = match args.next().as_deref() {
+                    Some("none") => None,
+                    Some("bayer") => Some(DitherMethod::Bayer),
+                    Some("blue-noise") => Some(DitherMethod::BlueNoise),
+                    Some("error-diffusion") => Some(DitherMethod::ErrorDiffusion),
+                    _ => usage(),
+                }
+            },
+            "--font" => options.font_path = Some(args.next().unwrap_or_else(|| usage())),
+            "--exposure" => {
+                options.tone_map.exposure = args.next()
+                    .and_then(|exposure| exposure.parse().ok())
+                    .unwrap_or_else(|| usage())
+            },
+            _ => usage(),
+        }
+    }
+
+    options
+}
+
+// "srgb", "rec709" or "gamma=<gamma>"
+fn parse_transfer(name: &str) -> Option<TransferFunction> {
+    match name {
+        "srgb" => Some(TransferFunction::Srgb),
+        "rec709" => Some(TransferFunction::Rec709),
+        _ => {
+            let gamma: f32 = name.strip_prefix("gamma=")?.parse().ok()?;
+            if gamma > 0.0 { Some(TransferFunction::Gamma(gamma)) } else { None }
+        },
+    }
+}
+
+// "clamp", "reinhard", "reinhard=<white point>" or "aces"
+fn parse_tone_map_operator(name: &str) -> Option<ToneMapOperator> {
+    match name {
+        "clamp" => Some(ToneMapOperator::Clamp),
+        "reinhard" => Some(ToneMapOperator::Reinhard { white: f32::INFINITY }),
+        "aces" => Some(ToneMapOperator::AcesFilmic),
+        _ => {
+            let white: f32 = name.strip_prefix("reinhard=")?.parse().ok()?;
+            if white > 0.0 { Some(ToneMapOperator::Reinhard { white }) } else { None }
+        },
+    }
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "Usage: graphics_vid [--png <output_dir>] [--y4m [<output_file>]] [--frames <count>] \
+         [--transfer srgb|rec709|gamma=<gamma>] \
+         [--tonemap clamp|reinhard|reinhard=<white>|aces] [--exposure <stops>] \
+         [--dither none|bayer|blue-noise|error-diffusion] [--font <font.ttf>]"
+    );
+    std::process::exit(2);
+}
 
 fn main() {
-    let mut buffer: Vec<(f32,f32,f32,f32)> = vec![(0.0, 0.0, 0.0, 1.0); WIDTH * HEIGHT];
+    let options = parse_options();
+
+    let title_font = options.font_path.as_ref().map(|path| {
+        Font::open(path).unwrap_or_else(|e| {
+            eprintln!("Failed to load font {}: {}", path, e);
+            std::process::exit(1);
+        })
+    });
+    let scene = Scene::new(title_font);
+
+    let result = match (&options.png_dir, &options.y4m_path) {
+        (Some(_), Some(_)) => usage(),
+        (Some(dir), None) => render_png_sequence(Path::new(dir), &scene, &options),
+        (None, Some(path)) => render_y4m(path, &scene, &options),
+        (None, None) => {
+            run_window(&scene, &options.tone_map);
+            Ok(())
+        },
+    };
+
+    if let Err(e) = result {
+        eprintln!("Failed to render frames: {}", e);
+        std::process::exit(1);
+    }
+}
+
+// Render frames 0..frames without opening a window, writing each one as
+// frame_NNNNN.png in dir. Frames are FRAME_RATE-ths of a second apart no
+// matter how long they take to render.
+fn render_png_sequence(dir: &Path, scene: &Scene, options: &Options) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    let mut clock = Clock::fixed(FRAME_RATE as f64);
+    let mut canvas = Canvas::new(WIDTH, HEIGHT);
+    let mut ibuffer: Vec<u32> = vec![0; WIDTH * HEIGHT];
+    let table = EncodingTable::new(options.transfer);
+    let dither = options.dither.map(Dither::new);
+
+    for frame in 0..options.frames {
+        let time = clock.time();
+        scene.draw(&mut canvas, time);
+        canvas.tone_map(&options.tone_map);
+        scene.post.apply(&mut canvas, time);
+        encode_frame(&canvas, &table, dither.as_ref(), &mut ibuffer);
+
+        let path = dir.join(format!("frame_{:05}.png", frame));
+        write_png(BufWriter::new(File::create(&path)?), &ibuffer, WIDTH, HEIGHT)?;
+        println!("Wrote {}", path.display());
+
+        clock.tick();
+    }
+
+    Ok(())
+}
+
+// Render frames 0..frames as a single Y4M stream. Progress goes to stderr
+// since stdout may be the video itself.
+fn render_y4m(path: &str, scene: &Scene, options: &Options) -> io::Result<()> {
+    let output: Box<dyn Write> = if path == "-" {
+        Box::new(io::stdout())
+    } else {
+        Box::new(File::create(path)?)
+    };
+
+    let mut clock = Clock::fixed(FRAME_RATE as f64);
+    let mut canvas = Canvas::new(WIDTH, HEIGHT);
+    let mut ibuffer: Vec<u32> = vec![0; WIDTH * HEIGHT];
+    let mut writer = Y4mWriter::new(BufWriter::new(output), WIDTH, HEIGHT, (FRAME_RATE, 1))?;
+    let table = EncodingTable::new(options.transfer);
+    let dither = options.dither.map(Dither::new);
+
+    for frame in 0..options.frames {
+        let time = clock.time();
+        scene.draw(&mut canvas, time);
+        canvas.tone_map(&options.tone_map);
+        scene.post.apply(&mut canvas, time);
+        encode_frame(&canvas, &table, dither.as_ref(), &mut ibuffer);
+        writer.write_frame(&ibuffer)?;
+        eprintln!("Wrote frame {}", frame);
+
+        clock.tick();
+    }
+
+    writer.flush()
+}
+
+// Turn a finished frame into 0x00RRGGBB pixels for output
+fn encode_frame(canvas: &Canvas, table: &EncodingTable, dither: Option<&Dither>, ibuffer: &mut Vec<u32>) {
+    match dither {
+        None => canvas.encode(table, ibuffer),
+        Some(dither) => canvas.encode_dithered(table, dither, ibuffer),
+    }
+}
+
+fn run_window(scene: &Scene, tone_map: &ToneMap) {
+    let clock = Clock::real_time();
+    let mut canvas = Canvas::new(WIDTH, HEIGHT);
     let mut ibuffer: Vec<u32> = vec![0; WIDTH * HEIGHT];
 
     let window_options = WindowOptions {
@@ -21,146 +251,240 @@ fn main() {
     // Limit to max ~60 fps update rate
     window.limit_update_rate(Some(std::time::Duration::from_micros(16666)));
 
-    let mut t = 0;
     while window.is_open() && !window.is_key_down(Key::Escape) {
-        clear((0.0,0.0,0.0,1.0), &mut buffer);
-
         let mouse_posf = window.get_mouse_pos(minifb::MouseMode::Pass).unwrap_or((0.0, 0.0));
         let mouse_posi = (
             mouse_posf.0.round() as i32,
             mouse_posf.1.round() as i32,
         );
 
-        fn distort((x,y): (i32, i32)) -> (i32, i32) {
-            // As y gets closer to 0, x gets closer to the midpoint (WIDTH/2)
-            let distortion = y as f32 / HEIGHT as f32;
-            let xf = (x - (WIDTH/2) as i32) as f32 * distortion + (WIDTH/2) as f32;
-            let yf = (y - (HEIGHT/2) as i32) as f32 * distortion + (HEIGHT/2) as f32;
+        let frame_start = std::time::Instant::now();
+        let time = clock.time();
+        scene.draw(&mut canvas, time);
+        canvas.tone_map(tone_map);
+        scene.post.apply(&mut canvas, time);
+
+        //canvas.wu_line((1.0, 1.0, 1.0, 1.0), (WIDTH as i32/2, HEIGHT as i32/2), mouse_posi);
+
+        canvas.gamma_correct(&mut ibuffer);
+
+        let frame_time = frame_start.elapsed();
+        println!("Frame time: {:?}", frame_time);
 
-            (xf.round() as i32, yf.round() as i32)
+        // We unwrap here as we want this code to exit if it fails. Real applications may want to handle this in a different way
+        window.update_with_buffer(&ibuffer, WIDTH, HEIGHT).unwrap();
+    }
+}
+
+// The demo scene. Everything in it is a function of time in seconds, so
+// the live preview and offline renders produce the same frames.
+struct Scene {
+    // How far the grid has scrolled towards the viewer, in grid units.
+    // The grid repeats every 50 units, so this loops.
+    grid_scroll: Track<f32>,
+    // Looks at the grid from just above it. The grid fades out into the
+    // distance with the camera's depth cue.
+    camera: Camera,
+    // Angle of the spinning model in radians
+    model_spin: Track<f32>,
+    model: Mesh,
+    // Glow around the grid and the model
+    grid_bloom: Bloom,
+    // Effects run on every frame after tone mapping, for an old monitor
+    // look
+    post: EffectChain,
+    title_font: Option<Font>,
+    // Drawing only needs the scene borrowed, but the glyph cache fills up
+    // as it goes
+    glyphs: RefCell<GlyphCache>,
+}
+
+impl Scene {
+    fn new(title_font: Option<Font>) -> Scene {
+        Scene {
+            grid_scroll: Track::new(vec![
+                Keyframe::new(0.0, 0.0, Easing::Linear),
+                Keyframe::new(50.0 / 60.0, 50.0, Easing::Linear),
+            ]),
+            camera: Camera {
+                depth_cue: Some(DepthCue { near: 1000.0, far: 5000.0, falloff: 3.0 }),
+                ..Camera::new(Vec3::new(0.0, -540.0, 0.0), Vec3::new(0.0, -465.0, 1000.0), PI / 3.0)
+            },
+            model_spin: Track::new(vec![
+                Keyframe::new(0.0, 0.0, Easing::Linear),
+                Keyframe::new(8.0, 2.0 * PI, Easing::Linear),
+            ]),
+            model: Mesh::icosahedron(250.0),
+            grid_bloom: Bloom::new(0.25, 1.5, 24.0),
+            post: EffectChain::new()
+                .with(ChromaticAberration::new(2.0))
+                .with(Scanlines::new(0.3, 3.0))
+                .with(Vignette {
+                    strength: Track::new(vec![
+                        Keyframe::new(0.0, 0.0, Easing::EaseOut),
+                        Keyframe::new(1.0, 0.6, Easing::Linear),
+                    ]),
+                    ..Vignette::new(0.6, 0.4, 0.6)
+                })
+                .with(FilmGrain::new(0.05)),
+            title_font,
+            glyphs: RefCell::new(GlyphCache::new()),
         }
+    }
 
-        let frame_start = std::time::Instant::now();
-        for y in 1..=25 {
-            for x in 1..=36 {
-                let real_y = y * 50 + (t%50);
-                let value = match real_y {
-                    (0..=950) => (real_y as f32 / 1080.0).powi(2),
-                    _ => {
-                        let t = (1000 - real_y) as f32 / 50.0;
-                        clamp(interpf(t, 0.0, 1.0), 0.0, 1.0)
-                    },
+    fn draw(&self, canvas: &mut Canvas, time: f32) {
+        canvas.clear((0.0,0.0,0.0,1.0));
+        canvas.enable_depth_buffer();
+
+        let t = self.grid_scroll.evaluate_looped(time);
+
+        // A spinning icosahedron hovering over the grid. It's written into
+        // the depth buffer first so it hides the grid lines behind it.
+        let spin = self.model_spin.evaluate_looped(time);
+        let model = Mat4::translate(1200.0, -300.0, 3000.0) * Mat4::rotate_y(spin) * Mat4::rotate_x(spin * 0.5);
+        canvas.draw_mesh_depth(&self.camera, &self.model, &model);
+
+        // The grid lies flat on the ground in front of the camera, 50 units
+        // to a cell, with z going away from the camera
+        const ROWS: i32 = 80;
+        const COLUMNS: i32 = 36;
+        const GRID_START: f32 = 1014.0;
+
+        // Brightness of the grid row whose near edge is at distance d. Rows
+        // fade in as they scroll in from the front, and the depth cue fades
+        // them out with distance.
+        let row_value = |d: f32| clamp(d / 50.0, 0.0, 1.0);
+
+        // The grid is drawn additively so it glows where lines meet. Each
+        // cell draws its near, left and diagonal lines, and the far and
+        // right lines only where no other cell will draw them, since lines
+        // drawn twice would be twice as bright.
+        for y in 0..ROWS {
+            let d = (y * 50) as f32 - t;
+            let value = row_value(d);
+            if value == 0.0 {
+                continue;
+            }
+            let color = (
+                value,
+                0.0,
+                value,
+                1.0,
+            );
+            let last_row = y == ROWS - 1;
+
+            for x in -COLUMNS / 2..COLUMNS / 2 {
+                let (left, right) = ((x * 50) as f32, (x * 50 + 50) as f32);
+                let (near, far) = (GRID_START + d, GRID_START + d + 50.0);
+                let mut line = |(x0, z0), (x1, z1)| {
+                    canvas.line_3d(color, BlendMode::Add, &self.camera, Vec3::new(x0, 0.0, z0), Vec3::new(x1, 0.0, z1));
                 };
 
-                let color = (
-                    value,
-                    0.0,
-                    value,
-                    1.0,
-                );
-
-                if value != 0.0 {
-                    wu_line(color, distort(((x+0)*50, (y+0)*50 + (t%50))), distort(((x+1)*50, (y+0)*50 + (t%50))), WIDTH, &mut buffer);
-                    wu_line(color, distort(((x+1)*50, (y+0)*50 + (t%50))), distort(((x+1)*50, (y+1)*50 + (t%50))), WIDTH, &mut buffer);
-                    wu_line(color, distort(((x+1)*50, (y+1)*50 + (t%50))), distort(((x+0)*50, (y+1)*50 + (t%50))), WIDTH, &mut buffer);
-                    wu_line(color, distort(((x+0)*50, (y+1)*50 + (t%50))), distort(((x+0)*50, (y+0)*50 + (t%50))), WIDTH, &mut buffer);
-                    wu_line(color, distort(((x+0)*50, (y+1)*50 + (t%50))), distort(((x+1)*50, (y+0)*50 + (t%50))), WIDTH, &mut buffer);
+                line((left, near), (right, near));
+                line((left, near), (left, far));
+                line((left, near), (right, far));
+                if x == COLUMNS / 2 - 1 {
+                    line((right, near), (right, far));
+                }
+                if last_row {
+                    line((left, far), (right, far));
                 }
             }
         }
 
-        draw_text((1.0, 1.0, 1.0), (750,250), 40.0, "0123456789", WIDTH, &mut buffer);
-        draw_text((1.0, 1.0, 1.0), (100,325), 40.0, "THE QUICK BROWN FOX JUMPS OVER THE LAZY DOG", WIDTH, &mut buffer);
-        draw_text((1.0, 1.0, 1.0), (100,400), 40.0, "the quick brown fox jumps over the lazy dog", WIDTH, &mut buffer);
+        // The model is drawn at full brightness, hiding its back edges
+        let camera = Camera { depth_cue: None, ..self.camera };
+        canvas.draw_mesh((0.0, 0.8, 1.0, 1.0), BlendMode::Add, &camera, &self.model, &model);
+
+        canvas.bloom(&self.grid_bloom);
+
+        if let Some(font) = &self.title_font {
+            canvas.fill_text((1.0, 1.0, 1.0), BlendMode::SourceOver, font, (100.0, 1030.0), 72.0, "Graphics Vid");
+        }
 
-        for (line_text, line_num) in r##"
-draw_text(
+        // Most of the text is the same every frame, so its glyphs are
+        // rasterized once and kept
+        let mut glyphs = self.glyphs.borrow_mut();
+        canvas.draw_text_cached(&mut glyphs, (1.0, 1.0, 1.0), BlendMode::SourceOver, (750,250), TextStyle::stroked(40.0, 3.0), "0123456789");
+        canvas.draw_text_cached(&mut glyphs, (1.0, 1.0, 1.0), BlendMode::SourceOver, (100,325), TextStyle::new(40.0), "THE QUICK BROWN FOX JUMPS OVER THE LAZY DOG");
+        canvas.draw_text_cached(&mut glyphs, (1.0, 1.0, 1.0), BlendMode::SourceOver, (100,400), TextStyle { spacing: TextSpacing::Proportional, ..TextStyle::new(40.0) }, "the quick brown fox jumps over the lazy dog");
+
+        let code = TextLayout::new(20.0);
+        let code_text = r##"
+canvas.draw_text(
     (1.0, 1.0, 1.0),
+    BlendMode::SourceOver,
     (60,150),
     20.0,
     " !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~",
-    WIDTH,
-    &mut buffer
 );
-        "##.split("\n").zip(0..=10000)
-        {
-            draw_text((1.0, 1.0, 1.0), (100,450+line_num*30), 20.0, line_text, WIDTH, &mut buffer);
+        "##;
+        for run in code.layout((100,450), code_text) {
+            canvas.draw_text_cached(&mut glyphs, (1.0, 1.0, 1.0), BlendMode::SourceOver, run.origin, TextStyle::new(code.size), run.text);
         }
 
-        draw_text((1.0, 1.0, 1.0), (10,89), 8.0, " 6 pt: ", WIDTH, &mut buffer);
-        draw_text(
+        canvas.draw_text_cached(&mut glyphs, (1.0, 1.0, 1.0), BlendMode::SourceOver, (10,89), TextStyle::new(8.0), " 6 pt: ");
+        canvas.draw_text_cached(
+            &mut glyphs,
             (1.0, 1.0, 1.0),
+            BlendMode::SourceOver,
             (60,89),
-            6.0,
+            TextStyle::new(6.0),
             "!\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~",
-            WIDTH,
-            &mut buffer
         );
-        draw_text((1.0, 1.0, 1.0), (10,100), 8.0, " 8 pt: ", WIDTH, &mut buffer);
-        draw_text(
+        canvas.draw_text_cached(&mut glyphs, (1.0, 1.0, 1.0), BlendMode::SourceOver, (10,100), TextStyle::new(8.0), " 8 pt: ");
+        canvas.draw_text_cached(
+            &mut glyphs,
             (1.0, 1.0, 1.0),
+            BlendMode::SourceOver,
             (60,100),
-            8.0,
+            TextStyle::new(8.0),
             "!\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~",
-            WIDTH,
-            &mut buffer
         );
-        draw_text((1.0, 1.0, 1.0), (10,115), 8.0, "10 pt: ", WIDTH, &mut buffer);
-        draw_text(
+        canvas.draw_text_cached(&mut glyphs, (1.0, 1.0, 1.0), BlendMode::SourceOver, (10,115), TextStyle::new(8.0), "10 pt: ");
+        canvas.draw_text_cached(
+            &mut glyphs,
             (1.0, 1.0, 1.0),
+            BlendMode::SourceOver,
             (60,114),
-            10.0,
+            TextStyle::new(10.0),
             "!\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~",
-            WIDTH,
-            &mut buffer
         );
-        draw_text((1.0, 1.0, 1.0), (10,133), 8.0, "12 pt: ", WIDTH, &mut buffer);
-        draw_text(
+        canvas.draw_text_cached(&mut glyphs, (1.0, 1.0, 1.0), BlendMode::SourceOver, (10,133), TextStyle::new(8.0), "12 pt: ");
+        canvas.draw_text_cached(
+            &mut glyphs,
             (1.0, 1.0, 1.0),
+            BlendMode::SourceOver,
             (60,131),
-            12.0,
+            TextStyle::new(12.0),
             "!\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~",
-            WIDTH,
-            &mut buffer
         );
-        draw_text((1.0, 1.0, 1.0), (10,154), 8.0, "14 pt: ", WIDTH, &mut buffer);
-        draw_text(
+        canvas.draw_text_cached(&mut glyphs, (1.0, 1.0, 1.0), BlendMode::SourceOver, (10,154), TextStyle::new(8.0), "14 pt: ");
+        canvas.draw_text_cached(
+            &mut glyphs,
             (1.0, 1.0, 1.0),
+            BlendMode::SourceOver,
             (60,151),
-            14.0,
+            TextStyle::new(14.0),
             "!\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~",
-            WIDTH,
-            &mut buffer
         );
-        draw_text((1.0, 1.0, 1.0), (10,178), 8.0, "16 pt: ", WIDTH, &mut buffer);
-        draw_text(
+        canvas.draw_text_cached(&mut glyphs, (1.0, 1.0, 1.0), BlendMode::SourceOver, (10,178), TextStyle::new(8.0), "16 pt: ");
+        canvas.draw_text_cached(
+            &mut glyphs,
             (1.0, 1.0, 1.0),
+            BlendMode::SourceOver,
             (60,174),
-            16.0,
+            TextStyle::new(16.0),
             "!\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~",
-            WIDTH,
-            &mut buffer
         );
-        draw_text((1.0, 1.0, 1.0), (10,205), 8.0, "18 pt: ", WIDTH, &mut buffer);
-        draw_text(
+        canvas.draw_text_cached(&mut glyphs, (1.0, 1.0, 1.0), BlendMode::SourceOver, (10,205), TextStyle::new(8.0), "18 pt: ");
+        canvas.draw_text_cached(
+            &mut glyphs,
             (1.0, 1.0, 1.0),
+            BlendMode::SourceOver,
             (60,200),
-            18.0,
+            TextStyle::new(18.0),
             "!\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~",
-            WIDTH,
-            &mut buffer
         );
-
-        //wu_line((1.0, 1.0, 1.0, 1.0), (WIDTH as i32/2, HEIGHT as i32/2), mouse_posi, WIDTH, &mut buffer);
-
-        gamma_correct_buffer(&buffer, &mut ibuffer);
-
-        let frame_time = frame_start.elapsed();
-        println!("Frame time: {:?}", frame_time);
-
-        // We unwrap here as we want this code to exit if it fails. Real applications may want to handle this in a different way
-        window.update_with_buffer(&ibuffer, WIDTH, HEIGHT).unwrap();
-
-        t += 1;
     }
 }
@@ -0,0 +1,410 @@
+use std::ops::{Add, Mul, Neg, Sub};
+
+// Small vectors and matrices for transforming geometry. Coordinates follow
+// the canvas: x goes right and y goes down, so positive angles turn
+// clockwise on screen. In 3D, z goes into the screen, away from the viewer.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec4 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+// Component-wise arithmetic, which is the same for every vector size
+macro_rules! vector_ops {
+    ($name:ident { $($field:ident),+ }) => {
+        impl $name {
+            pub fn dot(self, other: $name) -> f32 {
+                0.0 $(+ self.$field * other.$field)+
+            }
+
+            pub fn length(self) -> f32 {
+                self.dot(self).sqrt()
+            }
+
+            // The vector scaled to a length of 1.0, or unchanged if it has
+            // no length
+            pub fn normalize(self) -> $name {
+                let length = self.length();
+                if length > 0.0 { self * (1.0 / length) } else { self }
+            }
+
+            pub fn lerp(self, other: $name, t: f32) -> $name {
+                self + (other - self) * t
+            }
+        }
+
+        impl Add for $name {
+            type Output = $name;
+            fn add(self, other: $name) -> $name {
+                $name { $($field: self.$field + other.$field),+ }
+            }
+        }
+
+        impl Sub for $name {
+            type Output = $name;
+            fn sub(self, other: $name) -> $name {
+                $name { $($field: self.$field - other.$field),+ }
+            }
+        }
+
+        impl Mul<f32> for $name {
+            type Output = $name;
+            fn mul(self, scale: f32) -> $name {
+                $name { $($field: self.$field * scale),+ }
+            }
+        }
+
+        impl Neg for $name {
+            type Output = $name;
+            fn neg(self) -> $name {
+                $name { $($field: -self.$field),+ }
+            }
+        }
+    };
+}
+
+vector_ops!(Vec2 { x, y });
+vector_ops!(Vec3 { x, y, z });
+vector_ops!(Vec4 { x, y, z, w });
+
+impl Vec2 {
+    pub fn new(x: f32, y: f32) -> Vec2 {
+        Vec2 { x, y }
+    }
+
+    // Length of the cross product of the two vectors extended to 3D.
+    // Positive when other is clockwise from self on screen.
+    pub fn cross(self, other: Vec2) -> f32 {
+        self.x * other.y - self.y * other.x
+    }
+}
+
+impl Vec3 {
+    pub fn new(x: f32, y: f32, z: f32) -> Vec3 {
+        Vec3 { x, y, z }
+    }
+
+    pub fn cross(self, other: Vec3) -> Vec3 {
+        Vec3 {
+            x: self.y * other.z - self.z * other.y,
+            y: self.z * other.x - self.x * other.z,
+            z: self.x * other.y - self.y * other.x,
+        }
+    }
+
+    pub fn extend(self, w: f32) -> Vec4 {
+        Vec4::new(self.x, self.y, self.z, w)
+    }
+}
+
+impl Vec4 {
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Vec4 {
+        Vec4 { x, y, z, w }
+    }
+
+    pub fn truncate(self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+
+    // Divide through by w to turn homogeneous coordinates back into a point
+    pub fn project(self) -> Vec3 {
+        Vec3::new(self.x / self.w, self.y / self.w, self.z / self.w)
+    }
+}
+
+// Drawing calls take points as tuples, so convert both ways
+impl From<(f32, f32)> for Vec2 {
+    fn from((x, y): (f32, f32)) -> Vec2 {
+        Vec2 { x, y }
+    }
+}
+
+impl From<Vec2> for (f32, f32) {
+    fn from(v: Vec2) -> (f32, f32) {
+        (v.x, v.y)
+    }
+}
+
+impl From<(f32, f32, f32)> for Vec3 {
+    fn from((x, y, z): (f32, f32, f32)) -> Vec3 {
+        Vec3 { x, y, z }
+    }
+}
+
+impl From<Vec3> for (f32, f32, f32) {
+    fn from(v: Vec3) -> (f32, f32, f32) {
+        (v.x, v.y, v.z)
+    }
+}
+
+// A 3x3 matrix for 2D transforms, applied to column vectors (x, y, 1).
+// Rows are stored one after another, so m[row][column].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat3 {
+    pub m: [[f32; 3]; 3],
+}
+
+impl Mat3 {
+    pub fn identity() -> Mat3 {
+        Mat3 {
+            m: [
+                [1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+                [0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    pub fn translate(x: f32, y: f32) -> Mat3 {
+        Mat3 {
+            m: [
+                [1.0, 0.0, x],
+                [0.0, 1.0, y],
+                [0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    // Rotate clockwise on screen by angle radians around the origin
+    pub fn rotate(angle: f32) -> Mat3 {
+        let (s, c) = angle.sin_cos();
+        Mat3 {
+            m: [
+                [c, -s, 0.0],
+                [s, c, 0.0],
+                [0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    pub fn scale(x: f32, y: f32) -> Mat3 {
+        Mat3 {
+            m: [
+                [x, 0.0, 0.0],
+                [0.0, y, 0.0],
+                [0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    // Transform a point, dividing through if the bottom row makes this a
+    // projective transform
+    pub fn transform_point(&self, p: Vec2) -> Vec2 {
+        let m = &self.m;
+        let x = m[0][0] * p.x + m[0][1] * p.y + m[0][2];
+        let y = m[1][0] * p.x + m[1][1] * p.y + m[1][2];
+        let w = m[2][0] * p.x + m[2][1] * p.y + m[2][2];
+        Vec2::new(x / w, y / w)
+    }
+
+    // Transform a direction, which unlike a point isn't moved by
+    // translations
+    pub fn transform_vector(&self, v: Vec2) -> Vec2 {
+        let m = &self.m;
+        Vec2::new(m[0][0] * v.x + m[0][1] * v.y, m[1][0] * v.x + m[1][1] * v.y)
+    }
+
+    // The same transform working on the z = 0 plane in 3D
+    pub fn to_mat4(&self) -> Mat4 {
+        let m = &self.m;
+        Mat4 {
+            m: [
+                [m[0][0], m[0][1], 0.0, m[0][2]],
+                [m[1][0], m[1][1], 0.0, m[1][2]],
+                [0.0, 0.0, 1.0, 0.0],
+                [m[2][0], m[2][1], 0.0, m[2][2]],
+            ],
+        }
+    }
+}
+
+// Applies the right hand transform first, then the left
+impl Mul for Mat3 {
+    type Output = Mat3;
+    fn mul(self, other: Mat3) -> Mat3 {
+        let mut m = [[0.0; 3]; 3];
+        for (row, out) in m.iter_mut().enumerate() {
+            for (column, value) in out.iter_mut().enumerate() {
+                *value = (0..3).map(|k| self.m[row][k] * other.m[k][column]).sum();
+            }
+        }
+        Mat3 { m }
+    }
+}
+
+// A 4x4 matrix for 3D and projective transforms, applied to column vectors
+// (x, y, z, 1). Rows are stored one after another, so m[row][column].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f32; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn identity() -> Mat4 {
+        Mat4 {
+            m: [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    pub fn translate(x: f32, y: f32, z: f32) -> Mat4 {
+        Mat4 {
+            m: [
+                [1.0, 0.0, 0.0, x],
+                [0.0, 1.0, 0.0, y],
+                [0.0, 0.0, 1.0, z],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    pub fn scale(x: f32, y: f32, z: f32) -> Mat4 {
+        Mat4 {
+            m: [
+                [x, 0.0, 0.0, 0.0],
+                [0.0, y, 0.0, 0.0],
+                [0.0, 0.0, z, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    // Rotate by angle radians around the x axis, turning y towards z. With
+    // y going down the screen, positive angles tip the top of the screen
+    // towards the viewer.
+    pub fn rotate_x(angle: f32) -> Mat4 {
+        let (s, c) = angle.sin_cos();
+        Mat4 {
+            m: [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, c, -s, 0.0],
+                [0.0, s, c, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    // Rotate by angle radians around the y axis, turning z towards x
+    pub fn rotate_y(angle: f32) -> Mat4 {
+        let (s, c) = angle.sin_cos();
+        Mat4 {
+            m: [
+                [c, 0.0, s, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [-s, 0.0, c, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    // Rotate by angle radians around the z axis, turning x towards y. This
+    // is the same as the 2D rotation.
+    pub fn rotate_z(angle: f32) -> Mat4 {
+        Mat3::rotate(angle).to_mat4()
+    }
+
+    // Perspective projection for a viewer at the origin looking along +z,
+    // onto a canvas of the given size in pixels. fov_y is the angle in
+    // radians between the top and bottom of the canvas. The point straight
+    // ahead lands in the middle of the canvas, and after dividing through
+    // by w, z is 0.0 at the near distance and 1.0 at the far distance, for
+    // depth testing.
+    pub fn perspective(fov_y: f32, (width, height): (f32, f32), near: f32, far: f32) -> Mat4 {
+        let focal = height / 2.0 / (fov_y / 2.0).tan();
+        let depth = far / (far - near);
+        Mat4 {
+            m: [
+                [focal, 0.0, width / 2.0, 0.0],
+                [0.0, focal, height / 2.0, 0.0],
+                [0.0, 0.0, depth, -depth * near],
+                [0.0, 0.0, 1.0, 0.0],
+            ],
+        }
+    }
+
+    pub fn transform(&self, v: Vec4) -> Vec4 {
+        let row = |r: &[f32; 4]| r[0] * v.x + r[1] * v.y + r[2] * v.z + r[3] * v.w;
+        Vec4::new(row(&self.m[0]), row(&self.m[1]), row(&self.m[2]), row(&self.m[3]))
+    }
+
+    // Transform a point, dividing through by w
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        self.transform(p.extend(1.0)).project()
+    }
+
+    // Transform a direction, which unlike a point isn't moved by
+    // translations or projected
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        self.transform(v.extend(0.0)).truncate()
+    }
+}
+
+// Applies the right hand transform first, then the left
+impl Mul for Mat4 {
+    type Output = Mat4;
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (row, out) in m.iter_mut().enumerate() {
+            for (column, value) in out.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[row][k] * other.m[k][column]).sum();
+            }
+        }
+        Mat4 { m }
+    }
+}
+
+#[test]
+fn test_math() {
+    use std::f32::consts::FRAC_PI_2;
+
+    let close = |a: Vec3, b: Vec3| (a - b).length() < 1e-4;
+
+    assert_eq!(Vec3::new(1.0, 0.0, 0.0).cross(Vec3::new(0.0, 1.0, 0.0)), Vec3::new(0.0, 0.0, 1.0));
+    assert_eq!(Vec2::new(3.0, 4.0).length(), 5.0);
+    assert_eq!(Vec2::new(3.0, 4.0).normalize(), Vec2::new(0.6, 0.8));
+    assert_eq!(Vec2::new(0.0, 0.0).normalize(), Vec2::new(0.0, 0.0));
+
+    // Positive rotations turn x towards y, which is clockwise on screen,
+    // and the right hand transform happens first
+    let m = Mat3::translate(10.0, 0.0) * Mat3::rotate(FRAC_PI_2);
+    let p = m.transform_point(Vec2::new(1.0, 0.0));
+    assert!((p - Vec2::new(10.0, 1.0)).length() < 1e-6);
+    assert!((m.transform_vector(Vec2::new(1.0, 0.0)) - Vec2::new(0.0, 1.0)).length() < 1e-6);
+    assert_eq!(Mat3::scale(2.0, 3.0).to_mat4().transform_point(Vec3::new(1.0, 1.0, 5.0)), Vec3::new(2.0, 3.0, 5.0));
+
+    assert!(close(Mat4::rotate_x(FRAC_PI_2).transform_point(Vec3::new(0.0, 1.0, 0.0)), Vec3::new(0.0, 0.0, 1.0)));
+    assert!(close(Mat4::rotate_y(FRAC_PI_2).transform_point(Vec3::new(0.0, 0.0, 1.0)), Vec3::new(1.0, 0.0, 0.0)));
+    assert!(close(Mat4::rotate_z(FRAC_PI_2).transform_point(Vec3::new(1.0, 0.0, 0.0)), Vec3::new(0.0, 1.0, 0.0)));
+    assert_eq!(Mat4::translate(1.0, 2.0, 3.0).transform_vector(Vec3::new(1.0, 0.0, 0.0)), Vec3::new(1.0, 0.0, 0.0));
+
+    // Perspective puts the view direction in the middle of the canvas,
+    // shrinks things with distance, and maps near and far to depths of 0
+    // and 1
+    let projection = Mat4::perspective(FRAC_PI_2, (200.0, 100.0), 1.0, 101.0);
+    assert!(close(projection.transform_point(Vec3::new(0.0, 0.0, 1.0)), Vec3::new(100.0, 50.0, 0.0)));
+    assert!(close(projection.transform_point(Vec3::new(0.0, -1.0, 1.0)), Vec3::new(100.0, 0.0, 0.0)));
+    assert!(close(projection.transform_point(Vec3::new(20.2, 0.0, 101.0)), Vec3::new(110.0, 50.0, 1.0)));
+    let a = projection.transform_point(Vec3::new(0.0, 0.0, 10.0)).z;
+    let b = projection.transform_point(Vec3::new(0.0, 0.0, 20.0)).z;
+    assert!(0.0 < a && a < b && b < 1.0);
+}
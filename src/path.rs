@@ -0,0 +1,123 @@
+use crate::bezier::{flatten_cubic, flatten_quad, CURVE_TOLERANCE};
+
+// An outline made of straight line segments, built up one point at a time
+// like an SVG or PostScript path. Coordinates are in pixels with (0.0, 0.0)
+// at the top left corner of the top left pixel.
+#[derive(Clone, Debug, Default)]
+pub struct Path {
+    subpaths: Vec<SubPath>,
+}
+
+// A run of connected points. A path starts a new subpath on every move_to.
+#[derive(Clone, Debug, Default)]
+pub struct SubPath {
+    pub points: Vec<(f32, f32)>,
+    pub closed: bool,
+}
+
+impl Path {
+    pub fn new() -> Path {
+        Path {
+            subpaths: Vec::new(),
+        }
+    }
+
+    pub fn subpaths(&self) -> &[SubPath] {
+        &self.subpaths
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subpaths.iter().all(|subpath| subpath.points.is_empty())
+    }
+
+    // The point the next line_to starts from
+    pub fn current_point(&self) -> Option<(f32, f32)> {
+        self.subpaths.last().and_then(|subpath| subpath.points.last().copied())
+    }
+
+    // Start a new subpath at p
+    pub fn move_to(&mut self, p: (f32, f32)) -> &mut Path {
+        self.subpaths.push(SubPath {
+            points: vec![p],
+            closed: false,
+        });
+        self
+    }
+
+    // Add a line from the current point to p. Without a current point this
+    // acts like move_to.
+    pub fn line_to(&mut self, p: (f32, f32)) -> &mut Path {
+        match self.subpaths.last_mut() {
+            Some(subpath) if !subpath.closed => subpath.points.push(p),
+            _ => {
+                // Like SVG, drawing after a close continues from where the
+                // closed subpath started
+                let start = self.subpaths.last().map(|subpath| subpath.points[0]);
+                match start {
+                    Some(start) => {
+                        self.move_to(start);
+                        self.subpaths.last_mut().unwrap().points.push(p);
+                    },
+                    None => {
+                        self.move_to(p);
+                    },
+                }
+            },
+        }
+        self
+    }
+
+    // Add a quadratic Bézier curve from the current point to p with the
+    // control point control. The curve is flattened into lines right away.
+    pub fn quad_to(&mut self, control: (f32, f32), p: (f32, f32)) -> &mut Path {
+        let start = self.start_curve(control);
+        let mut points = Vec::new();
+        flatten_quad(start, control, p, CURVE_TOLERANCE, &mut points);
+        for point in points {
+            self.line_to(point);
+        }
+        self
+    }
+
+    // Add a cubic Bézier curve from the current point to p with the control
+    // points control1 and control2
+    pub fn cubic_to(&mut self, control1: (f32, f32), control2: (f32, f32), p: (f32, f32)) -> &mut Path {
+        let start = self.start_curve(control1);
+        let mut points = Vec::new();
+        flatten_cubic(start, control1, control2, p, CURVE_TOLERANCE, &mut points);
+        for point in points {
+            self.line_to(point);
+        }
+        self
+    }
+
+    // Where a curve starts. Without a current point the curve starts at its
+    // first control point, like in SVG.
+    fn start_curve(&mut self, control: (f32, f32)) -> (f32, f32) {
+        match self.subpaths.last() {
+            Some(subpath) if !subpath.closed => *subpath.points.last().unwrap(),
+            _ => {
+                self.line_to(control);
+                self.current_point().unwrap()
+            },
+        }
+    }
+
+    // Join the current subpath back to its first point
+    pub fn close(&mut self) -> &mut Path {
+        if let Some(subpath) = self.subpaths.last_mut() {
+            subpath.closed = true;
+        }
+        self
+    }
+
+    // Smallest and largest coordinates of all the points, or None if the
+    // path has no points.
+    pub fn bounds(&self) -> Option<((f32, f32), (f32, f32))> {
+        let mut points = self.subpaths.iter().flat_map(|subpath| subpath.points.iter());
+        let first = *points.next()?;
+        Some(points.fold((first, first), |(min, max), &(x, y)| {
+            ((min.0.min(x), min.1.min(y)), (max.0.max(x), max.1.max(y)))
+        }))
+    }
+}
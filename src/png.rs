@@ -0,0 +1,427 @@
+use std::io::{self, Write};
+
+// PNG encoding for 0x00RRGGBB frames like the ones produced by
+// gamma_correct_buffer. Everything needed (CRC, Adler-32, zlib/deflate)
+// lives here so headless rendering doesn't pull in any extra dependencies.
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+// Encode a frame as an 8-bit RGB PNG
+pub fn encode_png(pixels: &[u32], width: usize, height: usize) -> Vec<u8> {
+    assert_eq!(pixels.len(), width * height, "pixel count doesn't match dimensions");
+
+    let mut png = Vec::new();
+    png.extend_from_slice(&PNG_SIGNATURE);
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    ihdr.push(8); // Bit depth
+    ihdr.push(2); // Colour type: RGB
+    ihdr.push(0); // Compression method: zlib
+    ihdr.push(0); // Filter method: adaptive
+    ihdr.push(0); // Interlace method: none
+    write_chunk(&mut png, b"IHDR", &ihdr);
+
+    let scanlines = filter_scanlines(pixels, width, height);
+    write_chunk(&mut png, b"IDAT", &zlib_compress(&scanlines));
+    write_chunk(&mut png, b"IEND", &[]);
+
+    png
+}
+
+pub fn write_png<W: Write>(mut writer: W, pixels: &[u32], width: usize, height: usize) -> io::Result<()> {
+    writer.write_all(&encode_png(pixels, width, height))
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let crc_start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[crc_start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// Convert the frame into PNG scanlines, each prefixed by the filter type
+// that gives the smallest sum of absolute differences for that row. This
+// is the heuristic suggested by the PNG spec, and it does very well on the
+// mostly-black frames we render.
+fn filter_scanlines(pixels: &[u32], width: usize, height: usize) -> Vec<u8> {
+    let stride = width * 3;
+    let mut out = Vec::with_capacity((stride + 1) * height);
+
+    let mut previous = vec![0u8; stride];
+    let mut current = vec![0u8; stride];
+    let mut candidates = [
+        vec![0u8; stride],
+        vec![0u8; stride],
+        vec![0u8; stride],
+        vec![0u8; stride],
+    ];
+
+    for row in pixels.chunks(width.max(1)).take(height) {
+        for (bytes, pixel) in current.chunks_mut(3).zip(row) {
+            bytes[0] = (pixel >> 16) as u8;
+            bytes[1] = (pixel >> 8) as u8;
+            bytes[2] = *pixel as u8;
+        }
+
+        for i in 0..stride {
+            let a = if i >= 3 { current[i-3] } else { 0 };
+            let b = previous[i];
+            let c = if i >= 3 { previous[i-3] } else { 0 };
+            let x = current[i];
+
+            candidates[0][i] = x;
+            candidates[1][i] = x.wrapping_sub(a);
+            candidates[2][i] = x.wrapping_sub(b);
+            candidates[3][i] = x.wrapping_sub(paeth_predictor(a, b, c));
+        }
+
+        // Filter type 0 is None, 1 is Sub, 2 is Up and 4 is Paeth
+        let (filter_type, best) = [0u8, 1, 2, 4].iter()
+            .zip(candidates.iter())
+            .min_by_key(|(_, filtered)| {
+                filtered.iter().map(|&v| (v as i8).unsigned_abs() as u64).sum::<u64>()
+            })
+            .unwrap();
+
+        out.push(*filter_type);
+        out.extend_from_slice(best);
+
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    out
+}
+
+fn paeth_predictor(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    // Table for the reflected polynomial 0xEDB88320, built at compile time
+    const TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        let mut n = 0;
+        while n < 256 {
+            let mut c = n as u32;
+            let mut k = 0;
+            while k < 8 {
+                c = if c & 1 != 0 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+                k += 1;
+            }
+            table[n] = c;
+            n += 1;
+        }
+        table
+    };
+
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc = TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    crc ^ 0xFFFF_FFFF
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+
+    let mut a = 1u32;
+    let mut b = 0u32;
+
+    // 5552 is the largest number of bytes we can sum before b could overflow
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+
+    (b << 16) | a
+}
+
+// Wrap a deflate stream in a zlib header and Adler-32 trailer
+pub fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    // CMF = 0x78 (deflate, 32K window), FLG = 0x01 (fastest, no dictionary).
+    // 0x7801 is divisible by 31 as required by the FCHECK bits.
+    let mut out = vec![0x78, 0x01];
+    out.extend_from_slice(&deflate(data));
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+// Bit sink for deflate. Deflate packs bits starting from the least
+// significant bit of each byte.
+struct BitWriter {
+    bytes: Vec<u8>,
+    bit_buffer: u64,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            bytes: Vec::new(),
+            bit_buffer: 0,
+            bit_count: 0,
+        }
+    }
+
+    fn write_bits(&mut self, value: u32, count: u32) {
+        self.bit_buffer |= (value as u64) << self.bit_count;
+        self.bit_count += count;
+        while self.bit_count >= 8 {
+            self.bytes.push(self.bit_buffer as u8);
+            self.bit_buffer >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    // Huffman codes are defined most significant bit first, so they are
+    // reversed before being packed.
+    fn write_code(&mut self, code: u32, length: u32) {
+        let reversed = code.reverse_bits() >> (32 - length);
+        self.write_bits(reversed, length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            self.bytes.push(self.bit_buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+const MAX_CHAIN: usize = 32;
+
+// Write a literal/length symbol using the fixed Huffman code (RFC 1951 3.2.6)
+fn write_fixed_literal(writer: &mut BitWriter, symbol: u16) {
+    let symbol = symbol as u32;
+    match symbol {
+        0..=143 => writer.write_code(0x30 + symbol, 8),
+        144..=255 => writer.write_code(0x190 + symbol - 144, 9),
+        256..=279 => writer.write_code(symbol - 256, 7),
+        _ => writer.write_code(0xC0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    // Find the last base which is <= the value we want to encode
+    let length_code = LENGTH_BASE.iter().rposition(|&base| base as usize <= length).unwrap();
+    write_fixed_literal(writer, 257 + length_code as u16);
+    writer.write_bits(
+        (length - LENGTH_BASE[length_code] as usize) as u32,
+        LENGTH_EXTRA[length_code] as u32,
+    );
+
+    let distance_code = DISTANCE_BASE.iter().rposition(|&base| base as usize <= distance).unwrap();
+    writer.write_code(distance_code as u32, 5);
+    writer.write_bits(
+        (distance - DISTANCE_BASE[distance_code] as usize) as u32,
+        DISTANCE_EXTRA[distance_code] as u32,
+    );
+}
+
+fn hash3(data: &[u8], i: usize) -> usize {
+    let v = (data[i] as u32) << 16 | (data[i+1] as u32) << 8 | data[i+2] as u32;
+    (v.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+}
+
+// Compress data into a single fixed-Huffman deflate block, finding matches
+// with a hash chain over the previous 32K of input.
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+
+    // BFINAL = 1, BTYPE = 01 (fixed Huffman)
+    writer.write_bits(1, 1);
+    writer.write_bits(1, 2);
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW_SIZE];
+
+    let insert = |head: &mut Vec<usize>, prev: &mut Vec<usize>, i: usize| {
+        if i + MIN_MATCH <= data.len() {
+            let h = hash3(data, i);
+            prev[i % WINDOW_SIZE] = head[h];
+            head[h] = i;
+        }
+    };
+
+    let mut i = 0;
+    while i < data.len() {
+        let mut best_length = 0;
+        let mut best_distance = 0;
+
+        if i + MIN_MATCH <= data.len() {
+            let max_length = MAX_MATCH.min(data.len() - i);
+            let mut candidate = head[hash3(data, i)];
+            let mut chain = 0;
+
+            while candidate != usize::MAX && i - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+                let length = data[candidate..]
+                    .iter()
+                    .zip(&data[i..i + max_length])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+
+                if length > best_length {
+                    best_length = length;
+                    best_distance = i - candidate;
+                    if length == max_length {
+                        break;
+                    }
+                }
+
+                let next = prev[candidate % WINDOW_SIZE];
+                // Entries in prev get overwritten as the window slides, so
+                // stop once the chain stops going backwards.
+                if next == usize::MAX || next >= candidate {
+                    break;
+                }
+                candidate = next;
+                chain += 1;
+            }
+        }
+
+        if best_length >= MIN_MATCH {
+            write_match(&mut writer, best_length, best_distance);
+            for j in i..i + best_length {
+                insert(&mut head, &mut prev, j);
+            }
+            i += best_length;
+        } else {
+            write_fixed_literal(&mut writer, data[i] as u16);
+            insert(&mut head, &mut prev, i);
+            i += 1;
+        }
+    }
+
+    // End of block
+    write_fixed_literal(&mut writer, 256);
+
+    writer.finish()
+}
+
+#[test]
+fn test_checksums() {
+    assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+}
+
+#[test]
+fn test_deflate() {
+    // Just enough of an inflater to read back the single fixed Huffman
+    // block deflate writes
+    fn inflate(zlib: &[u8]) -> Vec<u8> {
+        assert_eq!(&zlib[..2], &[0x78, 0x01]);
+        let data = &zlib[2..zlib.len() - 4];
+        let mut position = 0;
+        let mut bits = |count: u32| {
+            let mut value = 0;
+            for i in 0..count {
+                let bit = (data[position / 8] >> (position % 8)) & 1;
+                value |= (bit as u32) << i;
+                position += 1;
+            }
+            value
+        };
+        assert_eq!(bits(1), 1);
+        assert_eq!(bits(2), 1);
+
+        let mut out: Vec<u8> = Vec::new();
+        loop {
+            // Huffman codes are read most significant bit first
+            let mut code = 0;
+            for _ in 0..7 {
+                code = code << 1 | bits(1);
+            }
+            let symbol = if code <= 0x17 {
+                256 + code
+            } else {
+                code = code << 1 | bits(1);
+                match code {
+                    0x30..=0xBF => code - 0x30,
+                    0xC0..=0xC7 => 280 + code - 0xC0,
+                    _ => 144 + (code << 1 | bits(1)) - 0x190,
+                }
+            } as usize;
+
+            match symbol {
+                0..=255 => out.push(symbol as u8),
+                256 => break,
+                _ => {
+                    let code = symbol - 257;
+                    let length = LENGTH_BASE[code] as usize + bits(LENGTH_EXTRA[code] as u32) as usize;
+                    let mut code = 0;
+                    for _ in 0..5 {
+                        code = code << 1 | bits(1);
+                    }
+                    let code = code as usize;
+                    let distance = DISTANCE_BASE[code] as usize + bits(DISTANCE_EXTRA[code] as u32) as usize;
+                    assert!(distance <= out.len());
+                    for _ in 0..length {
+                        out.push(out[out.len() - distance]);
+                    }
+                },
+            }
+        }
+
+        assert_eq!(&zlib[zlib.len() - 4..], &adler32(&out).to_be_bytes());
+        out
+    }
+
+    let mut seed = 1u32;
+    let random: Vec<u8> = (0..100_000)
+        .map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) as u8
+        })
+        .collect();
+    let repetitive: Vec<u8> = b"abcabcabd".iter().cycle().take(70_000).copied().collect();
+    let runs = [vec![7; 1000], random[..20_000].to_vec(), random[..20_000].to_vec(), vec![0; 300]].concat();
+
+    for data in [&b""[..], b"a", b"aaaa", &random, &repetitive, &runs] {
+        let compressed = zlib_compress(data);
+        assert_eq!(inflate(&compressed), data);
+    }
+    assert!(zlib_compress(&repetitive).len() < 2000);
+    // The second copy of the random bytes is found 20000 bytes back
+    assert!(zlib_compress(&runs).len() < 25_000);
+}
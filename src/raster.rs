@@ -0,0 +1,359 @@
+use crate::{clamp, Canvas};
+use crate::composite::{premultiply, BlendMode};
+
+// Coverage values for a rectangular area of the canvas, from 0.0 (pixel
+// untouched) to 1.0 (pixel completely covered). Shapes are rasterized into
+// a mask first and then composited, so every pixel is blended exactly once
+// no matter how many pieces of the shape overlap it.
+#[derive(Clone, Debug)]
+pub struct Mask {
+    x: i32,
+    y: i32,
+    width: usize,
+    height: usize,
+    coverage: Vec<f32>,
+}
+
+impl Mask {
+    pub fn new(x: i32, y: i32, width: usize, height: usize) -> Mask {
+        Mask {
+            x,
+            y,
+            width,
+            height,
+            coverage: vec![0.0; width * height],
+        }
+    }
+
+    // Canvas coordinates of the top left pixel of the mask
+    pub fn origin(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn coverage(&self) -> &[f32] {
+        &self.coverage
+    }
+
+    // Coverage of a pixel in canvas coordinates. Pixels outside the mask
+    // aren't covered.
+    pub fn get(&self, x: i32, y: i32) -> f32 {
+        let (mx, my) = (x.wrapping_sub(self.x), y.wrapping_sub(self.y));
+        if in_bounds(mx, my, self.width, self.height) {
+            self.coverage[mx as usize + my as usize * self.width]
+        } else {
+            0.0
+        }
+    }
+
+    // Raise the coverage of a pixel in canvas coordinates to at least
+    // coverage. Pixels outside the mask are ignored.
+    pub fn cover(&mut self, x: i32, y: i32, coverage: f32) {
+        let (mx, my) = (x.wrapping_sub(self.x), y.wrapping_sub(self.y));
+        if in_bounds(mx, my, self.width, self.height) {
+            let pixel = &mut self.coverage[mx as usize + my as usize * self.width];
+            *pixel = pixel.max(coverage);
+        }
+    }
+}
+
+fn in_bounds(x: i32, y: i32, width: usize, height: usize) -> bool {
+    x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height
+}
+
+// Which parts of a self-intersecting or nested outline count as inside
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FillRule {
+    // Inside wherever the outline winds around the point at all
+    NonZero,
+    // Inside wherever the outline winds around the point an odd number of
+    // times, so nested shapes make holes
+    EvenOdd,
+}
+
+// Scanline rasterizer that computes exact area coverage for polygons.
+//
+// For every edge we add the signed area it sweeps into an accumulation
+// buffer, and summing each row from left to right then gives the winding
+// number weighted coverage of every pixel. This is the approach used by
+// font-rs and similar glyph rasterizers.
+pub struct Rasterizer {
+    x: i32,
+    y: i32,
+    width: usize,
+    height: usize,
+    // Two extra columns per row: edges touching the right hand side of the
+    // area write one past it, and anything right of the area is clamped to
+    // the column after that.
+    stride: usize,
+    accumulation: Vec<f32>,
+}
+
+impl Rasterizer {
+    pub fn new(x: i32, y: i32, width: usize, height: usize) -> Rasterizer {
+        let stride = width + 2;
+        Rasterizer {
+            x,
+            y,
+            width,
+            height,
+            stride,
+            accumulation: vec![0.0; stride * height],
+        }
+    }
+
+    // A rasterizer covering the part of the canvas within the bounding box
+    // from min to max (plus a pixel of margin for anti-aliasing), or None
+    // if that's entirely off the canvas.
+    pub fn for_bounds(canvas: &Canvas, min: (f32, f32), max: (f32, f32)) -> Option<Rasterizer> {
+        if !(min.0.is_finite() && min.1.is_finite() && max.0.is_finite() && max.1.is_finite()) {
+            return None;
+        }
+
+        let x0 = clamp(min.0.floor() - 1.0, 0.0, canvas.width() as f32) as i32;
+        let y0 = clamp(min.1.floor() - 1.0, 0.0, canvas.height() as f32) as i32;
+        let x1 = clamp(max.0.ceil() + 1.0, 0.0, canvas.width() as f32) as i32;
+        let y1 = clamp(max.1.ceil() + 1.0, 0.0, canvas.height() as f32) as i32;
+
+        if x1 <= x0 || y1 <= y0 {
+            return None;
+        }
+
+        Some(Rasterizer::new(x0, y0, (x1 - x0) as usize, (y1 - y0) as usize))
+    }
+
+    // Add a closed polygon. The last point is joined back to the first.
+    pub fn polygon(&mut self, points: &[(f32, f32)]) {
+        for (i, &p0) in points.iter().enumerate() {
+            let p1 = points[(i + 1) % points.len()];
+            self.line(p0, p1);
+        }
+    }
+
+    // Add a single edge, in canvas coordinates. Edges only make sense as
+    // part of closed outlines: the coverage of a pixel is the winding
+    // number of the outlines around it.
+    pub fn line(&mut self, p0: (f32, f32), p1: (f32, f32)) {
+        // Edges with NaN or infinite end points can't be put anywhere
+        if !(p0.0.is_finite() && p0.1.is_finite() && p1.0.is_finite() && p1.1.is_finite()) {
+            return;
+        }
+
+        let p0 = (p0.0 - self.x as f32, p0.1 - self.y as f32);
+        let p1 = (p1.0 - self.x as f32, p1.1 - self.y as f32);
+
+        // Whatever is to the left of the area still contributes winding to
+        // every pixel in the row, so split the edge where it crosses the
+        // left and right hand sides and flatten the outside parts onto
+        // those sides.
+        let right = self.width as f32 + 1.0;
+        let mut splits = [0.0, 1.0, 1.0, 1.0];
+        let mut count = 1;
+        for &boundary in &[0.0, right] {
+            if (p0.0 < boundary) != (p1.0 < boundary) {
+                splits[count] = (boundary - p0.0) / (p1.0 - p0.0);
+                count += 1;
+            }
+        }
+        splits[count] = 1.0;
+        splits[1..count].sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let lerp = |t: f32| (p0.0 + (p1.0 - p0.0) * t, p0.1 + (p1.1 - p0.1) * t);
+        for pair in splits[..=count].windows(2) {
+            let (a, b) = (lerp(pair[0]), lerp(pair[1]));
+            self.accumulate_line(
+                (clamp(a.0, 0.0, right), a.1),
+                (clamp(b.0, 0.0, right), b.1),
+            );
+        }
+    }
+
+    fn accumulate_line(&mut self, p0: (f32, f32), p1: (f32, f32)) {
+        let finite = p0.0.is_finite() && p0.1.is_finite() && p1.0.is_finite() && p1.1.is_finite();
+        if p0.1 == p1.1 || !finite {
+            return;
+        }
+
+        // Edges going up the screen subtract coverage, edges going down add it
+        let (direction, (x0, y0), (x1, y1)) = if p0.1 < p1.1 {
+            (1.0, p0, p1)
+        } else {
+            (-1.0, p1, p0)
+        };
+
+        let dxdy = (x1 - x0) / (y1 - y0);
+        let mut x = x0;
+        let mut y_start = y0;
+        if y_start < 0.0 {
+            x -= y_start * dxdy;
+            y_start = 0.0;
+        }
+        let y_end = y1.min(self.height as f32);
+        if y_start >= y_end {
+            return;
+        }
+
+        let max_index = self.stride as i32 - 1;
+        for y in (y_start as usize)..(y_end.ceil() as usize) {
+            let row = &mut self.accumulation[y * self.stride..(y + 1) * self.stride];
+            let mut add = |i: i32, value: f32| row[i.min(max_index) as usize] += value;
+
+            let dy = ((y + 1) as f32).min(y_end) - (y as f32).max(y_start);
+            let x_next = x + dxdy * dy;
+            let d = dy * direction;
+
+            let (left, right) = if x < x_next { (x, x_next) } else { (x_next, x) };
+            let left_floor = left.floor();
+            let left_index = left_floor as i32;
+            let right_ceil = right.ceil();
+            let right_index = right_ceil as i32;
+
+            if right_index <= left_index + 1 {
+                // The edge stays within one pixel in this row. That pixel
+                // gets the area to the right of the edge and the rest goes
+                // to the next one.
+                let middle = 0.5 * (x + x_next) - left_floor;
+                add(left_index, d - d * middle);
+                add(left_index + 1, d * middle);
+            } else {
+                // The edge crosses several pixels. The first and last get a
+                // triangle each, and the ones in between a trapezoid.
+                let s = 1.0 / (right - left);
+                let left_frac = left - left_floor;
+                let first_area = 0.5 * s * (1.0 - left_frac) * (1.0 - left_frac);
+                let right_frac = right - right_ceil + 1.0;
+                let last_area = 0.5 * s * right_frac * right_frac;
+
+                add(left_index, d * first_area);
+                if right_index == left_index + 2 {
+                    add(left_index + 1, d * (1.0 - first_area - last_area));
+                } else {
+                    let second_area = s * (1.5 - left_frac);
+                    add(left_index + 1, d * (second_area - first_area));
+                    for i in (left_index + 2)..(right_index - 1) {
+                        add(i, d * s);
+                    }
+                    let covered = second_area + (right_index - left_index - 3) as f32 * s;
+                    add(right_index - 1, d * (1.0 - covered - last_area));
+                }
+                add(right_index, d * last_area);
+            }
+
+            x = x_next;
+        }
+    }
+
+    // Sum up the accumulated edges. The winding number around a pixel is
+    // fractional where edges pass through it, and the fill rule decides
+    // how that maps to coverage.
+    pub fn into_mask(self, rule: FillRule) -> Mask {
+        let mut mask = Mask::new(self.x, self.y, self.width, self.height);
+
+        for (row, out) in self.accumulation
+            .chunks(self.stride)
+            .zip(mask.coverage.chunks_mut(self.width.max(1)))
+        {
+            let mut winding = 0.0;
+            for (value, coverage) in row.iter().zip(out.iter_mut()) {
+                winding += value;
+                *coverage = match rule {
+                    FillRule::NonZero => winding.abs().min(1.0),
+                    FillRule::EvenOdd => {
+                        let parity = winding.abs() % 2.0;
+                        if parity > 1.0 { 2.0 - parity } else { parity }
+                    },
+                };
+            }
+        }
+
+        mask
+    }
+}
+
+impl Canvas {
+    // Blend color into every pixel covered by the mask, mixing each one by
+    // its coverage. Pixels outside the mask are left alone whatever the
+    // blend mode.
+    pub fn fill_mask(&mut self, color: (f32, f32, f32, f32), blend: BlendMode, mask: &Mask) {
+        self.fill_mask_at(color, blend, mask, (0, 0));
+    }
+
+    // Like fill_mask, with the mask moved offset pixels from where it is, so
+    // one mask can be stamped in many places
+    pub fn fill_mask_at(&mut self, color: (f32, f32, f32, f32), blend: BlendMode, mask: &Mask, offset: (i32, i32)) {
+        let origin_x = mask.origin().0 + offset.0;
+        let origin_y = mask.origin().1 + offset.1;
+
+        // Only visit the part of the mask that's on the canvas
+        let left = (-origin_x).max(0);
+        let right = (self.width() as i32 - origin_x).min(mask.width() as i32);
+        let top = (-origin_y).max(0);
+        let bottom = (self.height() as i32 - origin_y).min(mask.height() as i32);
+        if left >= right || top >= bottom {
+            return;
+        }
+
+        // The same as blend_pixel for each pixel, but working a row at a
+        // time since masks are often blended many times a frame
+        let source = premultiply(color);
+        let width = self.width();
+        for my in top..bottom {
+            let coverage = &mask.coverage()[my as usize * mask.width()..][left as usize..right as usize];
+            let start = (origin_y + my) as usize * width + (origin_x + left) as usize;
+            let pixels = &mut self.pixels_mut()[start..start + coverage.len()];
+            for (pixel, &coverage) in pixels.iter_mut().zip(coverage) {
+                if coverage > 0.0 {
+                    let new = blend.composite(source, *pixel);
+                    *pixel = (
+                        pixel.0 + (new.0 - pixel.0) * coverage,
+                        pixel.1 + (new.1 - pixel.1) * coverage,
+                        pixel.2 + (new.2 - pixel.2) * coverage,
+                        pixel.3 + (new.3 - pixel.3) * coverage,
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_rasterizer_coverage() {
+    let canvas = Canvas::new(20, 20);
+
+    // A square covering pixels 2..6 fully and half of the pixels around it
+    let square = [(1.5, 1.5), (6.5, 1.5), (6.5, 6.5), (1.5, 6.5)];
+    let mut rasterizer = Rasterizer::for_bounds(&canvas, (1.5, 1.5), (6.5, 6.5)).unwrap();
+    rasterizer.polygon(&square);
+    let mask = rasterizer.into_mask(FillRule::NonZero);
+    assert_eq!(mask.get(3, 3), 1.0);
+    assert_eq!(mask.get(1, 3), 0.5);
+    assert_eq!(mask.get(1, 1), 0.25);
+    assert_eq!(mask.get(7, 3), 0.0);
+
+    // Overlapping copies don't make the inside any more covered
+    let mut rasterizer = Rasterizer::new(0, 0, 20, 20);
+    rasterizer.polygon(&square);
+    rasterizer.polygon(&square);
+    assert_eq!(rasterizer.into_mask(FillRule::NonZero).get(3, 3), 1.0);
+
+    // Shapes hanging off the sides of the area still cover what's inside
+    let mut rasterizer = Rasterizer::new(5, 5, 10, 10);
+    rasterizer.polygon(&[(-10.0, -10.0), (30.0, -10.0), (30.0, 8.5), (-10.0, 8.5)]);
+    let mask = rasterizer.into_mask(FillRule::NonZero);
+    assert_eq!(mask.get(5, 5), 1.0);
+    assert_eq!(mask.get(14, 7), 1.0);
+    assert_eq!(mask.get(14, 8), 0.5);
+    assert_eq!(mask.get(14, 9), 0.0);
+
+    // Pixels far off the mask aren't covered, even where the distance to
+    // them doesn't fit in an i32
+    assert_eq!(mask.get(i32::MIN, 0), 0.0);
+    assert_eq!(mask.get(5, i32::MIN + 1), 0.0);
+}
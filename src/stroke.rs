@@ -0,0 +1,425 @@
+use std::f32::consts::PI;
+
+use crate::{clamp, Canvas};
+use crate::composite::BlendMode;
+use crate::path::Path;
+use crate::raster::{FillRule, Rasterizer};
+
+// How the ends of a stroked line are drawn
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineCap {
+    // The stroke stops exactly at the end points
+    Butt,
+    // The stroke is extended past the end points by half its width
+    Square,
+    // The stroke ends in a half circle around each end point
+    Round,
+}
+
+// How the corners between segments of a stroked path are drawn
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineJoin {
+    // The outside edges are extended until they meet, unless that would be
+    // further than the miter limit from the corner, in which case the corner
+    // is bevelled
+    Miter,
+    // The corner is rounded off with a circle the width of the stroke
+    Round,
+    // The corner is cut off straight across
+    Bevel,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StrokeStyle {
+    pub width: f32,
+    pub join: LineJoin,
+    pub cap: LineCap,
+    // Longest allowed miter, as a multiple of the stroke width
+    pub miter_limit: f32,
+}
+
+impl StrokeStyle {
+    // Miter joins and butt caps, like SVG
+    pub fn new(width: f32) -> StrokeStyle {
+        StrokeStyle {
+            width,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            miter_limit: 4.0,
+        }
+    }
+
+    // Furthest the stroke can reach from its path. Miters can stick out
+    // further than half the stroke width.
+    pub fn reach(&self) -> f32 {
+        self.width / 2.0 * match self.join {
+            LineJoin::Miter => self.miter_limit.max(std::f32::consts::SQRT_2),
+            _ => std::f32::consts::SQRT_2,
+        }
+    }
+}
+
+// Length of the overlap between a one pixel wide box centred on `center`
+// and the interval [low, high]. This is the fraction of the pixel covered
+// along one axis.
+fn box_overlap(center: f32, low: f32, high: f32) -> f32 {
+    clamp((center + 0.5).min(high) - (center - 0.5).max(low), 0.0, 1.0)
+}
+
+// Narrow [low, high] to the values of x for which coef*x + offset is in
+// [min, max].
+fn restrict_interval(
+    (low, high): (f32, f32),
+    coef: f32,
+    offset: f32,
+    (min, max): (f32, f32),
+) -> (f32, f32) {
+    if coef.abs() < 1e-6 {
+        return if min <= offset && offset <= max {
+            (low, high)
+        } else {
+            (f32::INFINITY, f32::NEG_INFINITY)
+        };
+    }
+
+    let a = (min - offset) / coef;
+    let b = (max - offset) / coef;
+    (low.max(a.min(b)), high.min(a.max(b)))
+}
+
+impl Canvas {
+    // Draw an anti-aliased line of any width between two points given in
+    // pixel coordinates, where (0.0, 0.0) is the top left corner of the
+    // top left pixel. Each pixel is blended by how much of it the stroke
+    // covers, so edges stay smooth at any angle and width.
+    pub fn stroke_line(
+        &mut self,
+        color: (f32, f32, f32, f32),
+        blend: BlendMode,
+        (x0, y0): (f32, f32),
+        (x1, y1): (f32, f32),
+        stroke_width: f32,
+        cap: LineCap,
+    ) {
+        if stroke_width.is_nan() || stroke_width <= 0.0 {
+            return;
+        }
+        let ((x0, y0), (x1, y1)) = match self.transform.project_line((x0, y0), (x1, y1)) {
+            None => return,
+            Some(line) => line,
+        };
+
+        let half_width = stroke_width / 2.0;
+        let dx = x1 - x0;
+        let dy = y1 - y0;
+        let length = (dx * dx + dy * dy).sqrt();
+
+        // Zero length lines have no direction, but we still want caps to
+        // show up as a dot, so pick one arbitrarily.
+        let (ux, uy) = if length > 1e-6 {
+            (dx / length, dy / length)
+        } else {
+            (1.0, 0.0)
+        };
+
+        // Extent of the stroke along the line, measured from p0
+        let (start, end) = match cap {
+            LineCap::Butt => (0.0, length),
+            LineCap::Square | LineCap::Round => (-half_width, length + half_width),
+        };
+        if end <= start {
+            return;
+        }
+
+        // Like line_segment_in_rect, only visit the part of the stroke
+        // that's actually on the canvas. The rows come from the bounding
+        // box, and within a row the span comes from solving for where the
+        // pixel centres are within a pixel of the stroke.
+        let margin = half_width + 1.0;
+        let min_y = (y0.min(y1) - margin).floor().max(0.0) as i32;
+        let max_y = (y0.max(y1) + margin).ceil().min(self.height as f32 - 1.0) as i32;
+
+        for y in min_y..=max_y {
+            let cy = y as f32 + 0.5 - y0;
+
+            // In terms of cx = pixel centre x - x0, the distance along the
+            // line is cx*ux + cy*uy and across it is cy*ux - cx*uy.
+            let span = (f32::NEG_INFINITY, f32::INFINITY);
+            let span = restrict_interval(span, ux, cy * uy, (start - 1.0, end + 1.0));
+            let span = restrict_interval(span, -uy, cy * ux, (-margin, margin));
+            if span.0 > span.1 {
+                continue;
+            }
+
+            let min_x = (span.0 + x0 - 0.5).floor().max(0.0) as i32;
+            let max_x = (span.1 + x0 - 0.5).ceil().min(self.width as f32 - 1.0) as i32;
+
+            for x in min_x..=max_x {
+                let cx = x as f32 + 0.5 - x0;
+                let along = cx * ux + cy * uy;
+                let across = cy * ux - cx * uy;
+
+                let coverage = match cap {
+                    LineCap::Butt | LineCap::Square => {
+                        box_overlap(along, start, end) * box_overlap(across, -half_width, half_width)
+                    },
+                    LineCap::Round => {
+                        let past_end = along - clamp(along, 0.0, length);
+                        let distance = (past_end * past_end + across * across).sqrt();
+                        clamp(half_width + 0.5 - distance, 0.0, stroke_width.min(1.0))
+                    },
+                };
+
+                if coverage > 0.0 {
+                    self.blend_pixel(color, blend, coverage, x, y);
+                }
+            }
+        }
+    }
+
+    // Stroke every subpath of path. The whole stroke is rasterized before
+    // anything is drawn, so pixels where segments meet or overlap are only
+    // blended once.
+    pub fn stroke_path(&mut self, color: (f32, f32, f32, f32), blend: BlendMode, path: &Path, style: &StrokeStyle) {
+        if style.width.is_nan() || style.width <= 0.0 {
+            return;
+        }
+
+        let path = &self.transform.project_path(path);
+        let ((min_x, min_y), (max_x, max_y)) = match path.bounds() {
+            None => return,
+            Some(bounds) => bounds,
+        };
+
+        let reach = style.reach();
+        let mut rasterizer = match Rasterizer::for_bounds(
+            self,
+            (min_x - reach, min_y - reach),
+            (max_x + reach, max_y + reach),
+        ) {
+            None => return,
+            Some(rasterizer) => rasterizer,
+        };
+
+        for subpath in path.subpaths() {
+            for polygon in stroke_outline(&subpath.points, subpath.closed, style) {
+                rasterizer.polygon(&polygon);
+            }
+        }
+
+        self.fill_mask(color, blend, &rasterizer.into_mask(FillRule::NonZero));
+    }
+}
+
+// Break the stroke of a run of points down into convex pieces: a rectangle
+// for each segment, plus the joins and caps. All pieces are wound the same
+// way so that filling them with the non-zero rule gives their union.
+fn stroke_outline(points: &[(f32, f32)], closed: bool, style: &StrokeStyle) -> Vec<Vec<(f32, f32)>> {
+    let half_width = style.width / 2.0;
+
+    // Repeated points don't have a direction, so drop them
+    let mut points: Vec<(f32, f32)> = points.to_vec();
+    points.dedup();
+    if closed && points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+
+    let mut pieces = Vec::new();
+
+    if points.len() == 1 {
+        let p = points[0];
+        match style.cap {
+            LineCap::Butt => (),
+            LineCap::Square => pieces.push(vec![
+                (p.0 - half_width, p.1 - half_width),
+                (p.0 + half_width, p.1 - half_width),
+                (p.0 + half_width, p.1 + half_width),
+                (p.0 - half_width, p.1 + half_width),
+            ]),
+            LineCap::Round => pieces.push(circle_polygon(p, half_width)),
+        }
+        return pieces;
+    }
+
+    let segment_count = if closed { points.len() } else { points.len() - 1 };
+    let segment = |i: usize| (points[i % points.len()], points[(i + 1) % points.len()]);
+
+    for i in 0..segment_count {
+        let (p0, p1) = segment(i);
+        let (nx, ny) = normal(p0, p1);
+        pieces.push(vec![
+            (p0.0 + nx * half_width, p0.1 + ny * half_width),
+            (p1.0 + nx * half_width, p1.1 + ny * half_width),
+            (p1.0 - nx * half_width, p1.1 - ny * half_width),
+            (p0.0 - nx * half_width, p0.1 - ny * half_width),
+        ]);
+    }
+
+    // Joins between each pair of consecutive segments
+    let join_count = if closed { segment_count } else { segment_count - 1 };
+    for i in 0..join_count {
+        let (p0, corner) = segment(i);
+        let (_, p2) = segment(i + 1);
+        if let Some(piece) = join_polygon(p0, corner, p2, style) {
+            pieces.push(piece);
+        }
+    }
+
+    if !closed {
+        let (first, second) = (points[0], points[1]);
+        let (second_last, last) = (points[points.len() - 2], points[points.len() - 1]);
+        for &(end, previous) in &[(first, second), (last, second_last)] {
+            match style.cap {
+                LineCap::Butt => (),
+                LineCap::Square => {
+                    let (nx, ny) = normal(previous, end);
+                    let (dx, dy) = (-ny * half_width, nx * half_width);
+                    pieces.push(vec![
+                        (end.0 + nx * half_width, end.1 + ny * half_width),
+                        (end.0 + nx * half_width + dx, end.1 + ny * half_width + dy),
+                        (end.0 - nx * half_width + dx, end.1 - ny * half_width + dy),
+                        (end.0 - nx * half_width, end.1 - ny * half_width),
+                    ]);
+                },
+                LineCap::Round => pieces.push(circle_polygon(end, half_width)),
+            }
+        }
+    }
+
+    for piece in pieces.iter_mut() {
+        if signed_area(piece) < 0.0 {
+            piece.reverse();
+        }
+    }
+
+    pieces
+}
+
+// Unit vector perpendicular to the segment from p0 to p1. Rotating the
+// direction a quarter turn clockwise gives the normal on its left hand side
+// (on screen, where y points down).
+fn normal(p0: (f32, f32), p1: (f32, f32)) -> (f32, f32) {
+    let (dx, dy) = (p1.0 - p0.0, p1.1 - p0.1);
+    let length = (dx * dx + dy * dy).sqrt();
+    (dy / length, -dx / length)
+}
+
+// The wedge filling in the outside of the corner between the segments
+// p0-corner and corner-p2, or None if they are in a straight line.
+fn join_polygon(p0: (f32, f32), corner: (f32, f32), p2: (f32, f32), style: &StrokeStyle) -> Option<Vec<(f32, f32)>> {
+    let half_width = style.width / 2.0;
+    let n0 = normal(p0, corner);
+    let n1 = normal(corner, p2);
+
+    // The outside of the corner is on the side the path turns away from
+    let (d0, d1) = ((corner.0 - p0.0, corner.1 - p0.1), (p2.0 - corner.0, p2.1 - corner.1));
+    let cross = d0.0 * d1.1 - d0.1 * d1.0;
+    if cross == 0.0 && d0.0 * d1.0 + d0.1 * d1.1 >= 0.0 {
+        return None;
+    }
+    let side = if cross > 0.0 { 1.0 } else { -1.0 };
+    let (n0, n1) = ((n0.0 * side, n0.1 * side), (n1.0 * side, n1.1 * side));
+
+    let outer0 = (corner.0 + n0.0 * half_width, corner.1 + n0.1 * half_width);
+    let outer1 = (corner.0 + n1.0 * half_width, corner.1 + n1.1 * half_width);
+
+    match style.join {
+        LineJoin::Round => Some(circle_polygon(corner, half_width)),
+        LineJoin::Bevel => Some(vec![corner, outer0, outer1]),
+        LineJoin::Miter => {
+            // The miter tip is along the average of the two normals, at a
+            // distance of half_width / cos(angle between the normals / 2).
+            // |n0 + n1| is 2 cos(angle / 2), which also gives us the ratio of
+            // the miter length to the stroke width.
+            let (mx, my) = (n0.0 + n1.0, n0.1 + n1.1);
+            let length_squared = mx * mx + my * my;
+            if length_squared < 1e-12 || 2.0 / length_squared.sqrt() > style.miter_limit {
+                return Some(vec![corner, outer0, outer1]);
+            }
+
+            let scale = 2.0 * half_width / length_squared;
+            Some(vec![corner, outer0, (corner.0 + mx * scale, corner.1 + my * scale), outer1])
+        },
+    }
+}
+
+// A polygon close enough to a circle that the difference is well under a
+// tenth of a pixel
+fn circle_polygon(center: (f32, f32), radius: f32) -> Vec<(f32, f32)> {
+    let tolerance = 0.1;
+    let segments = if radius <= tolerance {
+        8
+    } else {
+        let step = 2.0 * (1.0 - tolerance / radius).acos();
+        clamp((2.0 * PI / step).ceil() as usize, 8, 256)
+    };
+
+    (0..segments)
+        .map(|i| {
+            let angle = i as f32 / segments as f32 * 2.0 * PI;
+            (center.0 + radius * angle.cos(), center.1 + radius * angle.sin())
+        })
+        .collect()
+}
+
+fn signed_area(points: &[(f32, f32)]) -> f32 {
+    let mut area = 0.0;
+    for (i, p0) in points.iter().enumerate() {
+        let p1 = points[(i + 1) % points.len()];
+        area += p0.0 * p1.1 - p1.0 * p0.1;
+    }
+    area / 2.0
+}
+
+
+#[test]
+fn test_stroke_line_coverage() {
+    let mut canvas = Canvas::new(32, 32);
+    canvas.clear((0.0, 0.0, 0.0, 1.0));
+    canvas.stroke_line((1.0, 1.0, 1.0, 1.0), BlendMode::SourceOver, (4.0, 10.0), (20.0, 10.0), 3.0, LineCap::Butt);
+
+    // Fully inside, half covered on the edges, and untouched outside
+    assert_eq!(canvas.get_pixel(10, 9), Some((1.0, 1.0, 1.0, 1.0)));
+    assert_eq!(canvas.get_pixel(10, 10), Some((1.0, 1.0, 1.0, 1.0)));
+    assert_eq!(canvas.get_pixel(10, 8), Some((0.5, 0.5, 0.5, 1.0)));
+    assert_eq!(canvas.get_pixel(10, 11), Some((0.5, 0.5, 0.5, 1.0)));
+    assert_eq!(canvas.get_pixel(10, 7), Some((0.0, 0.0, 0.0, 1.0)));
+    assert_eq!(canvas.get_pixel(3, 10), Some((0.0, 0.0, 0.0, 1.0)));
+    assert_eq!(canvas.get_pixel(4, 10), Some((1.0, 1.0, 1.0, 1.0)));
+
+    // Lines which are partially or completely off the canvas are clipped
+    for &cap in &[LineCap::Butt, LineCap::Square, LineCap::Round] {
+        canvas.stroke_line((1.0, 1.0, 1.0, 1.0), BlendMode::SourceOver, (-50.0, -20.0), (80.0, 60.0), 7.5, cap);
+        canvas.stroke_line((1.0, 1.0, 1.0, 1.0), BlendMode::SourceOver, (-50.0, -20.0), (-10.0, 60.0), 4.0, cap);
+        canvas.stroke_line((1.0, 1.0, 1.0, 1.0), BlendMode::SourceOver, (5.0, 5.0), (5.0, 5.0), 4.0, cap);
+    }
+}
+
+#[test]
+fn test_stroke_path_joins() {
+    let mut path = Path::new();
+    path.move_to((4.0, 4.0)).line_to((20.0, 4.0)).line_to((20.0, 20.0));
+
+    for &join in &[LineJoin::Miter, LineJoin::Round, LineJoin::Bevel] {
+        let mut canvas = Canvas::new(32, 32);
+        canvas.clear((0.0, 0.0, 0.0, 1.0));
+        let style = StrokeStyle {
+            join,
+            ..StrokeStyle::new(4.0)
+        };
+        canvas.stroke_path((1.0, 1.0, 1.0, 0.5), BlendMode::SourceOver, &path, &style);
+
+        // Pixels where the two segments overlap are only blended once
+        assert_eq!(canvas.get_pixel(19, 4), Some((0.5, 0.5, 0.5, 1.0)));
+        assert_eq!(canvas.get_pixel(10, 4), Some((0.5, 0.5, 0.5, 1.0)));
+        assert_eq!(canvas.get_pixel(20, 10), Some((0.5, 0.5, 0.5, 1.0)));
+
+        // Only the miter fills the outside corner
+        let corner = canvas.get_pixel(21, 2).unwrap().0;
+        if join == LineJoin::Miter {
+            assert_eq!(corner, 0.5);
+        } else {
+            assert!(corner < 0.5);
+        }
+    }
+}
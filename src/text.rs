@@ -0,0 +1,282 @@
+use crate::{Canvas, TextPen, TextSpacing, layout_text};
+use crate::composite::BlendMode;
+
+// How the lines of a block of text line up with each other
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+    // Lines are stretched to fill the wrapping width by widening the gaps
+    // between words, except for the last line of each paragraph
+    Justify,
+}
+
+// Which part of a block of text its origin is on vertically
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Baseline {
+    // The top of the capital letters on the first line, like draw_text
+    Top,
+    // The line the letters of the first line sit on
+    Alphabetic,
+    // Halfway between the top of the block and the bottom
+    Middle,
+    // The bottom of the descenders on the last line
+    Bottom,
+}
+
+// How to lay out a block of text in the stroke font. Lines are broken at
+// '\n', and at the spaces between words to stay within max_width if it's
+// given. The block is as wide as max_width, or as its longest line without
+// it, and starts at the origin.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextLayout {
+    // Height of a capital letter in pixels
+    pub size: f32,
+    pub spacing: TextSpacing,
+    pub align: Align,
+    pub baseline: Baseline,
+    // Distance from one line to the next, as a multiple of size
+    pub line_spacing: f32,
+    pub max_width: Option<i32>,
+}
+
+// A piece of laid out text, and the origin to draw it at with draw_text
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextRun<'a> {
+    pub origin: (i32, i32),
+    pub text: &'a str,
+}
+
+impl TextLayout {
+    pub fn new(size: f32) -> TextLayout {
+        TextLayout {
+            size,
+            spacing: TextSpacing::Monospace,
+            align: Align::Left,
+            baseline: Baseline::Top,
+            line_spacing: 1.5,
+            max_width: None,
+        }
+    }
+
+    // Distance from the top of one line to the top of the next, in pixels
+    pub fn line_height(&self) -> i32 {
+        (self.size * self.line_spacing).round() as i32
+    }
+
+    // The text broken into lines
+    pub fn lines<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        self.paragraph_lines(text).into_iter().map(|(line, _)| line).collect()
+    }
+
+    // The width and height of the block of text in pixels, from the top of
+    // the first line to the bottom of the descenders on the last
+    pub fn measure(&self, text: &str) -> (i32, i32) {
+        self.block_size(&self.paragraph_lines(text))
+    }
+
+    // The width and height of a block of lines, see measure
+    fn block_size(&self, lines: &[(&str, bool)]) -> (i32, i32) {
+        let longest = lines.iter().map(|&(line, _)| text_width(self.size, self.spacing, line)).max().unwrap_or(0);
+        let width = self.max_width.unwrap_or(longest);
+        let height = (lines.len() as i32 - 1) * self.line_height() + self.size as i32 * 3 / 2;
+        (width, height)
+    }
+
+    // The top left and bottom right corners of the block of text when it's
+    // laid out from origin
+    pub fn bounds(&self, origin: (i32, i32), text: &str) -> ((i32, i32), (i32, i32)) {
+        let (width, height) = self.measure(text);
+        let top = origin.1 + self.top_offset(height);
+        ((origin.0, top), (origin.0 + width, top + height))
+    }
+
+    // Where each line, or each word of justified lines, goes
+    pub fn layout<'a>(&self, origin: (i32, i32), text: &'a str) -> Vec<TextRun<'a>> {
+        let lines = self.paragraph_lines(text);
+        let (width, height) = self.block_size(&lines);
+        let top = origin.1 + self.top_offset(height);
+
+        let mut runs = Vec::new();
+        for (i, (line, last)) in lines.into_iter().enumerate() {
+            let y = top + i as i32 * self.line_height();
+            let slack = width - text_width(self.size, self.spacing, line);
+            match self.align {
+                Align::Left => runs.push(TextRun { origin: (origin.0, y), text: line }),
+                Align::Center => runs.push(TextRun { origin: (origin.0 + slack / 2, y), text: line }),
+                Align::Right => runs.push(TextRun { origin: (origin.0 + slack, y), text: line }),
+                Align::Justify => {
+                    let words = words(line);
+                    if last || words.len() < 2 || self.max_width.is_none() {
+                        runs.push(TextRun { origin: (origin.0, y), text: line });
+                        continue;
+                    }
+
+                    // Whatever's left over after the words themselves is
+                    // shared out between the gaps as evenly as whole
+                    // pixels allow
+                    let gaps = words.len() as i32 - 1;
+                    let extra = width - words.iter().map(|word| text_width(self.size, self.spacing, word)).sum::<i32>();
+                    let mut x = origin.0;
+                    for (j, word) in words.into_iter().enumerate() {
+                        runs.push(TextRun { origin: (x, y), text: word });
+                        let j = j as i32;
+                        x += text_width(self.size, self.spacing, word) + extra * (j + 1) / gaps - extra * j / gaps;
+                    }
+                },
+            }
+        }
+        runs
+    }
+
+    // Each line, and whether it's the last line of its paragraph
+    fn paragraph_lines<'a>(&self, text: &'a str) -> Vec<(&'a str, bool)> {
+        let mut lines = Vec::new();
+        for paragraph in text.split('\n') {
+            let paragraph = paragraph.strip_suffix('\r').unwrap_or(paragraph);
+            let start = lines.len();
+            match self.max_width {
+                Some(max_width) => wrap(self.size, self.spacing, max_width, paragraph, &mut lines),
+                None => lines.push((paragraph, false)),
+            }
+            if let Some(line) = lines[start..].last_mut() {
+                line.1 = true;
+            }
+        }
+        lines
+    }
+
+    // Where the top of the block is relative to the origin
+    fn top_offset(&self, height: i32) -> i32 {
+        match self.baseline {
+            Baseline::Top => 0,
+            Baseline::Alphabetic => -(self.size as i32),
+            Baseline::Middle => -height / 2,
+            Baseline::Bottom => -height,
+        }
+    }
+}
+
+// How wide a line of text in the stroke font is in pixels, from the left of
+// the first character to the right of the last
+pub fn text_width(size: f32, spacing: TextSpacing, text: &str) -> i32 {
+    layout_text(size, spacing, text, |_, _| {})
+}
+
+// Break a paragraph into lines no wider than max_width, at spaces where
+// possible. Words too long to fit on a line of their own are broken
+// wherever they need to be. The spaces lines are broken at are dropped.
+fn wrap<'a>(size: f32, spacing: TextSpacing, max_width: i32, mut text: &'a str, lines: &mut Vec<(&'a str, bool)>) {
+    // An empty paragraph is still a line
+    if text.is_empty() {
+        lines.push((text, false));
+    }
+
+    while !text.is_empty() {
+        // Read along the line until it's too wide, remembering the last
+        // space and the last character it could have been broken after
+        let mut pen = TextPen::new(size, spacing);
+        let (mut at_space, mut at_char) = (None, None);
+        let mut overflow = None;
+        for (i, c) in text.char_indices() {
+            pen.place(c);
+            if c == ' ' {
+                if at_char.is_some() {
+                    at_space = Some(i);
+                }
+            } else if pen.width() > max_width {
+                overflow = Some(i + c.len_utf8());
+                break;
+            } else {
+                at_char = Some(i + c.len_utf8());
+            }
+        }
+
+        // The whole of what's left fits, trailing spaces and all
+        let overflow = match overflow {
+            None if pen.width() <= max_width => {
+                lines.push((text, false));
+                return;
+            },
+            None => text.len(),
+            Some(overflow) => overflow,
+        };
+
+        // At least one character goes on each line, however narrow
+        let end = at_space.or(at_char).unwrap_or(overflow);
+        lines.push((text[..end].trim_end_matches(' '), false));
+        text = text[end..].trim_start_matches(' ');
+    }
+}
+
+// The words of a line, with any indentation kept on the first
+fn words(line: &str) -> Vec<&str> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    let mut words: Vec<&str> = line[indent..].split(' ').filter(|word| !word.is_empty()).collect();
+    if let Some(first) = words.first_mut() {
+        let end = indent + first.len();
+        *first = &line[..end];
+    }
+    words
+}
+
+impl Canvas {
+    // Draw a block of text laid out by layout, see TextLayout
+    pub fn draw_text_layout(
+        &mut self,
+        (r, g, b): (f32, f32, f32),
+        blend: BlendMode,
+        origin: (i32, i32),
+        layout: &TextLayout,
+        text: &str,
+    ) {
+        for run in layout.layout(origin, text) {
+            self.draw_text((r, g, b), blend, run.origin, layout.size, layout.spacing, run.text);
+        }
+    }
+}
+
+#[test]
+fn test_text_layout() {
+    // At size 20, characters are 12 pixels wide and 19 pixels apart
+    assert_eq!(text_width(20.0, TextSpacing::Monospace, ""), 0);
+    assert_eq!(text_width(20.0, TextSpacing::Monospace, "a"), 12);
+    assert_eq!(text_width(20.0, TextSpacing::Monospace, "abc"), 50);
+
+    let layout = TextLayout::new(20.0);
+    assert_eq!(layout.lines("one\ntwo\r\n\nthree"), vec!["one", "two", "", "three"]);
+    assert_eq!(layout.measure("one\nthree"), (88, 60));
+    assert_eq!(layout.bounds((10, 100), "one\nthree"), ((10, 100), (98, 160)));
+    let baseline = TextLayout { baseline: Baseline::Alphabetic, ..layout };
+    assert_eq!(baseline.bounds((10, 100), "one\nthree"), ((10, 80), (98, 140)));
+    let bottom = TextLayout { baseline: Baseline::Bottom, ..layout };
+    assert_eq!(bottom.bounds((10, 100), "one\nthree"), ((10, 40), (98, 100)));
+
+    // Lines wrap at spaces, and words that don't fit on a line of their own
+    // are broken up
+    let wrapped = TextLayout { max_width: Some(88), ..layout };
+    assert_eq!(wrapped.lines("ab cd ef  gh\n  ij"), vec!["ab cd", "ef", "gh", "  ij"]);
+    assert_eq!(wrapped.lines("abcdefghij"), vec!["abcde", "fghij"]);
+    assert_eq!(TextLayout { max_width: Some(1), ..layout }.lines("ab"), vec!["a", "b"]);
+
+    // Long paragraphs wrap in one pass along them
+    let long = "ab ".repeat(10000);
+    assert!(wrapped.lines(&long).iter().all(|&line| line == "ab ab"));
+    assert_eq!(wrapped.lines(&long).len(), 5000);
+
+    let runs = |align: Align, text| TextLayout { align, ..wrapped }.layout((0, 0), text);
+    let origins = |runs: Vec<TextRun>| runs.iter().map(|run| run.origin).collect::<Vec<_>>();
+    assert_eq!(origins(runs(Align::Left, "ab cd ef")), vec![(0, 0), (0, 30)]);
+    assert_eq!(origins(runs(Align::Center, "ab cd ef")), vec![(0, 0), (28, 30)]);
+    assert_eq!(origins(runs(Align::Right, "ab cd ef")), vec![(0, 0), (57, 30)]);
+
+    // Justified lines reach the right edge, apart from the last
+    let justified = runs(Align::Justify, "a b c d e");
+    assert_eq!(justified.iter().map(|run| run.text).collect::<Vec<_>>(), vec!["a", "b", "c", "d e"]);
+    assert_eq!(origins(justified), vec![(0, 0), (38, 0), (76, 0), (0, 30)]);
+
+    let mut canvas = Canvas::new(100, 60);
+    canvas.draw_text_layout((1.0, 1.0, 1.0), BlendMode::SourceOver, (0, 0), &wrapped, "ab cd");
+    assert!(canvas.pixels().iter().any(|pixel| pixel.0 > 0.0));
+}
@@ -0,0 +1,117 @@
+use rayon::prelude::*;
+
+use crate::Canvas;
+
+// How overbright values are squeezed into the 0.0..=1.0 range that can be
+// displayed. Additive drawing easily goes past 1.0, and clamping makes
+// those areas flat and changes their hue, while the curves here roll off
+// smoothly instead.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMapOperator {
+    // Cut off everything above 1.0. Leaves the displayable range alone.
+    Clamp,
+    // x * (1 + x / white^2) / (1 + x), which maps white to 1.0. Use
+    // f32::INFINITY for the classic x / (1 + x) curve, which never reaches
+    // 1.0.
+    Reinhard { white: f32 },
+    // Krzysztof Narkowicz's fit of the ACES filmic curve. Adds contrast,
+    // and maps 1.0 to about 0.8.
+    AcesFilmic,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToneMap {
+    pub operator: ToneMapOperator,
+    // Brightness adjustment in stops, applied before the curve: +1.0
+    // doubles every value and -1.0 halves it
+    pub exposure: f32,
+}
+
+impl ToneMap {
+    pub fn new(operator: ToneMapOperator) -> ToneMap {
+        ToneMap {
+            operator,
+            exposure: 0.0,
+        }
+    }
+
+    // Tone map a single linear value
+    pub fn map(&self, x: f32) -> f32 {
+        let x = x * self.exposure.exp2();
+        // Written this way round so NaN becomes 0.0 as well
+        let x = if x > 0.0 { x } else { 0.0 };
+
+        let mapped = match self.operator {
+            ToneMapOperator::Clamp => x,
+            ToneMapOperator::Reinhard { white } => {
+                x * (1.0 + x / (white * white)) / (1.0 + x)
+            },
+            ToneMapOperator::AcesFilmic => {
+                (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+            },
+        };
+        mapped.min(1.0)
+    }
+
+    // Tone map the color channels of a pixel. Alpha is left alone.
+    pub fn map_pixel(&self, (r, g, b, a): (f32, f32, f32, f32)) -> (f32, f32, f32, f32) {
+        (self.map(r), self.map(g), self.map(b), a)
+    }
+}
+
+// Tone map a buffer of linear pixels in place, in parallel
+pub fn tone_map_buffer(buffer: &mut [(f32, f32, f32, f32)], tone_map: &ToneMap) {
+    buffer.par_iter_mut().for_each(|pixel| *pixel = tone_map.map_pixel(*pixel));
+}
+
+impl Canvas {
+    // Tone map every pixel. This is the last step before gamma correction:
+    // anything drawn after it can go overbright again.
+    pub fn tone_map(&mut self, tone_map: &ToneMap) {
+        tone_map_buffer(self.pixels_mut(), tone_map);
+    }
+}
+
+#[test]
+fn test_tone_mapping() {
+    let clamp = ToneMap::new(ToneMapOperator::Clamp);
+    assert_eq!(clamp.map_pixel((0.25, 3.0, -1.0, 0.5)), (0.25, 1.0, 0.0, 0.5));
+    assert_eq!(clamp.map(f32::NAN), 0.0);
+
+    let reinhard = ToneMap::new(ToneMapOperator::Reinhard { white: f32::INFINITY });
+    assert_eq!(reinhard.map(1.0), 0.5);
+    assert_eq!(reinhard.map(3.0), 0.75);
+    let reinhard = ToneMap::new(ToneMapOperator::Reinhard { white: 4.0 });
+    assert_eq!(reinhard.map(4.0), 1.0);
+    assert_eq!(reinhard.map(100.0), 1.0);
+
+    // Every curve starts at black, only ever gets brighter, and stays
+    // displayable
+    let operators = [
+        ToneMapOperator::Clamp,
+        ToneMapOperator::Reinhard { white: 2.0 },
+        ToneMapOperator::AcesFilmic,
+    ];
+    for &operator in &operators {
+        let tone_map = ToneMap::new(operator);
+        assert!(tone_map.map(0.0) < 0.001);
+        let mut last = 0.0;
+        for i in 1..1000 {
+            let value = tone_map.map(i as f32 / 100.0);
+            assert!(value >= last && value <= 1.0);
+            last = value;
+        }
+    }
+
+    // Exposure is in stops
+    let brighter = ToneMap {
+        exposure: 1.0,
+        ..ToneMap::new(ToneMapOperator::Clamp)
+    };
+    assert_eq!(brighter.map(0.25), 0.5);
+
+    let mut canvas = Canvas::new(2, 1);
+    canvas.clear((2.0, 1.0, 0.0, 1.0));
+    canvas.tone_map(&ToneMap::new(ToneMapOperator::Reinhard { white: f32::INFINITY }));
+    assert_eq!(canvas.get_pixel(1, 0), Some((2.0 / 3.0, 0.5, 0.0, 1.0)));
+}
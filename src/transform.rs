@@ -0,0 +1,299 @@
+use crate::math::{Mat3, Mat4, Vec3, Vec4};
+use crate::path::Path;
+use crate::Canvas;
+
+// Smallest w a point can have after transforming and still be drawn.
+// Points with w at or below zero are behind the viewer of a perspective
+// projection, so geometry is clipped where it crosses this.
+const MIN_W: f32 = 1e-4;
+
+// The current transform from the coordinates drawing calls are given in to
+// canvas pixels, along with the transforms saved by push. Every canvas has
+// one, and every drawing call that takes points as floats goes through it,
+// so scenes can be drawn in their own units and placed with translate,
+// rotate, scale and perspective. Calls that take whole pixel coordinates
+// (set_pixel, wu_line, fill_mask) always address pixels directly.
+//
+// Like in the HTML canvas API and Processing, each transform applies to the
+// coordinates before the ones already on the stack: after translate then
+// rotate, shapes are rotated around their own origin and then moved.
+//
+// 2D drawing calls work on the z = 0 plane, so they can be tilted into 3D
+// with rotate_x and rotate_y and viewed with perspective. Stroke widths and
+// text sizes stay in pixels.
+#[derive(Clone, Debug)]
+pub struct TransformStack {
+    current: Mat4,
+    saved: Vec<Mat4>,
+}
+
+impl Default for TransformStack {
+    fn default() -> TransformStack {
+        TransformStack::new()
+    }
+}
+
+impl TransformStack {
+    pub fn new() -> TransformStack {
+        TransformStack {
+            current: Mat4::identity(),
+            saved: Vec::new(),
+        }
+    }
+
+    pub fn current(&self) -> Mat4 {
+        self.current
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.current == Mat4::identity()
+    }
+
+    // Save the current transform, to be restored by the matching pop
+    pub fn push(&mut self) {
+        self.saved.push(self.current);
+    }
+
+    pub fn pop(&mut self) {
+        self.current = self.saved.pop().expect("transform stack popped more times than it was pushed");
+    }
+
+    // Go back to drawing in pixels. Saved transforms are kept.
+    pub fn reset(&mut self) {
+        self.current = Mat4::identity();
+    }
+
+    pub fn set(&mut self, transform: Mat4) {
+        self.current = transform;
+    }
+
+    // Apply transform to coordinates before the current transform
+    pub fn apply(&mut self, transform: Mat4) {
+        self.current = self.current * transform;
+    }
+
+    pub fn apply_2d(&mut self, transform: Mat3) {
+        self.apply(transform.to_mat4());
+    }
+
+    pub fn translate(&mut self, x: f32, y: f32) {
+        self.apply(Mat4::translate(x, y, 0.0));
+    }
+
+    // Rotate clockwise on screen by angle radians
+    pub fn rotate(&mut self, angle: f32) {
+        self.apply(Mat4::rotate_z(angle));
+    }
+
+    pub fn scale(&mut self, x: f32, y: f32) {
+        self.apply(Mat4::scale(x, y, 1.0));
+    }
+
+    pub fn translate_3d(&mut self, x: f32, y: f32, z: f32) {
+        self.apply(Mat4::translate(x, y, z));
+    }
+
+    pub fn rotate_x(&mut self, angle: f32) {
+        self.apply(Mat4::rotate_x(angle));
+    }
+
+    pub fn rotate_y(&mut self, angle: f32) {
+        self.apply(Mat4::rotate_y(angle));
+    }
+
+    pub fn scale_3d(&mut self, x: f32, y: f32, z: f32) {
+        self.apply(Mat4::scale(x, y, z));
+    }
+
+    // View everything drawn from here on through a camera at the origin
+    // looking along +z, on a canvas of size pixels. See Mat4::perspective.
+    pub fn perspective(&mut self, fov_y: f32, size: (f32, f32), near: f32, far: f32) {
+        self.apply(Mat4::perspective(fov_y, size, near, far));
+    }
+
+    // Where a point ends up on the canvas, with its depth, or None if it's
+    // behind the viewer
+    pub fn project_point_3d(&self, p: Vec3) -> Option<Vec3> {
+        let p = self.current.transform(p.extend(1.0));
+        if p.w > MIN_W { Some(p.project()) } else { None }
+    }
+
+    pub fn project_point(&self, (x, y): (f32, f32)) -> Option<(f32, f32)> {
+        if self.is_identity() {
+            return Some((x, y));
+        }
+        self.project_point_3d(Vec3::new(x, y, 0.0)).map(|p| (p.x, p.y))
+    }
+
+    // The part of the line from p0 to p1 in front of the viewer, on the
+    // canvas. Straight lines stay straight under any of these transforms,
+    // so only the ends need transforming.
+    pub fn project_line_3d(&self, p0: Vec3, p1: Vec3) -> Option<(Vec3, Vec3)> {
+        let (a, b) = (self.current.transform(p0.extend(1.0)), self.current.transform(p1.extend(1.0)));
+        let (a, b) = match (a.w > MIN_W, b.w > MIN_W) {
+            (true, true) => (a, b),
+            (false, false) => return None,
+            (true, false) => (a, clip_w(a, b)),
+            (false, true) => (clip_w(b, a), b),
+        };
+        Some((a.project(), b.project()))
+    }
+
+    pub fn project_line(&self, p0: (f32, f32), p1: (f32, f32)) -> Option<((f32, f32), (f32, f32))> {
+        if self.is_identity() {
+            return Some((p0, p1));
+        }
+        let (a, b) = self.project_line_3d(Vec3::new(p0.0, p0.1, 0.0), Vec3::new(p1.0, p1.1, 0.0))?;
+        Some(((a.x, a.y), (b.x, b.y)))
+    }
+
+    // The part of a closed polygon in front of the viewer, on the canvas
+    pub fn project_polygon(&self, points: &[(f32, f32)]) -> Vec<(f32, f32)> {
+        if self.is_identity() {
+            return points.to_vec();
+        }
+
+        // Sutherland-Hodgman against the single plane w = MIN_W
+        let transformed: Vec<Vec4> = points.iter()
+            .map(|&(x, y)| self.current.transform(Vec4::new(x, y, 0.0, 1.0)))
+            .collect();
+        let mut out = Vec::with_capacity(points.len());
+        for (i, &p) in transformed.iter().enumerate() {
+            let previous = transformed[(i + transformed.len() - 1) % transformed.len()];
+            match (previous.w > MIN_W, p.w > MIN_W) {
+                (true, true) => out.push(p),
+                (true, false) => out.push(clip_w(previous, p)),
+                (false, true) => {
+                    out.push(clip_w(p, previous));
+                    out.push(p);
+                },
+                (false, false) => {},
+            }
+        }
+        out.iter().map(|p| p.project()).map(|p| (p.x, p.y)).collect()
+    }
+
+    // The part of a path in front of the viewer, on the canvas. Open
+    // subpaths are split where they go behind the viewer, and closed ones
+    // are clipped like polygons.
+    pub fn project_path(&self, path: &Path) -> Path {
+        self.project_subpaths(path, false)
+    }
+
+    // Like project_path, but every subpath is clipped as if it were closed,
+    // for filling
+    pub fn project_fill_path(&self, path: &Path) -> Path {
+        self.project_subpaths(path, true)
+    }
+
+    fn project_subpaths(&self, path: &Path, all_closed: bool) -> Path {
+        if self.is_identity() {
+            return path.clone();
+        }
+
+        let mut out = Path::new();
+        for subpath in path.subpaths() {
+            if subpath.closed || all_closed {
+                let points = self.project_polygon(&subpath.points);
+                if let Some((&first, rest)) = points.split_first() {
+                    out.move_to(first);
+                    for &p in rest {
+                        out.line_to(p);
+                    }
+                    out.close();
+                }
+                continue;
+            }
+
+            let mut pen_down = false;
+            if subpath.points.len() == 1 {
+                if let Some(p) = self.project_point(subpath.points[0]) {
+                    out.move_to(p);
+                }
+            }
+            for pair in subpath.points.windows(2) {
+                match self.project_line(pair[0], pair[1]) {
+                    None => pen_down = false,
+                    Some((a, b)) => {
+                        if !pen_down || out.current_point() != Some(a) {
+                            out.move_to(a);
+                        }
+                        out.line_to(b);
+                        // A line clipped at its far end can't connect to
+                        // the next one
+                        pen_down = self.project_point(pair[1]) == Some(b);
+                    },
+                }
+            }
+        }
+        out
+    }
+}
+
+// The point between inside and outside where w is exactly MIN_W
+fn clip_w(inside: Vec4, outside: Vec4) -> Vec4 {
+    let t = (inside.w - MIN_W) / (inside.w - outside.w);
+    inside.lerp(outside, t)
+}
+
+impl Canvas {
+    pub fn transform(&self) -> &TransformStack {
+        &self.transform
+    }
+
+    pub fn transform_mut(&mut self) -> &mut TransformStack {
+        &mut self.transform
+    }
+}
+
+#[test]
+fn test_transform_stack() {
+    use std::f32::consts::FRAC_PI_2;
+
+    let close = |a: (f32, f32), b: (f32, f32)| (a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4;
+
+    // Later transforms apply first, and pop undoes everything since push
+    let mut stack = TransformStack::new();
+    stack.translate(10.0, 20.0);
+    stack.push();
+    stack.rotate(FRAC_PI_2);
+    stack.scale(2.0, 2.0);
+    assert!(close(stack.project_point((1.0, 0.0)).unwrap(), (10.0, 22.0)));
+    stack.pop();
+    assert_eq!(stack.project_point((1.0, 0.0)), Some((11.0, 20.0)));
+
+    // Lines and polygons going behind the viewer are cut off where they
+    // cross, and lines entirely behind disappear
+    let mut stack = TransformStack::new();
+    stack.perspective(FRAC_PI_2, (100.0, 100.0), 1.0, 100.0);
+    stack.rotate_x(FRAC_PI_2);
+    stack.translate(0.0, -10.0);
+    assert_eq!(stack.project_point((0.0, 0.0)), None);
+    assert!(stack.project_line((0.0, 0.0), (0.0, 5.0)).is_none());
+    let (a, b) = stack.project_line((0.0, 20.0), (10.0, 0.0)).unwrap();
+    assert!(close(a, (50.0, 50.0)));
+    assert!(b.0 > 1e4);
+    assert_eq!(stack.project_polygon(&[(0.0, 0.0), (5.0, 0.0), (5.0, 5.0)]).len(), 0);
+    assert_eq!(stack.project_polygon(&[(0.0, 0.0), (5.0, 20.0), (-5.0, 20.0)]).len(), 4);
+
+    let mut path = Path::new();
+    path.move_to((0.0, 20.0)).line_to((0.0, 0.0)).line_to((5.0, 20.0));
+    let projected = stack.project_path(&path);
+    assert_eq!(projected.subpaths().len(), 2);
+
+    // Drawing calls honor the transform
+    let mut canvas = Canvas::new(20, 20);
+    canvas.clear((0.0, 0.0, 0.0, 1.0));
+    canvas.transform_mut().translate(10.0, 10.0);
+    canvas.transform_mut().scale(4.0, 4.0);
+    canvas.fill_polygon(
+        (1.0, 1.0, 1.0, 1.0),
+        crate::composite::BlendMode::SourceOver,
+        &[(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)],
+        crate::raster::FillRule::NonZero,
+    );
+    assert_eq!(canvas.get_pixel(6, 6), Some((1.0, 1.0, 1.0, 1.0)));
+    assert_eq!(canvas.get_pixel(13, 13), Some((1.0, 1.0, 1.0, 1.0)));
+    assert_eq!(canvas.get_pixel(14, 10), Some((0.0, 0.0, 0.0, 1.0)));
+    assert_eq!(canvas.get_pixel(5, 10), Some((0.0, 0.0, 0.0, 1.0)));
+}
@@ -0,0 +1,404 @@
+use crate::{Canvas, clamp, wu_line_coverage};
+use crate::composite::BlendMode;
+use crate::math::{Mat4, Vec3, Vec4};
+
+// Fades lines out with their distance from the camera, so the far parts of
+// a scene look like they're disappearing into the dark. Lines are full
+// brightness up to near and fade to black at far.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthCue {
+    pub near: f32,
+    pub far: f32,
+    // How quickly lines fade, where 1.0 fades evenly with distance and
+    // bigger numbers fade faster close to near
+    pub falloff: f32,
+}
+
+impl DepthCue {
+    pub fn new(near: f32, far: f32) -> DepthCue {
+        DepthCue { near, far, falloff: 1.0 }
+    }
+
+    pub fn intensity(&self, distance: f32) -> f32 {
+        let t = clamp((distance - self.near) / (self.far - self.near), 0.0, 1.0);
+        (1.0 - t).powf(self.falloff)
+    }
+}
+
+// A perspective camera for drawing 3D lines. World coordinates follow the
+// canvas, with x to the right and y going down, so up is -y.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub position: Vec3,
+    // The point in the middle of the view
+    pub target: Vec3,
+    // Which way is up in the world. The camera rolls so this points up the
+    // canvas.
+    pub up: Vec3,
+    // Angle in radians between the top and bottom of the view
+    pub fov_y: f32,
+    // Nothing closer than near or further away than far is drawn
+    pub near: f32,
+    pub far: f32,
+    // Fade lines with distance. Distances are measured along the direction
+    // the camera is looking.
+    pub depth_cue: Option<DepthCue>,
+}
+
+impl Camera {
+    pub fn new(position: Vec3, target: Vec3, fov_y: f32) -> Camera {
+        Camera {
+            position,
+            target,
+            up: Vec3::new(0.0, -1.0, 0.0),
+            fov_y,
+            near: 1.0,
+            far: 100_000.0,
+            depth_cue: None,
+        }
+    }
+
+    // Transform from world coordinates to the camera's, where it sits at
+    // the origin looking along +z with y going down the canvas
+    pub fn view(&self) -> Mat4 {
+        let forward = (self.target - self.position).normalize();
+        let right = forward.cross(self.up).normalize();
+        let down = forward.cross(right);
+
+        let row = |axis: Vec3| [axis.x, axis.y, axis.z, -axis.dot(self.position)];
+        Mat4 {
+            m: [row(right), row(down), row(forward), [0.0, 0.0, 0.0, 1.0]],
+        }
+    }
+
+    pub fn projection(&self, size: (f32, f32)) -> Mat4 {
+        Mat4::perspective(self.fov_y, size, self.near, self.far)
+    }
+
+    // Transform from world coordinates to clip coordinates for a canvas of
+    // size pixels. Dividing through by w gives pixels, and a depth of 0.0
+    // at the near distance up to 1.0 at the far distance.
+    pub fn view_projection(&self, size: (f32, f32)) -> Mat4 {
+        self.projection(size) * self.view()
+    }
+}
+
+// Clip a line given in clip coordinates to the near and far planes, where
+// 0 <= z <= w. Everything that's left is in front of the camera, so it can
+// be divided through by w safely.
+pub fn clip_line(mut a: Vec4, mut b: Vec4) -> Option<(Vec4, Vec4)> {
+    // Signed distances inside each plane, positive on the inside
+    let planes: [fn(Vec4) -> f32; 2] = [|p| p.z, |p| p.w - p.z];
+    for distance in &planes {
+        let (da, db) = (distance(a), distance(b));
+        match (da >= 0.0, db >= 0.0) {
+            (true, true) => {},
+            (false, false) => return None,
+            (true, false) => b = a.lerp(b, da / (da - db)),
+            (false, true) => a = a.lerp(b, da / (da - db)),
+        }
+    }
+    Some((a, b))
+}
+
+// Clip a polygon given in clip coordinates to the near plane
+fn clip_polygon(points: &[Vec4]) -> Vec<Vec4> {
+    let mut out = Vec::with_capacity(points.len() + 1);
+    for (i, &p) in points.iter().enumerate() {
+        let previous = points[(i + points.len() - 1) % points.len()];
+        let crossing = || previous.lerp(p, previous.z / (previous.z - p.z));
+        match (previous.z >= 0.0, p.z >= 0.0) {
+            (true, true) => out.push(p),
+            (true, false) => out.push(crossing()),
+            (false, true) => {
+                out.push(crossing());
+                out.push(p);
+            },
+            (false, false) => {},
+        }
+    }
+    out
+}
+
+// Vertices joined by straight edges
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mesh {
+    pub vertices: Vec<Vec3>,
+    // Pairs of indices into vertices
+    pub edges: Vec<(usize, usize)>,
+    // Flat convex polygons as lists of indices into vertices. These aren't
+    // drawn, but they hide the lines behind them when written into a depth
+    // buffer.
+    pub faces: Vec<Vec<usize>>,
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Vec3>, edges: Vec<(usize, usize)>) -> Mesh {
+        Mesh { vertices, edges, faces: Vec::new() }
+    }
+
+    // The edges of polygons given as lists of vertex indices, which are
+    // kept as the mesh's faces. Edges shared between faces only appear
+    // once, so they aren't drawn twice.
+    pub fn from_faces(vertices: Vec<Vec3>, faces: &[&[usize]]) -> Mesh {
+        let mut edges = Vec::new();
+        for face in faces {
+            for (i, &a) in face.iter().enumerate() {
+                let b = face[(i + 1) % face.len()];
+                let edge = (a.min(b), a.max(b));
+                if !edges.contains(&edge) {
+                    edges.push(edge);
+                }
+            }
+        }
+        let faces = faces.iter().map(|face| face.to_vec()).collect();
+        Mesh { vertices, edges, faces }
+    }
+
+    // A cube centred on the origin with sides size long
+    pub fn cube(size: f32) -> Mesh {
+        let h = size / 2.0;
+        let vertices = (0..8)
+            .map(|i| {
+                let corner = |bit: usize| if i & bit == 0 { -h } else { h };
+                Vec3::new(corner(1), corner(2), corner(4))
+            })
+            .collect();
+        Mesh::from_faces(vertices, &[
+            &[0, 1, 3, 2],
+            &[4, 5, 7, 6],
+            &[0, 1, 5, 4],
+            &[2, 3, 7, 6],
+            &[0, 2, 6, 4],
+            &[1, 3, 7, 5],
+        ])
+    }
+
+    // The twelve vertices of an icosahedron, on a sphere of the given
+    // radius around the origin
+    pub fn icosahedron(radius: f32) -> Mesh {
+        let phi = (1.0 + 5.0f32.sqrt()) / 2.0;
+        let scale = radius / (1.0 + phi * phi).sqrt();
+        let vertices = [
+            (-1.0, phi, 0.0), (1.0, phi, 0.0), (-1.0, -phi, 0.0), (1.0, -phi, 0.0),
+            (0.0, -1.0, phi), (0.0, 1.0, phi), (0.0, -1.0, -phi), (0.0, 1.0, -phi),
+            (phi, 0.0, -1.0), (phi, 0.0, 1.0), (-phi, 0.0, -1.0), (-phi, 0.0, 1.0),
+        ]
+            .iter()
+            .map(|&p| Vec3::from(p) * scale)
+            .collect();
+        Mesh::from_faces(vertices, &[
+            &[0, 11, 5], &[0, 5, 1], &[0, 1, 7], &[0, 7, 10], &[0, 10, 11],
+            &[1, 5, 9], &[5, 11, 4], &[11, 10, 2], &[10, 7, 6], &[7, 1, 8],
+            &[3, 9, 4], &[3, 4, 2], &[3, 2, 6], &[3, 6, 8], &[3, 8, 9],
+            &[4, 9, 5], &[2, 4, 11], &[6, 2, 10], &[8, 6, 7], &[9, 8, 1],
+        ])
+    }
+
+    // A flat grid of columns x rows square cells on the y = 0 plane,
+    // starting at the origin and going along +x and +z
+    pub fn grid(columns: usize, rows: usize, cell: f32) -> Mesh {
+        let index = |x: usize, z: usize| x + z * (columns + 1);
+        let vertices = (0..=rows)
+            .flat_map(|z| (0..=columns).map(move |x| Vec3::new(x as f32 * cell, 0.0, z as f32 * cell)))
+            .collect();
+
+        let mut edges = Vec::new();
+        let mut faces = Vec::new();
+        for z in 0..=rows {
+            for x in 0..=columns {
+                if x < columns {
+                    edges.push((index(x, z), index(x + 1, z)));
+                }
+                if z < rows {
+                    edges.push((index(x, z), index(x, z + 1)));
+                }
+                if x < columns && z < rows {
+                    faces.push(vec![index(x, z), index(x + 1, z), index(x + 1, z + 1), index(x, z + 1)]);
+                }
+            }
+        }
+        Mesh { vertices, edges, faces }
+    }
+}
+
+impl Canvas {
+    // Draw a line between two points in the world as seen by camera. The
+    // camera replaces the canvas transform. If the canvas has a depth
+    // buffer, the parts of the line behind surfaces in it are hidden, and
+    // the line fades with distance if the camera has a depth cue.
+    pub fn line_3d(&mut self, color: (f32, f32, f32, f32), blend: BlendMode, camera: &Camera, p0: Vec3, p1: Vec3) {
+        let view_projection = camera.view_projection((self.width() as f32, self.height() as f32));
+        self.line_clip(color, blend, &view_projection, camera.depth_cue, p0, p1);
+    }
+
+    // Draw every edge of a mesh placed in the world by model, as seen by
+    // camera, like line_3d
+    pub fn draw_mesh(
+        &mut self,
+        color: (f32, f32, f32, f32),
+        blend: BlendMode,
+        camera: &Camera,
+        mesh: &Mesh,
+        model: &Mat4,
+    ) {
+        let transform = camera.view_projection((self.width() as f32, self.height() as f32)) * *model;
+        for &(a, b) in &mesh.edges {
+            self.line_clip(color, blend, &transform, camera.depth_cue, mesh.vertices[a], mesh.vertices[b]);
+        }
+    }
+
+    // Write the faces of a mesh placed in the world by model into the depth
+    // buffer, so that lines drawn afterwards are hidden behind them. Write
+    // every mesh that should hide something before drawing any lines. Does
+    // nothing without a depth buffer.
+    pub fn draw_mesh_depth(&mut self, camera: &Camera, mesh: &Mesh, model: &Mat4) {
+        if !self.has_depth_buffer() {
+            return;
+        }
+
+        let transform = camera.view_projection((self.width() as f32, self.height() as f32)) * *model;
+        for face in &mesh.faces {
+            let points: Vec<Vec4> = face.iter().map(|&i| transform.transform(mesh.vertices[i].extend(1.0))).collect();
+            let corners: Vec<Vec3> = clip_polygon(&points)
+                .iter()
+                .map(|p| {
+                    let projected = p.project();
+                    Vec3::new(projected.x, projected.y, p.w)
+                })
+                .collect();
+            for i in 2..corners.len() {
+                self.fill_depth_triangle(corners[0], corners[i - 1], corners[i]);
+            }
+        }
+    }
+
+    // Transform a line to clip coordinates, clip it and draw what's left
+    fn line_clip(
+        &mut self,
+        color: (f32, f32, f32, f32),
+        blend: BlendMode,
+        transform: &Mat4,
+        depth_cue: Option<DepthCue>,
+        p0: Vec3,
+        p1: Vec3,
+    ) {
+        let (a, b) = (transform.transform(p0.extend(1.0)), transform.transform(p1.extend(1.0)));
+        let (a, b) = match clip_line(a, b) {
+            None => return,
+            Some(segment) => segment,
+        };
+        // After the perspective divide, w is how far each end is from the
+        // camera
+        let (inverse_a, inverse_b) = (1.0 / a.w, 1.0 / b.w);
+        let (a, b) = (a.project(), b.project());
+
+        if depth_cue.is_none() && !self.has_depth_buffer() {
+            self.wu_line_pixels(color, blend, (a.x, a.y), (b.x, b.y));
+            return;
+        }
+
+        // Work out the distance at each pixel from how far along the line
+        // it is. As with faces, the reciprocal of the distance is what
+        // changes linearly across the screen.
+        let (dx, dy) = (b.x - a.x, b.y - a.y);
+        let length_squared = dx * dx + dy * dy;
+        let (width, height) = (self.width(), self.height());
+        wu_line_coverage(width, height, (a.x, a.y), (b.x, b.y), |x, y, coverage| {
+            let t = if length_squared > 0.0 {
+                clamp(((x as f32 + 0.5 - a.x) * dx + (y as f32 + 0.5 - a.y) * dy) / length_squared, 0.0, 1.0)
+            } else {
+                0.0
+            };
+            let distance = 1.0 / (inverse_a + (inverse_b - inverse_a) * t);
+            if !self.depth_test(x, y, distance) {
+                return;
+            }
+            let intensity = depth_cue.map_or(1.0, |cue| cue.intensity(distance));
+            let color = (color.0 * intensity, color.1 * intensity, color.2 * intensity, color.3);
+            self.blend_pixel(color, blend, coverage, x, y);
+        });
+    }
+}
+
+#[test]
+fn test_wireframe() {
+    let camera = Camera::new(Vec3::new(0.0, -10.0, -10.0), Vec3::new(0.0, 0.0, 0.0), std::f32::consts::FRAC_PI_2);
+    let view_projection = camera.view_projection((100.0, 50.0));
+
+    // The target is in the middle of the canvas, and up in the world is up
+    // on the canvas
+    let centre = view_projection.transform_point(Vec3::new(0.0, 0.0, 0.0));
+    assert!((centre.x - 50.0).abs() < 1e-4 && (centre.y - 25.0).abs() < 1e-4);
+    assert!(view_projection.transform_point(Vec3::new(0.0, -1.0, 0.0)).y < 25.0);
+    assert!(view_projection.transform_point(Vec3::new(1.0, 0.0, 0.0)).x > 50.0);
+
+    // Lines are cut off at the near plane, and lines behind the camera
+    // disappear
+    let a = Vec4::new(0.0, 0.0, 1.0, 2.0);
+    let b = Vec4::new(4.0, 0.0, -1.0, 0.5);
+    let (_, clipped) = clip_line(a, b).unwrap();
+    assert!(clipped.z.abs() < 1e-6 && (clipped.x - 2.0).abs() < 1e-6);
+    assert!(clip_line(b, Vec4::new(0.0, 0.0, -2.0, 0.1)).is_none());
+    assert!(clip_line(a, Vec4::new(0.0, 0.0, 3.0, 2.0)).unwrap().1.z <= 2.0);
+
+    assert_eq!(Mesh::cube(2.0).edges.len(), 12);
+    assert_eq!(Mesh::icosahedron(1.0).edges.len(), 30);
+    for vertex in &Mesh::icosahedron(3.0).vertices {
+        assert!((vertex.length() - 3.0).abs() < 1e-5);
+    }
+    assert_eq!(Mesh::grid(3, 2, 1.0).edges.len(), 3 * 3 + 4 * 2);
+    assert_eq!(Mesh::grid(3, 2, 1.0).faces.len(), 6);
+
+    // A cube in front of the camera is drawn, one behind it isn't, and
+    // one the camera is inside of doesn't blow up
+    let mut canvas = Canvas::new(100, 50);
+    let drawn = |canvas: &Canvas| canvas.pixels().iter().filter(|p| p.0 > 0.0).count();
+    for &(offset, visible) in &[(-30.0, false), (0.0, true)] {
+        canvas.clear((0.0, 0.0, 0.0, 1.0));
+        let model = Mat4::translate(0.0, offset, offset);
+        canvas.draw_mesh((1.0, 1.0, 1.0, 1.0), BlendMode::SourceOver, &camera, &Mesh::cube(4.0), &model);
+        assert_eq!(drawn(&canvas) > 0, visible);
+    }
+    let model = Mat4::translate(0.0, -10.0, -10.0);
+    canvas.draw_mesh((1.0, 1.0, 1.0, 1.0), BlendMode::SourceOver, &camera, &Mesh::cube(4.0), &model);
+
+    let cue = DepthCue::new(10.0, 20.0);
+    assert_eq!(cue.intensity(5.0), 1.0);
+    assert_eq!(cue.intensity(15.0), 0.5);
+    assert_eq!(cue.intensity(30.0), 0.0);
+
+    // A line straight across the view behind a cube, through the middle of
+    // the canvas
+    let brightness = |canvas: &Canvas, x: i32| canvas.get_pixel(x, 24).unwrap().0 + canvas.get_pixel(x, 25).unwrap().0;
+    let (p0, p1) = (Vec3::new(-20.0, 5.0, 5.0), Vec3::new(20.0, 5.0, 5.0));
+    canvas.clear((0.0, 0.0, 0.0, 1.0));
+    canvas.line_3d((1.0, 1.0, 1.0, 1.0), BlendMode::SourceOver, &camera, p0, p1);
+    let plain = brightness(&canvas, 50);
+    assert!(plain > 0.9);
+
+    // Depth cueing dims it
+    let mut cued = camera;
+    cued.depth_cue = Some(DepthCue::new(0.0, 30.0));
+    canvas.clear((0.0, 0.0, 0.0, 1.0));
+    canvas.line_3d((1.0, 1.0, 1.0, 1.0), BlendMode::SourceOver, &cued, p0, p1);
+    assert!(brightness(&canvas, 50) < plain * 0.5);
+
+    // With a depth buffer, the cube hides the middle of it but not its ends
+    canvas.enable_depth_buffer();
+    canvas.clear((0.0, 0.0, 0.0, 1.0));
+    canvas.draw_mesh_depth(&camera, &Mesh::cube(4.0), &Mat4::identity());
+    canvas.line_3d((1.0, 1.0, 1.0, 1.0), BlendMode::SourceOver, &camera, p0, p1);
+    assert_eq!(brightness(&canvas, 50), 0.0);
+    assert!(brightness(&canvas, 70) > 0.9);
+
+    // Its own edges aren't hidden by its faces
+    canvas.clear((0.0, 0.0, 0.0, 1.0));
+    canvas.draw_mesh((1.0, 1.0, 1.0, 1.0), BlendMode::SourceOver, &camera, &Mesh::cube(4.0), &Mat4::identity());
+    let visible = drawn(&canvas);
+    canvas.disable_depth_buffer();
+    canvas.clear((0.0, 0.0, 0.0, 1.0));
+    canvas.draw_mesh((1.0, 1.0, 1.0, 1.0), BlendMode::SourceOver, &camera, &Mesh::cube(4.0), &Mat4::identity());
+    let all = drawn(&canvas);
+    assert!(visible > all / 2 && visible < all);
+}
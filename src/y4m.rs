@@ -0,0 +1,149 @@
+use std::io::{self, Write};
+
+// Streams 0x00RRGGBB frames (as produced by gamma_correct_buffer) as a
+// YUV4MPEG2 video, which ffmpeg and most other encoders can read straight
+// from a pipe. Frames are converted to limited range BT.709 Y'CbCr with
+// 4:2:0 chroma subsampling.
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+    width: usize,
+    height: usize,
+    y_plane: Vec<u8>,
+    u_plane: Vec<u8>,
+    v_plane: Vec<u8>,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    // Write the stream header. frame_rate is a (numerator, denominator)
+    // pair, so 60 fps is (60, 1) and NTSC rates like 29.97 are (30000, 1001).
+    // Videos with no pixels can't be written.
+    pub fn new(mut writer: W, width: usize, height: usize, frame_rate: (u32, u32)) -> io::Result<Y4mWriter<W>> {
+        if width == 0 || height == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "video has no pixels"));
+        }
+
+        writeln!(
+            writer,
+            "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C420jpeg XCOLORRANGE=LIMITED",
+            width,
+            height,
+            frame_rate.0,
+            frame_rate.1,
+        )?;
+
+        let chroma_size = chroma_dimension(width) * chroma_dimension(height);
+        Ok(Y4mWriter {
+            writer,
+            width,
+            height,
+            y_plane: vec![0; width * height],
+            u_plane: vec![0; chroma_size],
+            v_plane: vec![0; chroma_size],
+        })
+    }
+
+    pub fn write_frame(&mut self, pixels: &[u32]) -> io::Result<()> {
+        assert_eq!(pixels.len(), self.width * self.height, "pixel count doesn't match dimensions");
+
+        let chroma_width = chroma_dimension(self.width);
+
+        for (y, row) in pixels.chunks(self.width).enumerate() {
+            for (x, pixel) in row.iter().enumerate() {
+                let (luma, _, _) = rgb_to_ycbcr(*pixel);
+                self.y_plane[x + y*self.width] = luma;
+            }
+        }
+
+        // Each chroma sample is the average of the (up to) 2x2 block of
+        // pixels it covers.
+        for cy in 0..chroma_dimension(self.height) {
+            for cx in 0..chroma_width {
+                let mut cb_sum = 0.0;
+                let mut cr_sum = 0.0;
+                let mut count = 0.0;
+
+                for y in (cy*2)..(cy*2 + 2).min(self.height) {
+                    for x in (cx*2)..(cx*2 + 2).min(self.width) {
+                        let (cb, cr) = rgb_to_cbcr(pixels[x + y*self.width]);
+                        cb_sum += cb;
+                        cr_sum += cr;
+                        count += 1.0;
+                    }
+                }
+
+                self.u_plane[cx + cy*chroma_width] = quantize_chroma(cb_sum / count);
+                self.v_plane[cx + cy*chroma_width] = quantize_chroma(cr_sum / count);
+            }
+        }
+
+        self.writer.write_all(b"FRAME\n")?;
+        self.writer.write_all(&self.y_plane)?;
+        self.writer.write_all(&self.u_plane)?;
+        self.writer.write_all(&self.v_plane)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+fn chroma_dimension(luma_dimension: usize) -> usize {
+    luma_dimension.div_ceil(2)
+}
+
+fn unpack_rgb(pixel: u32) -> (f32, f32, f32) {
+    (
+        ((pixel >> 16) & 0xFF) as f32 / 255.0,
+        ((pixel >> 8) & 0xFF) as f32 / 255.0,
+        (pixel & 0xFF) as f32 / 255.0,
+    )
+}
+
+// Unscaled BT.709 colour difference signals, each in -0.5..=0.5
+fn rgb_to_cbcr(pixel: u32) -> (f32, f32) {
+    let (r, g, b) = unpack_rgb(pixel);
+    let luma = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    ((b - luma) / 1.8556, (r - luma) / 1.5748)
+}
+
+fn quantize_chroma(value: f32) -> u8 {
+    (128.0 + 224.0 * value).round() as u8
+}
+
+// Convert a gamma encoded 0x00RRGGBB pixel into limited range BT.709 Y'CbCr
+pub fn rgb_to_ycbcr(pixel: u32) -> (u8, u8, u8) {
+    let (r, g, b) = unpack_rgb(pixel);
+    let luma = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let (cb, cr) = rgb_to_cbcr(pixel);
+
+    ((16.0 + 219.0 * luma).round() as u8, quantize_chroma(cb), quantize_chroma(cr))
+}
+
+#[test]
+fn test_y4m_stream_layout() {
+    let mut writer = Y4mWriter::new(Vec::new(), 3, 3, (60, 1)).unwrap();
+    writer.write_frame(&[0xFFFFFF; 9]).unwrap();
+    writer.write_frame(&[0x000000; 9]).unwrap();
+    let stream = writer.into_inner();
+
+    let header = b"YUV4MPEG2 W3 H3 F60:1 Ip A1:1 C420jpeg XCOLORRANGE=LIMITED\n";
+    assert!(stream.starts_with(header));
+
+    // 9 luma samples and two 2x2 chroma planes per frame
+    let frame_size = b"FRAME\n".len() + 9 + 4 + 4;
+    assert_eq!(stream.len(), header.len() + 2 * frame_size);
+
+    let first_frame = &stream[header.len() + b"FRAME\n".len()..];
+    assert_eq!(&first_frame[..9], &[235; 9]);
+    assert_eq!(&first_frame[9..17], &[128; 8]);
+
+    assert_eq!(rgb_to_ycbcr(0x000000), (16, 128, 128));
+    assert_eq!(rgb_to_ycbcr(0xFF0000), (63, 102, 240));
+
+    let error = Y4mWriter::new(Vec::new(), 0, 3, (60, 1)).err().unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+}